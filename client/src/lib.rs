@@ -0,0 +1,257 @@
+//! Typed client of the ezcasino server API and of its blackjack contract indexer.
+
+use anyhow::{anyhow, bail, Context, Result};
+use blackjack::{
+    advisor::Advice, BatchableAction, BlackJack, BlackJackAction, TableId, TableState,
+};
+use reqwest::{RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+
+pub use sdk::{Blob, Identity};
+
+const IDENTITY_HEADER: &str = "x-identity";
+const SESSION_KEY_HEADER: &str = "x-session-key";
+
+/// How an action is authorised for the player
+#[derive(Debug, Clone)]
+pub enum Authorisation {
+    /// Identity blobs built by the player's wallet
+    WalletBlobs([Blob; 2]),
+    /// Token of a session opened on the server, only valid to hit, stand or double down
+    SessionKey(String),
+}
+
+impl From<[Blob; 2]> for Authorisation {
+    fn from(wallet_blobs: [Blob; 2]) -> Self {
+        Authorisation::WalletBlobs(wallet_blobs)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiTable {
+    pub bank: Vec<u32>,
+    pub bank_count: u32,
+    pub user: Vec<u32>,
+    pub user_count: u32,
+    pub bet: u32,
+    pub state: TableState,
+    pub balance: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Resp {
+    pub tx_hash: String,
+    pub table_id: Option<TableId>,
+    pub table: ApiTable,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct HintResp {
+    pub table_id: TableId,
+    pub advice: Advice,
+    pub user_count: u32,
+    pub soft: bool,
+    pub dealer_up: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfigResponse {
+    pub contract_name: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UserBalances {
+    pub oranj: u32,
+    pub vitamin: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorBody {
+    error: String,
+}
+
+pub struct EzCasinoClient {
+    http: reqwest::Client,
+    url: String,
+    contract_name: String,
+}
+
+impl EzCasinoClient {
+    /// Client of the server at `url`, e.g. `http://localhost:4000`, for the contract
+    /// registered under the default name
+    pub fn new(url: impl Into<String>) -> Self {
+        EzCasinoClient {
+            http: reqwest::Client::new(),
+            url: url.into().trim_end_matches('/').to_string(),
+            contract_name: blackjack::CONTRACT_NAME.to_string(),
+        }
+    }
+
+    pub fn with_contract_name(mut self, contract_name: impl Into<String>) -> Self {
+        self.contract_name = contract_name.into();
+        self
+    }
+
+    pub async fn config(&self) -> Result<ConfigResponse> {
+        parse(self.http.get(format!("{}/api/config", self.url))).await
+    }
+
+    /// Whole contract state, as executed by the indexer
+    pub async fn state(&self) -> Result<BlackJack> {
+        parse(self.http.get(format!(
+            "{}/v1/indexer/contract/{}/state",
+            self.url, self.contract_name
+        )))
+        .await
+    }
+
+    pub async fn balances(&self, identity: &Identity) -> Result<UserBalances> {
+        parse(self.http.get(format!(
+            "{}/v1/indexer/contract/{}/user/{}/balances",
+            self.url, self.contract_name, identity
+        )))
+        .await
+    }
+
+    /// Basic-strategy move for the player's ongoing table
+    pub async fn hint(&self, identity: &Identity, table_id: TableId) -> Result<HintResp> {
+        parse(
+            self.http
+                .get(format!("{}/api/table/hint", self.url))
+                .header(IDENTITY_HEADER, &identity.0)
+                .query(&[("table_id", table_id)]),
+        )
+        .await
+    }
+
+    pub async fn init(
+        &self,
+        identity: &Identity,
+        wallet_blobs: [Blob; 2],
+        bet: u32,
+    ) -> Result<Resp> {
+        self.play(identity, BlackJackAction::Init(bet), wallet_blobs.into())
+            .await
+    }
+
+    pub async fn hit(
+        &self,
+        identity: &Identity,
+        auth: Authorisation,
+        table_id: TableId,
+    ) -> Result<Resp> {
+        self.play(identity, BlackJackAction::Hit(table_id), auth)
+            .await
+    }
+
+    pub async fn stand(
+        &self,
+        identity: &Identity,
+        auth: Authorisation,
+        table_id: TableId,
+    ) -> Result<Resp> {
+        self.play(identity, BlackJackAction::Stand(table_id), auth)
+            .await
+    }
+
+    pub async fn double_down(
+        &self,
+        identity: &Identity,
+        auth: Authorisation,
+        table_id: TableId,
+    ) -> Result<Resp> {
+        self.play(identity, BlackJackAction::DoubleDown(table_id), auth)
+            .await
+    }
+
+    pub async fn deposit(
+        &self,
+        identity: &Identity,
+        wallet_blobs: [Blob; 2],
+        amount: u32,
+    ) -> Result<Resp> {
+        self.play(
+            identity,
+            BlackJackAction::Deposit(amount),
+            wallet_blobs.into(),
+        )
+        .await
+    }
+
+    pub async fn withdraw(
+        &self,
+        identity: &Identity,
+        wallet_blobs: [Blob; 2],
+        amount: u32,
+        token: impl Into<String>,
+    ) -> Result<Resp> {
+        self.play(
+            identity,
+            BlackJackAction::Withdraw(amount, token.into()),
+            wallet_blobs.into(),
+        )
+        .await
+    }
+
+    /// Plays the actions in a single transaction, all or none of them
+    pub async fn batch(
+        &self,
+        identity: &Identity,
+        auth: Authorisation,
+        actions: Vec<BatchableAction>,
+    ) -> Result<Resp> {
+        self.play(identity, BlackJackAction::Batch(actions), auth)
+            .await
+    }
+
+    /// Sends the action to its route and waits for its execution
+    pub async fn play(
+        &self,
+        identity: &Identity,
+        action: BlackJackAction,
+        auth: Authorisation,
+    ) -> Result<Resp> {
+        let (route, mut body) = match action {
+            BlackJackAction::Init(bet) => ("init", json!({ "bet": bet })),
+            BlackJackAction::Hit(table_id) => ("hit", json!({ "table_id": table_id })),
+            BlackJackAction::Stand(table_id) => ("stand", json!({ "table_id": table_id })),
+            BlackJackAction::DoubleDown(table_id) => {
+                ("double_down", json!({ "table_id": table_id }))
+            }
+            BlackJackAction::Deposit(amount) => ("deposit", json!({ "deposit": amount })),
+            BlackJackAction::Withdraw(amount, token) => {
+                ("withdraw", json!({ "withdraw": amount, "token": token }))
+            }
+            BlackJackAction::Batch(actions) => ("batch", json!({ "actions": actions })),
+            action => bail!("{action:?} has no dedicated route"),
+        };
+
+        let mut request = self
+            .http
+            .post(format!("{}/api/{route}", self.url))
+            .header(IDENTITY_HEADER, &identity.0);
+        match auth {
+            Authorisation::WalletBlobs(wallet_blobs) => {
+                body["wallet_blobs"] = serde_json::to_value(wallet_blobs)?;
+            }
+            Authorisation::SessionKey(session_key) => {
+                request = request.header(SESSION_KEY_HEADER, session_key);
+            }
+        }
+        parse(request.json(&body)).await
+    }
+}
+
+async fn parse<T: DeserializeOwned>(request: RequestBuilder) -> Result<T> {
+    let response: Response = request.send().await.context("Sending request")?;
+    let status = response.status();
+    let body = response.bytes().await.context("Reading response")?;
+    if !status.is_success() {
+        let error = serde_json::from_slice::<ErrorBody>(&body)
+            .map(|body| body.error)
+            .unwrap_or_else(|_| String::from_utf8_lossy(&body).into_owned());
+        return Err(anyhow!("{status}: {error}"));
+    }
+    serde_json::from_slice(&body).context("Failed to decode response")
+}
@@ -0,0 +1,205 @@
+//! Basic-strategy advice for a solo table, read from a configurable strategy chart.
+//!
+//! Charts have one column per dealer up card, from 2 to 10 then the ace, and the rules
+//! they are played under decide how the moves the table does not allow fall back. Solo
+//! tables neither split nor surrender, so the charts hold no pairs and no surrender.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{BlackJack, Table, TableState};
+
+/// Move recommended to the player
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    Hit,
+    Stand,
+    DoubleDown,
+}
+
+/// Cell of a strategy chart
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartEntry {
+    Hit,
+    Stand,
+    /// Double down when allowed, hit otherwise
+    DoubleOrHit,
+    /// Double down when allowed, stand otherwise
+    DoubleOrStand,
+}
+
+impl ChartEntry {
+    /// Letter used in printed charts: H, S, D (double or hit) and d (double or stand)
+    fn from_letter(letter: u8) -> Self {
+        match letter {
+            b'S' => ChartEntry::Stand,
+            b'D' => ChartEntry::DoubleOrHit,
+            b'd' => ChartEntry::DoubleOrStand,
+            _ => ChartEntry::Hit,
+        }
+    }
+}
+
+/// When the table allows doubling down
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChartRules {
+    /// Doubling down is allowed after hitting, not only on the first two cards
+    pub double_after_hit: bool,
+}
+
+impl Default for ChartRules {
+    /// Rules of the contract's solo tables, which double down at any time
+    fn default() -> Self {
+        ChartRules {
+            double_after_hit: true,
+        }
+    }
+}
+
+const HARD_MIN: u32 = 4;
+const SOFT_MIN: u32 = 12;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StrategyChart {
+    /// Hard totals from 4 to 21
+    pub hard: [[ChartEntry; 10]; 18],
+    /// Soft totals from 12 to 21
+    pub soft: [[ChartEntry; 10]; 10],
+    pub rules: ChartRules,
+}
+
+fn row(letters: &str) -> [ChartEntry; 10] {
+    let mut row = [ChartEntry::Hit; 10];
+    for (entry, letter) in row.iter_mut().zip(letters.bytes()) {
+        *entry = ChartEntry::from_letter(letter);
+    }
+    row
+}
+
+impl Default for StrategyChart {
+    /// Multi-deck basic strategy for a dealer standing on all 17s, under the contract's rules
+    fn default() -> Self {
+        StrategyChart::basic(ChartRules::default())
+    }
+}
+
+impl StrategyChart {
+    /// Multi-deck basic strategy for a dealer standing on all 17s
+    pub fn basic(rules: ChartRules) -> Self {
+        StrategyChart {
+            hard: [
+                row("HHHHHHHHHH"), // 4
+                row("HHHHHHHHHH"), // 5
+                row("HHHHHHHHHH"), // 6
+                row("HHHHHHHHHH"), // 7
+                row("HHHHHHHHHH"), // 8
+                row("HDDDDHHHHH"), // 9
+                row("DDDDDDDDHH"), // 10
+                row("DDDDDDDDDH"), // 11
+                row("HHSSSHHHHH"), // 12
+                row("SSSSSHHHHH"), // 13
+                row("SSSSSHHHHH"), // 14
+                row("SSSSSHHHHH"), // 15
+                row("SSSSSHHHHH"), // 16
+                row("SSSSSSSSSS"), // 17
+                row("SSSSSSSSSS"), // 18
+                row("SSSSSSSSSS"), // 19
+                row("SSSSSSSSSS"), // 20
+                row("SSSSSSSSSS"), // 21
+            ],
+            soft: [
+                row("HHHHHHHHHH"), // 12
+                row("HHHDDHHHHH"), // 13
+                row("HHHDDHHHHH"), // 14
+                row("HHDDDHHHHH"), // 15
+                row("HHDDDHHHHH"), // 16
+                row("HDDDDHHHHH"), // 17
+                row("SddddSSHHH"), // 18
+                row("SSSSSSSSSS"), // 19
+                row("SSSSSSSSSS"), // 20
+                row("SSSSSSSSSS"), // 21
+            ],
+            rules,
+        }
+    }
+
+    /// Recommended move for the player's cards against the dealer's up card
+    pub fn advise(&self, user: &[u32], dealer_up: u32) -> Advice {
+        let column = chart_column(dealer_up);
+        let first_move = user.len() == 2;
+
+        let total = BlackJack::compute_score(user);
+        let entry = if is_soft(user) {
+            self.soft[(total.clamp(SOFT_MIN, 21) - SOFT_MIN) as usize][column]
+        } else {
+            self.hard[(total.clamp(HARD_MIN, 21) - HARD_MIN) as usize][column]
+        };
+
+        let can_double = first_move || self.rules.double_after_hit;
+        match entry {
+            ChartEntry::Hit => Advice::Hit,
+            ChartEntry::Stand => Advice::Stand,
+            ChartEntry::DoubleOrHit if can_double => Advice::DoubleDown,
+            ChartEntry::DoubleOrHit => Advice::Hit,
+            ChartEntry::DoubleOrStand if can_double => Advice::DoubleDown,
+            ChartEntry::DoubleOrStand => Advice::Stand,
+        }
+    }
+}
+
+/// Column of the card in a chart: 2 to 10 then the ace
+fn chart_column(card: u32) -> usize {
+    match card {
+        1 => 9,
+        card => (card.clamp(2, 10) - 2) as usize,
+    }
+}
+
+/// Whether an ace still counts as 11 in the score of the cards
+pub fn is_soft(cards: &[u32]) -> bool {
+    let hard: u32 = cards.iter().map(|card| (*card).min(10)).sum();
+    BlackJack::compute_score(cards) > hard
+}
+
+/// Recommended move on the table, if its game is still ongoing
+pub fn advise(table: &Table, chart: &StrategyChart) -> Option<Advice> {
+    if !matches!(table.state, TableState::Ongoing) {
+        return None;
+    }
+    let dealer_up = *table.bank.first()?;
+    Some(chart.advise(&table.user, dealer_up))
+}
+
+#[test]
+fn test_basic_strategy_advice() {
+    let chart = StrategyChart::default();
+    assert_eq!(chart.advise(&[10, 6], 10), Advice::Hit);
+    assert_eq!(chart.advise(&[10, 6], 5), Advice::Stand);
+    assert_eq!(chart.advise(&[6, 5], 1), Advice::Hit);
+    assert_eq!(chart.advise(&[6, 5], 10), Advice::DoubleDown);
+    // The contract doubles after hitting
+    assert_eq!(chart.advise(&[3, 2, 6], 6), Advice::DoubleDown);
+    assert_eq!(chart.advise(&[8, 8], 10), Advice::Hit);
+    assert_eq!(chart.advise(&[1, 7], 2), Advice::Stand);
+    assert_eq!(chart.advise(&[1, 7], 4), Advice::DoubleDown);
+    assert_eq!(chart.advise(&[1, 7], 9), Advice::Hit);
+
+    assert!(is_soft(&[1, 7]));
+    assert!(is_soft(&[1, 1, 5]));
+    assert!(!is_soft(&[1, 7, 9]));
+    assert!(!is_soft(&[10, 6]));
+
+    let chart = StrategyChart::basic(ChartRules {
+        double_after_hit: false,
+    });
+    assert_eq!(chart.advise(&[13, 12], 6), Advice::Stand);
+    assert_eq!(chart.advise(&[3, 2, 6], 6), Advice::Hit);
+    assert_eq!(chart.advise(&[1, 2, 5], 4), Advice::Stand);
+
+    let finished = Table {
+        bank: vec![10, 7],
+        user: vec![10, 9],
+        state: TableState::Won,
+        ..Default::default()
+    };
+    assert_eq!(advise(&finished, &chart), None);
+}
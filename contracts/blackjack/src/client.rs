@@ -1,18 +1,19 @@
 use alloc::{
+    collections::BTreeSet,
     string::{String, ToString},
     vec::Vec,
 };
 use anyhow::{anyhow, Context, Result};
 use client_sdk::contract_indexer::{
     axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router},
-    utoipa::{openapi::OpenApi, ToSchema},
+    utoipa::{openapi::OpenApi, IntoParams, ToSchema},
     utoipa_axum::{router::OpenApiRouter, routes},
     AppError, ContractHandler, ContractHandlerStore,
 };
 use client_sdk::transaction_builder::TxExecutorHandler;
 use hyle_modules::modules::prover::AutoProverEvent;
 use sdk::{
-    tracing::{debug, info},
+    tracing::{debug, info, warn},
     utils::as_hyle_output,
     Blob, BlobTransaction, Calldata, Hashed, Identity, RegisterContractEffect, StateCommitment,
     TxContext, ZkContract,
@@ -21,6 +22,7 @@ use sdk::{
 use client_sdk::contract_indexer::axum;
 use client_sdk::contract_indexer::utoipa;
 
+use crate::history::{GameHistory, HandOutcome, HandRecord, PlayerStats};
 use crate::*;
 
 pub mod metadata {
@@ -36,6 +38,16 @@ impl TxExecutorHandler for BlackJack {
     fn handle(&mut self, calldata: &Calldata) -> anyhow::Result<sdk::HyleOutput> {
         let initial_state_commitment = <Self as ZkContract>::commit(self);
         let mut res = <Self as ZkContract>::execute(self, calldata);
+        if res.is_err() {
+            // Reverted as `FullStateRevert` does in the guest, as a batch may fail after
+            // playing some of its actions. The commitment is the encoded state.
+            let optimistic_state = core::mem::take(&mut self.optimistic_state);
+            let history = core::mem::take(&mut self.history);
+            *self = borsh::from_slice(&initial_state_commitment.0)
+                .context("Failed to revert BlackJack")?;
+            self.optimistic_state = optimistic_state;
+            self.history = history;
+        }
         let next_state_commitment = <Self as ZkContract>::commit(self);
         Ok(as_hyle_output(
             initial_state_commitment,
@@ -51,9 +63,15 @@ impl TxExecutorHandler for BlackJack {
 
     fn construct_state(
         _register_blob: &RegisterContractEffect,
-        _metadata: &Option<Vec<u8>>,
+        metadata: &Option<Vec<u8>>,
     ) -> anyhow::Result<Self> {
-        Ok(Self::default())
+        // The contract settings are passed as constructor metadata at registration
+        let Some(metadata) = metadata else {
+            return Ok(Self::default());
+        };
+        let config: BlackJackConfig =
+            borsh::from_slice(metadata).context("Failed to decode BlackJack config")?;
+        Ok(Self::new(config))
     }
 }
 
@@ -85,6 +103,10 @@ fn apply_tx_to_state(
 
     let hyle_output = state.handle(&calldata)?;
     let program_outputs = str::from_utf8(&hyle_output.program_outputs).unwrap_or("no output");
+    // Reported as a failed transaction to the server waiting on the sequenced transaction
+    if !hyle_output.success {
+        return Err(anyhow!("{}", program_outputs));
+    }
 
     info!("🚀 Executed {contract_name}: {}", program_outputs);
     debug!(
@@ -94,6 +116,145 @@ fn apply_tx_to_state(
     Ok(())
 }
 
+/// Hands a transaction plays, as they were before it is applied
+struct PlayedHands {
+    caller: Identity,
+    tables: Vec<(Identity, TableId)>,
+    /// Solo tables whose hand was over already
+    over: BTreeSet<(Identity, TableId)>,
+    /// Shared tables finished already
+    finished: BTreeSet<TableId>,
+    vitamin_balances: BTreeMap<Identity, u32>,
+    next_table_id: TableId,
+}
+
+impl PlayedHands {
+    fn before(state: &BlackJack, action: &BlackJackAction, caller: &Identity) -> Self {
+        let tables = action.played_tables(caller);
+        let over = tables
+            .iter()
+            .filter(|(player, table_id)| {
+                state
+                    .get_table(player, *table_id)
+                    .is_some_and(|table| !matches!(table.state, TableState::Ongoing))
+            })
+            .cloned()
+            .collect();
+        let finished = tables
+            .iter()
+            .map(|(_, table_id)| *table_id)
+            .filter(|table_id| {
+                state
+                    .shared_tables
+                    .get(table_id)
+                    .is_some_and(|table| matches!(table.phase, SharedTablePhase::Finished))
+            })
+            .collect();
+        let vitamin_balances = tables
+            .iter()
+            .map(|(player, _)| player)
+            .chain([caller])
+            .map(|player| {
+                let balance = state.vitamin_balances.get(player).copied().unwrap_or(0);
+                (player.clone(), balance)
+            })
+            .collect();
+        PlayedHands {
+            caller: caller.clone(),
+            tables,
+            over,
+            finished,
+            vitamin_balances,
+            next_table_id: state.next_table_id,
+        }
+    }
+}
+
+/// Lists the hands that a settled transaction brought to an end, among the tables it
+/// played or opened
+fn completed_hands(
+    before: &PlayedHands,
+    after: &BlackJack,
+    tx_hash: &str,
+    tx_context: &TxContext,
+) -> Vec<HandRecord> {
+    let opened = (before.next_table_id..after.next_table_id)
+        .map(|table_id| (before.caller.clone(), table_id));
+    let played: BTreeSet<(Identity, TableId)> =
+        before.tables.iter().cloned().chain(opened).collect();
+    let mut hands = vec![];
+
+    for (player, table_id) in played.iter() {
+        let Some(table) = after.get_table(player, *table_id) else {
+            continue;
+        };
+        if matches!(table.state, TableState::Ongoing)
+            || before.over.contains(&(player.clone(), *table_id))
+        {
+            continue;
+        }
+        // A push is recorded as won, only a real win is awarded Vitamin tokens
+        let vitamin_before = before.vitamin_balances.get(player).copied().unwrap_or(0);
+        let vitamin_after = after.vitamin_balances.get(player).copied().unwrap_or(0);
+        let (outcome, payout, reward) = match table.state {
+            TableState::Won if vitamin_after > vitamin_before => {
+                (HandOutcome::Win, table.bet, table.bet)
+            }
+            TableState::Won => (HandOutcome::Push, table.bet, 0),
+            _ => (HandOutcome::Loss, 0, 0),
+        };
+        hands.push(HandRecord {
+            player: player.clone(),
+            table_id: *table_id,
+            shared: false,
+            cards: table.user.clone(),
+            bank: table.bank.clone(),
+            bet: table.bet,
+            outcome,
+            payout,
+            reward,
+            tx_hash: tx_hash.to_string(),
+            block_height: tx_context.block_height.0,
+            timestamp_ms: tx_context.timestamp.0,
+        });
+    }
+
+    let shared_tables: BTreeSet<TableId> = played.iter().map(|(_, table_id)| *table_id).collect();
+    for table_id in shared_tables {
+        let Some(table) = after.shared_tables.get(&table_id) else {
+            continue;
+        };
+        if !matches!(table.phase, SharedTablePhase::Finished) || before.finished.contains(&table_id)
+        {
+            continue;
+        }
+        for seat in table.seats.iter() {
+            let (outcome, payout, reward) = match seat.state {
+                SeatState::Won => (HandOutcome::Win, seat.bet, seat.bet),
+                SeatState::Push => (HandOutcome::Push, seat.bet, 0),
+                SeatState::Lost => (HandOutcome::Loss, 0, 0),
+                _ => continue,
+            };
+            hands.push(HandRecord {
+                player: seat.player.clone(),
+                table_id,
+                shared: true,
+                cards: seat.cards.clone(),
+                bank: table.bank.clone(),
+                bet: seat.bet,
+                outcome,
+                payout,
+                reward,
+                tx_hash: tx_hash.to_string(),
+                block_height: tx_context.block_height.0,
+                timestamp_ms: tx_context.timestamp.0,
+            });
+        }
+    }
+
+    hands
+}
+
 impl OptimisticBlackJack {
     fn compute_optimistic_state(
         &mut self,
@@ -123,6 +284,11 @@ impl ContractHandler<AutoProverEvent<BlackJack>> for BlackJack {
         let (router, api) = OpenApiRouter::default()
             .routes(routes!(get_state))
             .routes(routes!(get_user_balance))
+            .routes(routes!(get_user_history))
+            .routes(routes!(get_user_stats))
+            .routes(routes!(get_leaderboard))
+            .routes(routes!(get_shared_tables))
+            .routes(routes!(get_shared_table))
             .split_for_parts();
 
         (router.with_state(store), api)
@@ -134,8 +300,22 @@ impl ContractHandler<AutoProverEvent<BlackJack>> for BlackJack {
         index: BlobIndex,
         tx_context: TxContext,
     ) -> Result<Option<AutoProverEvent<BlackJack>>> {
-        apply_tx_to_state(self, tx, index, tx_context)
+        // Only the tables the action plays are looked at, before and after it
+        let before = tx
+            .blobs
+            .get(index.0)
+            .and_then(|blob| {
+                StructuredBlobData::<BlackJackAction>::try_from(blob.data.clone()).ok()
+            })
+            .map(|blob| PlayedHands::before(self, &blob.parameters, &tx.identity));
+        apply_tx_to_state(self, tx, index, tx_context.clone())
             .context("Failed to apply transaction to state")?;
+        if let Some(before) = before {
+            let hands = completed_hands(&before, self, &tx.hashed().to_string(), &tx_context);
+            if let Err(e) = self.history.record(hands) {
+                warn!("Failed to record game history: {:#}", e);
+            }
+        }
         self.optimistic_state
             .unsettled_txs
             .retain(|(t, i, _)| t != tx || *i != index);
@@ -249,3 +429,410 @@ pub async fn get_user_balance(
         vitamin: vitamin_balance,
     }))
 }
+
+/// Ledger of the indexed state, empty until a state is indexed
+async fn game_history(state: &ContractHandlerStore<BlackJack>) -> GameHistory {
+    let store = state.read().await;
+    store
+        .state
+        .as_ref()
+        .map(|state| state.history.clone())
+        .unwrap_or_default()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HistoryQuery {
+    /// Number of most recent hands to skip
+    offset: Option<usize>,
+    /// Maximum number of hands to return, 50 by default
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct UserHistory {
+    total: usize,
+    offset: usize,
+    #[schema(value_type = Vec<Object>)]
+    hands: Vec<HandRecord>,
+}
+
+const MAX_HISTORY_PAGE: usize = 200;
+
+#[utoipa::path(
+    get,
+    path = "/user/{user_id}/history",
+    tag = "Contract",
+    params(
+        ("user_id" = String, Path, description = "User identity"),
+        HistoryQuery
+    ),
+    responses(
+        (status = OK, description = "Get the completed hands of a user, most recent first", body = UserHistory)
+    )
+)]
+pub async fn get_user_history(
+    State(state): State<ContractHandlerStore<BlackJack>>,
+    axum::extract::Path(user_id): axum::extract::Path<String>,
+    axum::extract::Query(query): axum::extract::Query<HistoryQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(50).min(MAX_HISTORY_PAGE);
+    let (total, hands) = game_history(&state)
+        .await
+        .hands(&Identity(user_id), offset, limit)?;
+
+    Ok(Json(UserHistory {
+        total,
+        offset,
+        hands,
+    }))
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum StatsWindow {
+    Day,
+    Week,
+    Month,
+    #[default]
+    All,
+}
+
+impl StatsWindow {
+    /// Timestamp, in milliseconds, from which hands are taken into account
+    fn since_ms(self) -> u128 {
+        let period_ms: u128 = match self {
+            StatsWindow::Day => 24 * 3600 * 1000,
+            StatsWindow::Week => 7 * 24 * 3600 * 1000,
+            StatsWindow::Month => 30 * 24 * 3600 * 1000,
+            StatsWindow::All => return 0,
+        };
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        now_ms.saturating_sub(period_ms)
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum LeaderboardMetric {
+    #[default]
+    NetVitamin,
+    NetOranj,
+    Wins,
+    HandsPlayed,
+    BiggestWin,
+    LongestStreak,
+}
+
+impl LeaderboardMetric {
+    fn value(self, stats: &PlayerStats) -> i64 {
+        match self {
+            LeaderboardMetric::NetVitamin => stats.net_vitamin,
+            LeaderboardMetric::NetOranj => stats.net_oranj,
+            LeaderboardMetric::Wins => stats.wins as i64,
+            LeaderboardMetric::HandsPlayed => stats.hands_played as i64,
+            LeaderboardMetric::BiggestWin => stats.biggest_win as i64,
+            LeaderboardMetric::LongestStreak => stats.longest_streak as i64,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StatsQuery {
+    /// Period covered: "day", "week", "month" or "all" (default)
+    #[param(value_type = Option<String>)]
+    window: Option<StatsWindow>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LeaderboardQuery {
+    /// Ranking metric: "net_vitamin" (default), "net_oranj", "wins", "hands_played",
+    /// "biggest_win" or "longest_streak"
+    #[param(value_type = Option<String>)]
+    metric: Option<LeaderboardMetric>,
+    /// Period covered: "day", "week", "month" or "all" (default)
+    #[param(value_type = Option<String>)]
+    window: Option<StatsWindow>,
+    /// Number of players to return, 10 by default
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct LeaderboardEntry {
+    rank: usize,
+    value: i64,
+    stats: PlayerStats,
+}
+
+const MAX_LEADERBOARD_SIZE: usize = 100;
+
+#[utoipa::path(
+    get,
+    path = "/user/{user_id}/stats",
+    tag = "Contract",
+    params(
+        ("user_id" = String, Path, description = "User identity"),
+        StatsQuery
+    ),
+    responses(
+        (status = OK, description = "Get the aggregated results of a user", body = PlayerStats)
+    )
+)]
+pub async fn get_user_stats(
+    State(state): State<ContractHandlerStore<BlackJack>>,
+    axum::extract::Path(user_id): axum::extract::Path<String>,
+    axum::extract::Query(query): axum::extract::Query<StatsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let since_ms = query.window.unwrap_or_default().since_ms();
+    Ok(Json(
+        game_history(&state)
+            .await
+            .player_stats(&Identity(user_id), since_ms)?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/leaderboard",
+    tag = "Contract",
+    params(LeaderboardQuery),
+    responses(
+        (status = OK, description = "Get the best players for a metric over a period", body = Vec<LeaderboardEntry>)
+    )
+)]
+pub async fn get_leaderboard(
+    State(state): State<ContractHandlerStore<BlackJack>>,
+    axum::extract::Query(query): axum::extract::Query<LeaderboardQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let metric = query.metric.unwrap_or_default();
+    let since_ms = query.window.unwrap_or_default().since_ms();
+    let limit = query.limit.unwrap_or(10).min(MAX_LEADERBOARD_SIZE);
+
+    let stats = game_history(&state).await.all_stats(since_ms)?;
+    Ok(Json(leaderboard(stats, metric, limit)))
+}
+
+/// Ranks the players by decreasing value of the metric
+fn leaderboard(
+    mut stats: Vec<PlayerStats>,
+    metric: LeaderboardMetric,
+    limit: usize,
+) -> Vec<LeaderboardEntry> {
+    stats.sort_by_key(|stats| core::cmp::Reverse(metric.value(stats)));
+    stats
+        .into_iter()
+        .take(limit)
+        .enumerate()
+        .map(|(i, stats)| LeaderboardEntry {
+            rank: i + 1,
+            value: metric.value(&stats),
+            stats,
+        })
+        .collect()
+}
+
+#[derive(Serialize, ToSchema)]
+struct SeatView {
+    player: String,
+    cards: Vec<u32>,
+    count: u32,
+    bet: u32,
+    #[schema(value_type = String)]
+    state: SeatState,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SharedTableView {
+    table_id: TableId,
+    bank: Vec<u32>,
+    bank_count: u32,
+    #[schema(value_type = Object)]
+    phase: SharedTablePhase,
+    /// Player whose turn it is, if the hand is being played
+    turn: Option<String>,
+    seats: Vec<SeatView>,
+}
+
+impl SharedTableView {
+    fn new(table_id: TableId, table: &SharedTable) -> Self {
+        let turn = match table.phase {
+            SharedTablePhase::Playing(turn) => table
+                .seats
+                .get(turn as usize)
+                .map(|seat| seat.player.0.clone()),
+            _ => None,
+        };
+        SharedTableView {
+            table_id,
+            bank: table.bank.clone(),
+            bank_count: BlackJack::compute_score(&table.bank),
+            phase: table.phase.clone(),
+            turn,
+            seats: table
+                .seats
+                .iter()
+                .map(|seat| SeatView {
+                    player: seat.player.0.clone(),
+                    cards: seat.cards.clone(),
+                    count: BlackJack::compute_score(&seat.cards),
+                    bet: seat.bet,
+                    state: seat.state.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/shared_tables",
+    tag = "Contract",
+    responses(
+        (status = OK, description = "Get all shared tables and their seats", body = Vec<SharedTableView>)
+    )
+)]
+pub async fn get_shared_tables(
+    State(state): State<ContractHandlerStore<BlackJack>>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut store = state.write().await;
+    let cn = store.contract_name.clone();
+    let blackjack_state = store.state.as_mut().ok_or(AppError(
+        StatusCode::NOT_FOUND,
+        anyhow!("No state found for contract '{}'", cn),
+    ))?;
+    let state = blackjack_state
+        .optimistic_state
+        .compute_optimistic_state(blackjack_state.clone(), None)?;
+
+    Ok(Json(
+        state
+            .shared_tables
+            .iter()
+            .map(|(table_id, table)| SharedTableView::new(*table_id, table))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/shared_table/{table_id}",
+    tag = "Contract",
+    params(
+        ("table_id" = u32, Path, description = "Shared table id")
+    ),
+    responses(
+        (status = OK, description = "Get a shared table and its seats", body = SharedTableView),
+        (status = NOT_FOUND, description = "Shared table not found")
+    )
+)]
+pub async fn get_shared_table(
+    State(state): State<ContractHandlerStore<BlackJack>>,
+    axum::extract::Path(table_id): axum::extract::Path<TableId>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut store = state.write().await;
+    let cn = store.contract_name.clone();
+    let blackjack_state = store.state.as_mut().ok_or(AppError(
+        StatusCode::NOT_FOUND,
+        anyhow!("No state found for contract '{}'", cn),
+    ))?;
+    let state = blackjack_state
+        .optimistic_state
+        .compute_optimistic_state(blackjack_state.clone(), None)?;
+
+    let table = state.shared_tables.get(&table_id).ok_or(AppError(
+        StatusCode::NOT_FOUND,
+        anyhow!("Shared table {} not found", table_id),
+    ))?;
+
+    Ok(Json(SharedTableView::new(table_id, table)))
+}
+
+#[test]
+fn test_leaderboard() {
+    let history = GameHistory::default();
+    let hands = [
+        ("alice@wallet", HandOutcome::Win),
+        ("alice@wallet", HandOutcome::Loss),
+        ("bob@wallet", HandOutcome::Win),
+        ("bob@wallet", HandOutcome::Win),
+        ("carol@wallet", HandOutcome::Loss),
+    ];
+    for (i, (player, outcome)) in hands.into_iter().enumerate() {
+        history
+            .record(vec![history::hand(player, &format!("{i}"), outcome)])
+            .unwrap();
+    }
+    let stats = history.all_stats(0).unwrap();
+    let ranking = |metric, limit| {
+        leaderboard(stats.clone(), metric, limit)
+            .into_iter()
+            .map(|entry| (entry.stats.player, entry.value))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        ranking(LeaderboardMetric::NetVitamin, 10),
+        vec![
+            ("bob@wallet".to_string(), 20),
+            ("alice@wallet".to_string(), 10),
+            ("carol@wallet".to_string(), 0),
+        ]
+    );
+    assert_eq!(
+        ranking(LeaderboardMetric::LongestStreak, 1),
+        vec![("bob@wallet".to_string(), 2)]
+    );
+    assert_eq!(
+        ranking(LeaderboardMetric::NetOranj, 10)[0],
+        ("bob@wallet".to_string(), 0)
+    );
+}
+
+#[test]
+fn test_rejected_transaction_fails() {
+    let tx = BlobTransaction::new(
+        Identity::from("player@wallet"),
+        vec![BlackJackAction::Stand(42).as_blob("blackjack".into(), None, None)],
+    );
+    let mut state = BlackJack::default();
+    let result = state.optimistic_state.compute_optimistic_state(
+        state.clone(),
+        Some((tx, BlobIndex(0), TxContext::default())),
+    );
+    assert!(result.is_err());
+    assert!(state.optimistic_state.unsettled_txs.is_empty());
+}
+
+#[test]
+fn test_settled_hands_are_recorded() {
+    let player = Identity::from("player@wallet");
+    let mut state = BlackJack::default();
+    state.oranj_balances.insert(player.clone(), 100);
+    let settle = |state: &mut BlackJack, action: BlackJackAction| {
+        let tx = BlobTransaction::new(
+            player.clone(),
+            vec![action.as_blob("blackjack".into(), None, None)],
+        );
+        state
+            .handle_transaction_success(&tx, BlobIndex(0), TxContext::default())
+            .unwrap();
+        tx.hashed().to_string()
+    };
+
+    // The hand is recorded by the transaction ending it, unless dealt a natural
+    let mut tx_hash = settle(&mut state, BlackJackAction::Init(10));
+    if matches!(state.tables[&player][&0].state, TableState::Ongoing) {
+        assert_eq!(state.history.hands(&player, 0, 10).unwrap().0, 0);
+        tx_hash = settle(&mut state, BlackJackAction::Stand(0));
+    }
+    let (total, hands) = state.history.hands(&player, 0, 10).unwrap();
+    assert_eq!(total, 1);
+    assert_eq!((hands[0].table_id, hands[0].shared), (0, false));
+    assert_eq!(hands[0].tx_hash, tx_hash);
+}
@@ -0,0 +1,141 @@
+//! Runs the guest program in the risc0 executor, without proving, and checks its outputs
+//! against the host's execution of the same calldata.
+//!
+//! The guest runs from `blackjack.img` in the executor of the `r0vm` installed with the
+//! risc0 toolchain, so the image must be rebuilt after any change to the contract for this
+//! test to pass:
+//!
+//! ```sh
+//! cargo build -p contracts --features nonreproducible
+//! cargo test -p blackjack --features risc0-executor
+//! ```
+
+use alloc::{format, vec::Vec};
+use client_sdk::transaction_builder::TxExecutorHandler;
+use risc0_zkvm::{default_executor, ExecutorEnv};
+use sdk::{
+    BlockHeight, Calldata, ConsensusProposalHash, ContractAction, HyleOutput, Identity, TxContext,
+    TxHash, HYLE_TESTNET_CHAIN_ID,
+};
+
+use crate::client::metadata::BLACKJACK_ELF;
+use crate::*;
+
+/// Calldata of a transaction carrying a single blackjack blob, sequenced at `block_height`
+fn calldata(identity: &str, action: BlackJackAction, block_height: u64) -> Calldata {
+    let blob = action.as_blob("blackjack".into(), None, None);
+    Calldata {
+        identity: identity.into(),
+        index: BlobIndex(0),
+        blobs: alloc::vec![blob].into(),
+        tx_blob_count: 1,
+        tx_hash: TxHash::new(&format!("{identity}-{block_height}")),
+        tx_ctx: Some(TxContext {
+            block_hash: ConsensusProposalHash(format!("{block_height:064x}")),
+            block_height: BlockHeight(block_height),
+            chain_id: HYLE_TESTNET_CHAIN_ID,
+            ..Default::default()
+        }),
+        private_input: alloc::vec![],
+    }
+}
+
+/// Actions covering every kind of move, including some the contract rejects
+fn corpus() -> Vec<Calldata> {
+    let actions = [
+        ("alice@wallet", BlackJackAction::Init(10)),
+        ("alice@wallet", BlackJackAction::Hit(0)),
+        ("alice@wallet", BlackJackAction::Stand(0)),
+        ("bob@wallet", BlackJackAction::Init(5)),
+        ("bob@wallet", BlackJackAction::Init(20)),
+        ("bob@wallet", BlackJackAction::DoubleDown(1)),
+        ("bob@wallet", BlackJackAction::Stand(42)),
+        ("alice@wallet", BlackJackAction::OpenSharedTable),
+        ("alice@wallet", BlackJackAction::JoinSeat(0, 10)),
+        ("bob@wallet", BlackJackAction::JoinSeat(0, 30)),
+        ("bob@wallet", BlackJackAction::DealSharedTable(0)),
+        ("bob@wallet", BlackJackAction::SharedStand(0)),
+        ("alice@wallet", BlackJackAction::SharedHit(0)),
+        ("alice@wallet", BlackJackAction::SharedStand(0)),
+        ("bob@wallet", BlackJackAction::SharedStand(0)),
+        (
+            "bob@wallet",
+            BlackJackAction::Batch(alloc::vec![
+                BatchableAction::Init(10),
+                BatchableAction::Stand(42)
+            ]),
+        ),
+        (
+            "alice@wallet",
+            BlackJackAction::Batch(alloc::vec![
+                BatchableAction::Init(10),
+                BatchableAction::Hit(3),
+                BatchableAction::Hit(3)
+            ]),
+        ),
+        (
+            "carol@wallet",
+            BlackJackAction::AutoStand("alice@wallet".into(), 0),
+        ),
+        ("carol@wallet", BlackJackAction::CleanTick(0, None)),
+        ("operator@wallet", BlackJackAction::CleanTick(1, Some(0))),
+    ];
+    actions
+        .into_iter()
+        .enumerate()
+        .map(|(i, (identity, action))| calldata(identity, action, 10 + i as u64))
+        .collect()
+}
+
+fn initial_state() -> BlackJack {
+    let mut state = BlackJack::new(BlackJackConfig {
+        operator: Some("operator@wallet".into()),
+        game_timeout_blocks: Some(5),
+    });
+    for player in ["alice@wallet", "bob@wallet"] {
+        state.oranj_balances.insert(Identity::from(player), 100);
+    }
+    state
+}
+
+/// Outputs of the guest, as the prover would get them but without proving
+fn execute_guest(state: &BlackJack, calldata: &[Calldata]) -> Vec<HyleOutput> {
+    let commitment_metadata = borsh::to_vec(state).expect("Failed to encode BlackJack");
+    let input = borsh::to_vec(&(commitment_metadata, calldata)).expect("Failed to encode input");
+    let env = ExecutorEnv::builder()
+        .write(&input.len())
+        .expect("Failed to write input")
+        .write_slice(&input)
+        .build()
+        .expect("Failed to build executor env");
+    let session = default_executor()
+        .execute(env, BLACKJACK_ELF)
+        .expect("Guest execution failed");
+    session
+        .journal
+        .decode()
+        .expect("Failed to decode guest outputs")
+}
+
+/// Outputs of the host, which reverts the failed calls as the guest does
+fn execute_host(state: &BlackJack, calldata: &[Calldata]) -> Vec<HyleOutput> {
+    let mut state = state.clone();
+    calldata
+        .iter()
+        .map(|calldata| state.handle(calldata).expect("Host execution failed"))
+        .collect()
+}
+
+#[test]
+fn test_guest_matches_host() {
+    let state = initial_state();
+    let corpus = corpus();
+
+    let guest = execute_guest(&state, &corpus);
+    let host = execute_host(&state, &corpus);
+
+    assert_eq!(guest.len(), corpus.len());
+    for (i, (guest, host)) in guest.iter().zip(host.iter()).enumerate() {
+        assert_eq!(guest, host, "outputs diverge on calldata {i}");
+    }
+}
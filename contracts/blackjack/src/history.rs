@@ -0,0 +1,541 @@
+//! Ledger of completed hands, recorded by the indexer and persisted under the data directory.
+//!
+//! Hands are appended one after the other, borsh-encoded, to a single file that is
+//! replayed in memory when the ledger is opened. The file starts with a header giving the
+//! version of the record layout; files written before the header are of the first layout
+//! and are rewritten in the current one when opened. The ledger is held by the indexed state;
+//! the server opens it once at startup, and every state built afterwards shares it.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+
+use alloc::{string::String, vec::Vec};
+use anyhow::{anyhow, Context, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use client_sdk::contract_indexer::utoipa::{self, ToSchema};
+use sdk::{tracing::warn, Identity};
+use serde::{Deserialize, Serialize};
+
+use crate::TableId;
+
+pub const HISTORY_FILE: &str = "blackjack_history.bin";
+const HISTORY_MAGIC: &[u8] = b"BJH";
+/// Layout of the records written to the file
+pub const HISTORY_VERSION: u8 = 1;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum HandOutcome {
+    Win,
+    Loss,
+    Push,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone)]
+pub struct HandRecord {
+    pub player: Identity,
+    pub table_id: TableId,
+    /// Whether the hand was played at a shared table
+    pub shared: bool,
+    pub cards: Vec<u32>,
+    pub bank: Vec<u32>,
+    pub bet: u32,
+    pub outcome: HandOutcome,
+    /// Oranj tokens credited back to the player
+    pub payout: u32,
+    /// Vitamin tokens awarded to the player
+    pub reward: u32,
+    pub tx_hash: String,
+    pub block_height: u64,
+    /// Timestamp of the block, in milliseconds
+    pub timestamp_ms: u128,
+}
+
+/// Record of the first layout, without the timestamp
+#[derive(BorshDeserialize)]
+struct HandRecordV0 {
+    player: Identity,
+    table_id: TableId,
+    shared: bool,
+    cards: Vec<u32>,
+    bank: Vec<u32>,
+    bet: u32,
+    outcome: HandOutcome,
+    payout: u32,
+    reward: u32,
+    tx_hash: String,
+    block_height: u64,
+}
+
+impl From<HandRecordV0> for HandRecord {
+    fn from(record: HandRecordV0) -> Self {
+        HandRecord {
+            player: record.player,
+            table_id: record.table_id,
+            shared: record.shared,
+            cards: record.cards,
+            bank: record.bank,
+            bet: record.bet,
+            outcome: record.outcome,
+            payout: record.payout,
+            reward: record.reward,
+            tx_hash: record.tx_hash,
+            block_height: record.block_height,
+            // Unknown, such hands only count for the "all" window
+            timestamp_ms: 0,
+        }
+    }
+}
+
+/// Aggregated results of a player over a period
+#[derive(Serialize, Debug, Clone, Default, ToSchema)]
+pub struct PlayerStats {
+    pub player: String,
+    pub hands_played: u64,
+    pub wins: u64,
+    pub losses: u64,
+    pub pushes: u64,
+    /// Oranj tokens paid back minus Oranj tokens bet
+    pub net_oranj: i64,
+    /// Vitamin tokens awarded
+    pub net_vitamin: i64,
+    /// Largest amount won on a single hand
+    pub biggest_win: u32,
+    /// Longest run of consecutive wins
+    pub longest_streak: u32,
+}
+
+impl PlayerStats {
+    fn add(&mut self, hand: &HandRecord, current_streak: &mut u32) {
+        self.hands_played += 1;
+        self.net_oranj += hand.payout as i64 - hand.bet as i64;
+        self.net_vitamin += hand.reward as i64;
+        match hand.outcome {
+            HandOutcome::Win => {
+                self.wins += 1;
+                self.biggest_win = self
+                    .biggest_win
+                    .max((hand.payout + hand.reward).saturating_sub(hand.bet));
+                *current_streak += 1;
+                self.longest_streak = self.longest_streak.max(*current_streak);
+            }
+            HandOutcome::Loss => {
+                self.losses += 1;
+                *current_streak = 0;
+            }
+            HandOutcome::Push => {
+                self.pushes += 1;
+            }
+        }
+    }
+
+    fn compute(player: &Identity, hands: &[HandRecord], since_ms: u128) -> Self {
+        let mut stats = PlayerStats {
+            player: player.0.clone(),
+            ..Default::default()
+        };
+        let mut current_streak = 0;
+        for hand in hands.iter().filter(|hand| hand.timestamp_ms >= since_ms) {
+            stats.add(hand, &mut current_streak);
+        }
+        stats
+    }
+}
+
+#[derive(Debug, Default)]
+struct Ledger {
+    /// Unset until the ledger is opened
+    file: Option<File>,
+    hands: BTreeMap<Identity, Vec<HandRecord>>,
+    /// Transactions whose hands are recorded
+    tx_hashes: BTreeSet<String>,
+}
+
+impl Ledger {
+    /// Keeps the hands of transactions not recorded yet, returning them
+    fn insert(&mut self, records: Vec<HandRecord>) -> Vec<HandRecord> {
+        let new_txs: BTreeSet<String> = records
+            .iter()
+            .map(|record| record.tx_hash.clone())
+            .filter(|tx_hash| !self.tx_hashes.contains(tx_hash))
+            .collect();
+        let records: Vec<HandRecord> = records
+            .into_iter()
+            .filter(|record| new_txs.contains(&record.tx_hash))
+            .collect();
+        for record in records.iter() {
+            self.hands
+                .entry(record.player.clone())
+                .or_default()
+                .push(record.clone());
+        }
+        self.tx_hashes.extend(new_txs);
+        records
+    }
+}
+
+/// Ledger opened by the server, handed to the states built once it is set
+static OPENED: OnceLock<GameHistory> = OnceLock::new();
+
+/// Handle on the ledger, shared by the clones of the state
+#[derive(Debug, Clone)]
+pub struct GameHistory(Arc<Mutex<Ledger>>);
+
+impl Default for GameHistory {
+    /// The ledger opened by the server, or a new one kept in memory
+    fn default() -> Self {
+        OPENED
+            .get()
+            .cloned()
+            .unwrap_or_else(|| GameHistory(Default::default()))
+    }
+}
+
+impl GameHistory {
+    /// Opens the ledger in `data_directory` and shares it with every state built from then on,
+    /// including the ones decoded from disk
+    pub fn open_shared(data_directory: &Path) -> Result<()> {
+        let history = OPENED.get_or_init(|| GameHistory(Default::default()));
+        history.open(data_directory)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Ledger>> {
+        self.0
+            .lock()
+            .map_err(|_| anyhow!("Game history lock poisoned"))
+    }
+
+    /// Opens the file of the ledger in `data_directory`, loading the hands it holds and
+    /// writing the ones recorded in memory so far. Does nothing once opened.
+    pub fn open(&self, data_directory: &Path) -> Result<()> {
+        let mut ledger = self.lock()?;
+        if ledger.file.is_some() {
+            return Ok(());
+        }
+        let path = data_directory.join(HISTORY_FILE);
+
+        let mut bytes = Vec::new();
+        if path.exists() {
+            File::open(&path)
+                .and_then(|mut file| file.read_to_end(&mut bytes))
+                .context("Reading game history")?;
+        }
+        let (stored, valid_len) = match bytes.strip_prefix(HISTORY_MAGIC) {
+            Some([HISTORY_VERSION, records @ ..]) => {
+                let (stored, len) = decode::<HandRecord>(records);
+                (stored, Some(bytes.len() - records.len() + len))
+            }
+            Some([version, ..]) => {
+                return Err(anyhow!(
+                    "Game history is of an unknown version {version}, expected {HISTORY_VERSION}"
+                ))
+            }
+            // Empty, or written before the header
+            _ => {
+                let (stored, _) = decode::<HandRecordV0>(&bytes);
+                (stored.into_iter().map(HandRecord::from).collect(), None)
+            }
+        };
+
+        let mut file = match valid_len {
+            Some(valid_len) => {
+                let file = OpenOptions::new()
+                    .append(true)
+                    .open(&path)
+                    .context("Opening game history")?;
+                file.set_len(valid_len as u64)
+                    .context("Truncating game history")?;
+                file
+            }
+            None => rewrite(&path, &stored)?,
+        };
+
+        let recorded = core::mem::take(&mut *ledger);
+        ledger.insert(stored);
+        for record in ledger.insert(recorded.hands.into_values().flatten().collect()) {
+            append(&mut file, &record)?;
+        }
+        file.flush().context("Writing game history")?;
+        ledger.file = Some(file);
+        Ok(())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.lock().is_ok_and(|ledger| ledger.file.is_some())
+    }
+
+    /// Appends the hands to the ledger, unless their transaction was already recorded
+    pub fn record(&self, records: Vec<HandRecord>) -> Result<()> {
+        let mut ledger = self.lock()?;
+        let records = ledger.insert(records);
+        let Some(file) = ledger.file.as_mut() else {
+            return Ok(());
+        };
+        for record in records.iter() {
+            append(file, record)?;
+        }
+        file.flush().context("Writing game history")?;
+        Ok(())
+    }
+
+    /// Stats of the player over the hands played since `since_ms`
+    pub fn player_stats(&self, player: &Identity, since_ms: u128) -> Result<PlayerStats> {
+        let ledger = self.lock()?;
+        let hands = ledger
+            .hands
+            .get(player)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        Ok(PlayerStats::compute(player, hands, since_ms))
+    }
+
+    /// Stats of every player who played since `since_ms`
+    pub fn all_stats(&self, since_ms: u128) -> Result<Vec<PlayerStats>> {
+        let ledger = self.lock()?;
+        Ok(ledger
+            .hands
+            .iter()
+            .map(|(player, hands)| PlayerStats::compute(player, hands, since_ms))
+            .filter(|stats| stats.hands_played > 0)
+            .collect())
+    }
+
+    /// Returns the total number of hands of the player, and a page of them, most recent first
+    pub fn hands(
+        &self,
+        player: &Identity,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(usize, Vec<HandRecord>)> {
+        let ledger = self.lock()?;
+        let Some(hands) = ledger.hands.get(player) else {
+            return Ok((0, Vec::new()));
+        };
+        let page = hands
+            .iter()
+            .rev()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect();
+        Ok((hands.len(), page))
+    }
+}
+
+/// Decodes the records one after the other, returning them with the length they span
+fn decode<T: BorshDeserialize>(bytes: &[u8]) -> (Vec<T>, usize) {
+    let mut records = Vec::new();
+    let mut remaining = bytes;
+    let mut len = 0;
+    while !remaining.is_empty() {
+        match T::deserialize(&mut remaining) {
+            Ok(record) => {
+                records.push(record);
+                len = bytes.len() - remaining.len();
+            }
+            Err(e) => {
+                // Left by a write interrupted midway, the hand is dropped
+                warn!("Ignoring a partially written hand in the game history: {e}");
+                break;
+            }
+        }
+    }
+    (records, len)
+}
+
+/// Replaces the file with the header and the records in the current layout, returning it
+/// opened for appending
+fn rewrite(path: &Path, records: &[HandRecord]) -> Result<File> {
+    let tmp_path = path.with_extension("bin.tmp");
+    let mut file = File::create(&tmp_path).context("Rewriting game history")?;
+    file.write_all(HISTORY_MAGIC)
+        .and_then(|_| file.write_all(&[HISTORY_VERSION]))
+        .context("Rewriting game history")?;
+    for record in records {
+        append(&mut file, record)?;
+    }
+    file.sync_all().context("Rewriting game history")?;
+    std::fs::rename(&tmp_path, path).context("Rewriting game history")?;
+    OpenOptions::new()
+        .append(true)
+        .open(path)
+        .context("Opening game history")
+}
+
+fn append(file: &mut File, record: &HandRecord) -> Result<()> {
+    let bytes = borsh::to_vec(record).context("Failed to encode hand")?;
+    file.write_all(&bytes).context("Writing game history")
+}
+
+#[cfg(test)]
+pub(crate) fn hand(player: &str, tx_hash: &str, outcome: HandOutcome) -> HandRecord {
+    let (payout, reward) = match outcome {
+        HandOutcome::Win => (10, 10),
+        HandOutcome::Push => (10, 0),
+        HandOutcome::Loss => (0, 0),
+    };
+    HandRecord {
+        player: player.into(),
+        table_id: 0,
+        shared: false,
+        cards: alloc::vec![10, 9],
+        bank: alloc::vec![10, 7],
+        bet: 10,
+        outcome,
+        payout,
+        reward,
+        tx_hash: tx_hash.into(),
+        block_height: 1,
+        timestamp_ms: 1,
+    }
+}
+
+#[cfg(test)]
+fn test_directory(name: &str) -> std::path::PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("blackjack-history-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn test_history_dedupes_transactions() {
+    let history = GameHistory::default();
+    let alice: Identity = "alice@wallet".into();
+    history
+        .record(alloc::vec![hand("alice@wallet", "a", HandOutcome::Win)])
+        .unwrap();
+    // Settled again, e.g. while the indexer catches up
+    history
+        .record(alloc::vec![hand("alice@wallet", "a", HandOutcome::Win)])
+        .unwrap();
+    history
+        .record(alloc::vec![hand("alice@wallet", "b", HandOutcome::Loss)])
+        .unwrap();
+    assert_eq!(history.hands(&alice, 0, 10).unwrap().0, 2);
+}
+
+#[test]
+fn test_history_persists_hands() {
+    let directory = test_directory("persist");
+    let alice: Identity = "alice@wallet".into();
+
+    // Hands recorded before the file is opened are written to it
+    let history = GameHistory::default();
+    history
+        .record(alloc::vec![hand("alice@wallet", "a", HandOutcome::Win)])
+        .unwrap();
+    history.open(&directory).unwrap();
+    history
+        .record(alloc::vec![hand("alice@wallet", "b", HandOutcome::Loss)])
+        .unwrap();
+    drop(history);
+
+    // An interrupted write leaves part of a hand at the end of the file
+    let path = directory.join(HISTORY_FILE);
+    let len = std::fs::metadata(&path).unwrap().len();
+    let partial = borsh::to_vec(&hand("alice@wallet", "c", HandOutcome::Push)).unwrap();
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(&partial[..partial.len() / 2])
+        .unwrap();
+
+    let history = GameHistory::default();
+    history
+        .record(alloc::vec![hand("alice@wallet", "a", HandOutcome::Win)])
+        .unwrap();
+    history.open(&directory).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+    let (total, hands) = history.hands(&alice, 0, 10).unwrap();
+    assert_eq!(total, 2);
+    assert_eq!(hands[0].tx_hash, "b");
+
+    history
+        .record(alloc::vec![hand("alice@wallet", "c", HandOutcome::Push)])
+        .unwrap();
+    drop(history);
+    let history = GameHistory::default();
+    history.open(&directory).unwrap();
+    assert_eq!(history.hands(&alice, 0, 10).unwrap().0, 3);
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn test_history_reads_the_first_layout() {
+    let directory = test_directory("layout");
+    let alice: Identity = "alice@wallet".into();
+
+    // The first layout is the current one without the trailing timestamp
+    let mut legacy = Vec::new();
+    for tx_hash in ["a", "b"] {
+        let bytes = borsh::to_vec(&hand("alice@wallet", tx_hash, HandOutcome::Win)).unwrap();
+        legacy.extend_from_slice(&bytes[..bytes.len() - 16]);
+    }
+    let path = directory.join(HISTORY_FILE);
+    std::fs::write(&path, &legacy).unwrap();
+
+    let history = GameHistory::default();
+    history.open(&directory).unwrap();
+    let (total, hands) = history.hands(&alice, 0, 10).unwrap();
+    assert_eq!(total, 2);
+    assert!(hands.iter().all(|hand| hand.timestamp_ms == 0));
+    assert!(std::fs::read(&path).unwrap().starts_with(HISTORY_MAGIC));
+
+    history
+        .record(alloc::vec![hand("alice@wallet", "c", HandOutcome::Loss)])
+        .unwrap();
+    drop(history);
+    let history = GameHistory::default();
+    history.open(&directory).unwrap();
+    assert_eq!(history.hands(&alice, 0, 10).unwrap().0, 3);
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn test_player_stats() {
+    let history = GameHistory::default();
+    let alice: Identity = "alice@wallet".into();
+    for (i, outcome) in [
+        HandOutcome::Win,
+        HandOutcome::Win,
+        HandOutcome::Loss,
+        HandOutcome::Win,
+        HandOutcome::Push,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut record = hand("alice@wallet", &format!("{i}"), outcome);
+        record.timestamp_ms = i as u128;
+        history.record(alloc::vec![record]).unwrap();
+    }
+
+    let stats = history.player_stats(&alice, 0).unwrap();
+    assert_eq!(stats.hands_played, 5);
+    assert_eq!((stats.wins, stats.losses, stats.pushes), (3, 1, 1));
+    assert_eq!(stats.wins as f64 / stats.hands_played as f64, 0.6);
+    assert_eq!(stats.net_oranj, -10);
+    assert_eq!(stats.net_vitamin, 30);
+    assert_eq!(stats.biggest_win, 10);
+    assert_eq!(stats.longest_streak, 2);
+
+    // Only the hands played since the start of the window count
+    let stats = history.player_stats(&alice, 3).unwrap();
+    assert_eq!((stats.hands_played, stats.wins), (2, 1));
+    assert_eq!(
+        history
+            .player_stats(&"bob@wallet".into(), 0)
+            .unwrap()
+            .hands_played,
+        0
+    );
+}
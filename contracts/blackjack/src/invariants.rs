@@ -0,0 +1,219 @@
+//! Property tests running random sequences of actions against the contract.
+//!
+//! Actions run on a copy of the state that is dropped when they fail, as the node reverts
+//! failed transactions. After every successful action the invariants below must hold.
+
+use alloc::string::String;
+use proptest::prelude::*;
+use sdk::{ConsensusProposalHash, Identity};
+
+use crate::*;
+
+const PLAYERS: [&str; 3] = ["alice@wallet", "bob@wallet", "carol@wallet"];
+const OPERATOR: &str = "operator@wallet";
+
+#[derive(Debug, Clone)]
+enum Op {
+    Init(usize, u32, String),
+    Hit(usize, TableId, String),
+    Stand(usize, TableId, String),
+    DoubleDown(usize, TableId, String),
+    OpenSharedTable,
+    JoinSeat(usize, TableId, u32),
+    LeaveSeat(usize, TableId),
+    DealSharedTable(usize, TableId, String),
+    SharedHit(usize, TableId, String),
+    SharedStand(usize, TableId, String),
+    AutoStand(usize, TableId, String),
+    Clean(Option<u64>),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let player = 0..PLAYERS.len();
+    let table = 0..8_u32;
+    let bet = prop_oneof![0..50_u32, 0..2_000_u32];
+    let blockhash = "[0-9a-f]{16}";
+    prop_oneof![
+        3 => (player.clone(), bet.clone(), blockhash).prop_map(|(p, b, h)| Op::Init(p, b, h)),
+        3 => (player.clone(), table.clone(), blockhash).prop_map(|(p, t, h)| Op::Hit(p, t, h)),
+        3 => (player.clone(), table.clone(), blockhash).prop_map(|(p, t, h)| Op::Stand(p, t, h)),
+        2 => (player.clone(), table.clone(), blockhash)
+            .prop_map(|(p, t, h)| Op::DoubleDown(p, t, h)),
+        1 => Just(Op::OpenSharedTable),
+        2 => (player.clone(), table.clone(), bet).prop_map(|(p, t, b)| Op::JoinSeat(p, t, b)),
+        1 => (player.clone(), table.clone()).prop_map(|(p, t)| Op::LeaveSeat(p, t)),
+        2 => (player.clone(), table.clone(), blockhash)
+            .prop_map(|(p, t, h)| Op::DealSharedTable(p, t, h)),
+        2 => (player.clone(), table.clone(), blockhash).prop_map(|(p, t, h)| Op::SharedHit(p, t, h)),
+        2 => (player.clone(), table.clone(), blockhash)
+            .prop_map(|(p, t, h)| Op::SharedStand(p, t, h)),
+        1 => (player, table, blockhash).prop_map(|(p, t, h)| Op::AutoStand(p, t, h)),
+        1 => proptest::option::of(0..20_u64).prop_map(Op::Clean),
+    ]
+}
+
+fn identity(player: usize) -> Identity {
+    PLAYERS[player].into()
+}
+
+fn apply(state: &mut BlackJack, op: &Op, block_height: u64) -> Result<String, String> {
+    let hash = |h: &String| ConsensusProposalHash(h.clone());
+    match op {
+        Op::Init(p, bet, h) => state.new_game(&identity(*p), &hash(h), block_height, *bet),
+        Op::Hit(p, t, h) => state.hit(&identity(*p), *t, &hash(h)),
+        Op::Stand(p, t, h) => state.stand(&identity(*p), *t, &hash(h)),
+        Op::DoubleDown(p, t, h) => state.double_down(&identity(*p), *t, &hash(h)),
+        Op::OpenSharedTable => state.open_shared_table(block_height),
+        Op::JoinSeat(p, t, bet) => state.join_seat(&identity(*p), *t, *bet),
+        Op::LeaveSeat(p, t) => state.leave_seat(&identity(*p), *t),
+        Op::DealSharedTable(p, t, h) => {
+            state.deal_shared_table(&identity(*p), *t, &hash(h), block_height)
+        }
+        Op::SharedHit(p, t, h) => state.shared_hit(&identity(*p), *t, &hash(h)),
+        Op::SharedStand(p, t, h) => state.shared_stand(&identity(*p), *t, &hash(h)),
+        Op::AutoStand(p, t, h) => state.auto_stand(&identity(*p), *t, block_height, &hash(h)),
+        Op::Clean(expire_after) => state.clean(&OPERATOR.into(), block_height, *expire_after),
+    }
+}
+
+fn is_settled(state: &SeatState) -> bool {
+    matches!(state, SeatState::Won | SeatState::Lost | SeatState::Push)
+}
+
+/// Oranj tokens held by the players or escrowed on the hands still being played
+fn oranj_in_play(state: &BlackJack) -> u64 {
+    let balances: u64 = state.oranj_balances.values().map(|b| *b as u64).sum();
+    let tables: u64 = state
+        .tables
+        .values()
+        .flat_map(|tables| tables.values())
+        .filter(|table| matches!(table.state, TableState::Ongoing))
+        .map(|table| table.bet as u64)
+        .sum();
+    let seats: u64 = state
+        .shared_tables
+        .values()
+        .flat_map(|table| table.seats.iter())
+        .filter(|seat| !is_settled(&seat.state))
+        .map(|seat| seat.bet as u64)
+        .sum();
+    balances + tables + seats
+}
+
+/// Bets of the hands the action lost to the house
+fn newly_lost(before: &BlackJack, after: &BlackJack) -> u64 {
+    let mut lost = 0;
+    for (player, tables) in after.tables.iter() {
+        for (table_id, table) in tables.iter() {
+            let was_lost = before
+                .get_table(player, *table_id)
+                .is_some_and(|table| matches!(table.state, TableState::Lost));
+            if matches!(table.state, TableState::Lost) && !was_lost {
+                lost += table.bet as u64;
+            }
+        }
+    }
+    for (table_id, table) in after.shared_tables.iter() {
+        for seat in table.seats.iter() {
+            let was_lost = before
+                .shared_tables
+                .get(table_id)
+                .and_then(|table| table.seats.iter().find(|s| s.player == seat.player))
+                .is_some_and(|seat| matches!(seat.state, SeatState::Lost));
+            if matches!(seat.state, SeatState::Lost) && !was_lost {
+                lost += seat.bet as u64;
+            }
+        }
+    }
+    lost
+}
+
+/// Sends every move to the finished tables, which must all be rejected
+fn assert_finished_tables_reject_actions(state: &BlackJack) {
+    let blockhash = ConsensusProposalHash("ff".into());
+    for (player, tables) in state.tables.iter() {
+        for (table_id, table) in tables.iter() {
+            if matches!(table.state, TableState::Ongoing) {
+                continue;
+            }
+            let mut copy = state.clone();
+            assert!(copy.hit(player, *table_id, &blockhash).is_err());
+            assert!(copy.stand(player, *table_id, &blockhash).is_err());
+            assert!(copy.double_down(player, *table_id, &blockhash).is_err());
+            assert!(copy
+                .auto_stand(player, *table_id, u64::MAX, &blockhash)
+                .is_err());
+            assert_eq!(copy.as_bytes().unwrap(), state.as_bytes().unwrap());
+        }
+    }
+    for (table_id, table) in state.shared_tables.iter() {
+        if !matches!(table.phase, SharedTablePhase::Finished) {
+            continue;
+        }
+        for seat in table.seats.iter() {
+            let mut copy = state.clone();
+            assert!(copy
+                .shared_hit(&seat.player, *table_id, &blockhash)
+                .is_err());
+            assert!(copy
+                .shared_stand(&seat.player, *table_id, &blockhash)
+                .is_err());
+            assert!(copy
+                .deal_shared_table(&seat.player, *table_id, &blockhash, u64::MAX)
+                .is_err());
+            assert!(copy
+                .auto_stand(&seat.player, *table_id, u64::MAX, &blockhash)
+                .is_err());
+            assert!(copy.join_seat(&seat.player, *table_id, 10).is_err());
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_contract_invariants(
+        balances in proptest::collection::vec(0..5_000_u32, PLAYERS.len()),
+        ops in proptest::collection::vec(op(), 1..120),
+    ) {
+        let mut state = BlackJack::new(BlackJackConfig {
+            operator: Some(OPERATOR.into()),
+            game_timeout_blocks: Some(5),
+        });
+        for (player, balance) in balances.iter().enumerate() {
+            state.oranj_balances.insert(identity(player), *balance);
+        }
+        let supply: u64 = balances.iter().map(|b| *b as u64).sum();
+        let mut house = 0_u64;
+
+        for (block_height, op) in ops.iter().enumerate() {
+            let mut next = state.clone();
+            if apply(&mut next, op, block_height as u64).is_err() {
+                continue;
+            }
+            house += newly_lost(&state, &next);
+            state = next;
+
+            // Tokens only leave the players for the house
+            prop_assert_eq!(oranj_in_play(&state) + house, supply, "after {:?}", op);
+
+            let bets = state
+                .tables
+                .values()
+                .flat_map(|tables| tables.values().map(|table| table.bet))
+                .chain(
+                    state
+                        .shared_tables
+                        .values()
+                        .flat_map(|table| table.seats.iter().map(|seat| seat.bet)),
+                );
+            for bet in bets {
+                prop_assert!(bet >= 10 && bet as u64 <= supply, "bet {} after {:?}", bet, op);
+            }
+
+            assert_finished_tables_reject_actions(&state);
+
+            let restored = BlackJack::from(sdk::ZkContract::commit(&state));
+            prop_assert_eq!(restored.as_bytes().unwrap(), state.as_bytes().unwrap());
+        }
+    }
+}
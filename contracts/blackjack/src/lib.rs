@@ -12,17 +12,29 @@ use borsh::{io::Error, BorshDeserialize, BorshSerialize};
 use rand::Rng;
 use rand_seeder::{SipHasher, SipRng};
 use sdk::caller::ExecutionContext;
-use sdk::{Blob, BlobData, BlobIndex, Calldata, ContractAction, StructuredBlobData};
+use sdk::{Blob, BlobData, BlobIndex, Calldata, ContractAction, StructuredBlobData, TxContext};
 use serde::{Deserialize, Serialize};
 
 use hyle_smt_token::SmtTokenAction;
-use sdk::{BlockHash, ContractName, Identity, RunResult};
+use sdk::{BlockHash, ContractName, Identity, RunResult, TxHash};
 
 #[cfg(feature = "client")]
 use crate::client::OptimisticBlackJack;
 
 #[cfg(feature = "client")]
 pub mod client;
+#[cfg(feature = "client")]
+pub mod history;
+#[cfg(feature = "client")]
+pub mod simulation;
+
+pub mod advisor;
+#[cfg(all(test, feature = "risc0-executor"))]
+mod executor;
+#[cfg(test)]
+mod invariants;
+mod shared;
+pub use shared::*;
 
 impl sdk::FullStateRevert for BlackJack {}
 
@@ -32,31 +44,12 @@ impl sdk::ZkContract for BlackJack {
         // Parse contract inputs
         let (action, mut ctx) = sdk::utils::parse_calldata::<BlackJackAction>(calldata)?;
 
-        let user = &calldata.identity;
-
         let Some(tx_ctx) = calldata.tx_ctx.as_ref() else {
             return Err("Missing tx context necessary for this contract".to_string());
         };
 
         // Execute the given action
-        let res = match action {
-            BlackJackAction::Init(bet) => self.new_game(user, &tx_ctx.block_hash, bet)?,
-            BlackJackAction::Hit => self.hit(user, &tx_ctx.block_hash)?,
-            BlackJackAction::Stand => self.stand(user)?,
-            BlackJackAction::DoubleDown => self.double_down(user)?,
-            BlackJackAction::Deposit(amount) => self.claim(amount, user, calldata, &ctx)?,
-            BlackJackAction::Withdraw(amount, token) => {
-                self.withdraw(amount, user, token, &mut ctx)?
-            }
-            BlackJackAction::CleanTick(_nonce) => {
-                // Remove all tables and balances that are 0
-                self.tables
-                    .retain(|_, table| !matches!(table.state, TableState::Ongoing));
-                self.oranj_balances.retain(|_, &mut balance| balance > 0);
-                self.vitamin_balances.retain(|_, &mut balance| balance > 0);
-                "Cleaned state".to_string()
-            }
-        };
+        let res = self.run_action(action, calldata, tx_ctx, &mut ctx)?;
 
         Ok((res.into(), ctx, alloc::vec![]))
     }
@@ -67,6 +60,29 @@ impl sdk::ZkContract for BlackJack {
     }
 }
 
+/// Name the contract is registered under by default. The state of the contract first
+/// registered as `blackjack` has another layout, which this version can't decode.
+pub const CONTRACT_NAME: &str = "blackjack_v2";
+
+/// Number of actions a `Batch` can hold
+pub const MAX_BATCH_ACTIONS: usize = 16;
+
+/// Seed of the cards a transaction draws. The block hash alone would deal the same cards
+/// to every transaction of the block.
+pub fn tx_seed(block_hash: &BlockHash, tx_hash: &TxHash) -> BlockHash {
+    sdk::ConsensusProposalHash(format!("{}:{}", block_hash.0, tx_hash.0))
+}
+
+/// Generator of the cards drawn at a table, distinct for tables dealt by the same transaction.
+/// The number of cards already dealt tells apart the actions played at the table by a batch.
+fn table_rng(seed: &BlockHash, table_id: TableId, dealt: usize) -> SipRng {
+    let mut hasher = SipHasher::new();
+    hasher.write(seed.0.as_bytes());
+    hasher.write_u32(table_id);
+    hasher.write_usize(dealt);
+    hasher.into_rng()
+}
+
 pub const CARDS: [u32; 13] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13];
 // pub const NB_CARDPACKS: usize = 6 * 4;
 // pub const TOTAL_CARDS: usize = NB_CARDPACKS * CARDS.len();
@@ -79,6 +95,9 @@ pub enum TableState {
     Won,
 }
 
+/// Identifier of a table, unique across all players
+pub type TableId = u32;
+
 /// The state of the contract, that is totally serialized on-chain
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Table {
@@ -87,29 +106,163 @@ pub struct Table {
     pub user: Vec<u32>,
     pub bet: u32,
     pub state: TableState,
+    /// Block height at which the game was started
+    pub started_at: u64,
+}
+
+impl Table {
+    /// Whether the game is still ongoing more than `timeout_blocks` blocks after it started
+    pub fn is_abandoned(&self, block_height: u64, timeout_blocks: u64) -> bool {
+        matches!(self.state, TableState::Ongoing)
+            && block_height.saturating_sub(self.started_at) > timeout_blocks
+    }
+}
+
+/// Settings of the contract, given as constructor metadata at registration
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BlackJackConfig {
+    /// Identity allowed to garbage-collect the state with `CleanTick`
+    pub operator: Option<Identity>,
+    /// Number of blocks after which anyone can `AutoStand` an ongoing game. Disabled when unset.
+    pub game_timeout_blocks: Option<u64>,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
 pub struct BlackJack {
-    pub tables: BTreeMap<Identity, Table>,
+    pub config: BlackJackConfig,
+    pub next_table_id: TableId,
+    pub tables: BTreeMap<Identity, BTreeMap<TableId, Table>>,
+    pub shared_tables: BTreeMap<TableId, SharedTable>,
     pub oranj_balances: BTreeMap<Identity, u32>,
     pub vitamin_balances: BTreeMap<Identity, u32>,
     #[cfg(feature = "client")]
     #[serde(skip)]
     #[borsh(skip)]
     pub optimistic_state: OptimisticBlackJack,
+    /// Completed hands, recorded as transactions settle
+    #[cfg(feature = "client")]
+    #[serde(skip)]
+    #[borsh(skip)]
+    pub history: history::GameHistory,
 }
 
 /// Enum representing possible calls to the contract functions.
 #[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub enum BlackJackAction {
     Init(u32),
-    Hit,
-    Stand,
-    DoubleDown,
+    Hit(TableId),
+    Stand(TableId),
+    DoubleDown(TableId),
     Deposit(u32),
     Withdraw(u32, String), // amount, token ("oranj" or "vitamin")
-    CleanTick(u128),       // remove all tables and balances that are 0
+    OpenSharedTable,
+    JoinSeat(TableId, u32), // shared table, bet
+    LeaveSeat(TableId),
+    DealSharedTable(TableId),
+    SharedHit(TableId),
+    SharedStand(TableId),
+    AutoStand(Identity, TableId), // stand on behalf of a player whose game timed out
+    CleanTick(u128, Option<u64>), // nonce, expire ongoing tables older than this many blocks
+    Batch(Vec<BatchableAction>),  // actions played in order in a single transaction
+}
+
+/// Actions a `Batch` can hold. Deposits and withdrawals move tokens through their own blobs
+/// of the transaction, and batches do not nest.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum BatchableAction {
+    Init(u32),
+    Hit(TableId),
+    Stand(TableId),
+    DoubleDown(TableId),
+    OpenSharedTable,
+    JoinSeat(TableId, u32),
+    LeaveSeat(TableId),
+    DealSharedTable(TableId),
+    SharedHit(TableId),
+    SharedStand(TableId),
+    AutoStand(Identity, TableId),
+    CleanTick(u128, Option<u64>),
+}
+
+impl From<BatchableAction> for BlackJackAction {
+    fn from(action: BatchableAction) -> Self {
+        match action {
+            BatchableAction::Init(bet) => BlackJackAction::Init(bet),
+            BatchableAction::Hit(table_id) => BlackJackAction::Hit(table_id),
+            BatchableAction::Stand(table_id) => BlackJackAction::Stand(table_id),
+            BatchableAction::DoubleDown(table_id) => BlackJackAction::DoubleDown(table_id),
+            BatchableAction::OpenSharedTable => BlackJackAction::OpenSharedTable,
+            BatchableAction::JoinSeat(table_id, bet) => BlackJackAction::JoinSeat(table_id, bet),
+            BatchableAction::LeaveSeat(table_id) => BlackJackAction::LeaveSeat(table_id),
+            BatchableAction::DealSharedTable(table_id) => {
+                BlackJackAction::DealSharedTable(table_id)
+            }
+            BatchableAction::SharedHit(table_id) => BlackJackAction::SharedHit(table_id),
+            BatchableAction::SharedStand(table_id) => BlackJackAction::SharedStand(table_id),
+            BatchableAction::AutoStand(player, table_id) => {
+                BlackJackAction::AutoStand(player, table_id)
+            }
+            BatchableAction::CleanTick(nonce, expire_after) => {
+                BlackJackAction::CleanTick(nonce, expire_after)
+            }
+        }
+    }
+}
+
+impl BlackJackAction {
+    /// The table targeted by the action, if any
+    pub fn table_id(&self) -> Option<TableId> {
+        match self {
+            BlackJackAction::Hit(table_id)
+            | BlackJackAction::Stand(table_id)
+            | BlackJackAction::DoubleDown(table_id)
+            | BlackJackAction::JoinSeat(table_id, _)
+            | BlackJackAction::LeaveSeat(table_id)
+            | BlackJackAction::DealSharedTable(table_id)
+            | BlackJackAction::SharedHit(table_id)
+            | BlackJackAction::SharedStand(table_id)
+            | BlackJackAction::AutoStand(_, table_id) => Some(*table_id),
+            // The table the batch ends on
+            BlackJackAction::Batch(actions) => actions
+                .iter()
+                .rev()
+                .find_map(|action| BlackJackAction::from(action.clone()).table_id()),
+            _ => None,
+        }
+    }
+
+    /// Whether the action opens a table, with an `Init` or `OpenSharedTable`
+    pub fn opens_table(&self) -> bool {
+        match self {
+            BlackJackAction::Init(_) | BlackJackAction::OpenSharedTable => true,
+            BlackJackAction::Batch(actions) => actions.iter().any(|action| {
+                matches!(
+                    action,
+                    BatchableAction::Init(_) | BatchableAction::OpenSharedTable
+                )
+            }),
+            _ => false,
+        }
+    }
+
+    /// Tables the action plays, every one of a batch, with the player of each: the caller,
+    /// or the player an `AutoStand` stands for
+    pub fn played_tables(&self, caller: &Identity) -> Vec<(Identity, TableId)> {
+        match self {
+            BlackJackAction::AutoStand(player, table_id) => {
+                alloc::vec![(player.clone(), *table_id)]
+            }
+            BlackJackAction::Batch(actions) => actions
+                .iter()
+                .flat_map(|action| BlackJackAction::from(action.clone()).played_tables(caller))
+                .collect(),
+            action => action
+                .table_id()
+                .map(|table_id| (caller.clone(), table_id))
+                .into_iter()
+                .collect(),
+        }
+    }
 }
 
 impl ContractAction for BlackJackAction {
@@ -131,24 +284,111 @@ impl ContractAction for BlackJackAction {
 }
 
 impl BlackJack {
+    pub fn new(config: BlackJackConfig) -> Self {
+        BlackJack {
+            config,
+            ..Default::default()
+        }
+    }
+
     pub fn as_bytes(&self) -> Result<Vec<u8>, Error> {
         borsh::to_vec(self)
     }
+
+    pub fn get_table(&self, user: &Identity, table_id: TableId) -> Option<&Table> {
+        self.tables
+            .get(user)
+            .and_then(|tables| tables.get(&table_id))
+    }
+
+    /// Table played by an action, read from the state it left: the table it targets, or
+    /// the last table given out when it opened one
+    pub fn played_table(&self, action: &BlackJackAction) -> Option<TableId> {
+        action.table_id().or_else(|| {
+            action
+                .opens_table()
+                .then(|| self.next_table_id.checked_sub(1))
+                .flatten()
+        })
+    }
+
+    /// Whether the user has at least one game in progress, alone or at a shared table
+    pub fn has_ongoing_game(&self, user: &Identity) -> bool {
+        self.tables.get(user).is_some_and(|tables| {
+            tables
+                .values()
+                .any(|table| matches!(table.state, TableState::Ongoing))
+        }) || self
+            .shared_tables
+            .values()
+            .any(|table| table.has_ongoing_seat(user))
+    }
+
+    fn run_action(
+        &mut self,
+        action: BlackJackAction,
+        calldata: &Calldata,
+        tx_ctx: &TxContext,
+        ctx: &mut ExecutionContext,
+    ) -> Result<String, String> {
+        let user = &calldata.identity;
+        let seed = tx_seed(&tx_ctx.block_hash, &calldata.tx_hash);
+        match action {
+            BlackJackAction::Init(bet) => self.new_game(user, &seed, tx_ctx.block_height.0, bet),
+            BlackJackAction::Hit(table_id) => self.hit(user, table_id, &seed),
+            BlackJackAction::Stand(table_id) => self.stand(user, table_id, &seed),
+            BlackJackAction::DoubleDown(table_id) => self.double_down(user, table_id, &seed),
+            BlackJackAction::Deposit(amount) => self.claim(amount, user, calldata, ctx),
+            BlackJackAction::Withdraw(amount, token) => self.withdraw(amount, user, token, ctx),
+            BlackJackAction::OpenSharedTable => self.open_shared_table(tx_ctx.block_height.0),
+            BlackJackAction::JoinSeat(table_id, bet) => self.join_seat(user, table_id, bet),
+            BlackJackAction::LeaveSeat(table_id) => self.leave_seat(user, table_id),
+            BlackJackAction::DealSharedTable(table_id) => {
+                self.deal_shared_table(user, table_id, &seed, tx_ctx.block_height.0)
+            }
+            BlackJackAction::SharedHit(table_id) => self.shared_hit(user, table_id, &seed),
+            BlackJackAction::SharedStand(table_id) => self.shared_stand(user, table_id, &seed),
+            BlackJackAction::AutoStand(player, table_id) => {
+                self.auto_stand(&player, table_id, tx_ctx.block_height.0, &seed)
+            }
+            BlackJackAction::CleanTick(_nonce, expire_after) => {
+                self.clean(user, tx_ctx.block_height.0, expire_after)
+            }
+            BlackJackAction::Batch(actions) => self.batch(actions, calldata, tx_ctx, ctx),
+        }
+    }
+
+    /// Plays the actions in order, all or none of them: the first failing action fails the
+    /// transaction, whose changes are reverted as for any failed transaction
+    fn batch(
+        &mut self,
+        actions: Vec<BatchableAction>,
+        calldata: &Calldata,
+        tx_ctx: &TxContext,
+        ctx: &mut ExecutionContext,
+    ) -> Result<String, String> {
+        if actions.is_empty() {
+            return Err("Empty batch".to_string());
+        }
+        if actions.len() > MAX_BATCH_ACTIONS {
+            return Err(format!("A batch holds at most {MAX_BATCH_ACTIONS} actions"));
+        }
+        let mut outputs = Vec::with_capacity(actions.len());
+        for action in actions {
+            outputs.push(self.run_action(action.into(), calldata, tx_ctx, ctx)?);
+        }
+        Ok(outputs.join("\n"))
+    }
 }
 
 impl BlackJack {
     pub fn new_game(
         &mut self,
         user: &Identity,
-        blockhash: &BlockHash,
+        seed: &BlockHash,
+        block_height: u64,
         bet: u32,
     ) -> Result<String, String> {
-        if let Some(table) = self.tables.get(user) {
-            if matches!(table.state, TableState::Ongoing) {
-                return Ok(format!("Game already started for user {user}", user = user,));
-            }
-        }
-
         if bet < 10 {
             return Err("Minimum bet is 10".to_string());
         }
@@ -162,17 +402,20 @@ impl BlackJack {
             ));
         }
 
-        let mut hasher = SipHasher::new();
-        hasher.write(blockhash.0.as_bytes());
-        let mut rnd = hasher.into_rng();
+        let table_id = self.next_table_id;
+        self.next_table_id = table_id
+            .checked_add(1)
+            .ok_or_else(|| "Table id overflow".to_string())?;
 
-        let card_1: u32 = *CARDS.get(rnd.random_range(0..(CARDS.len() - 1))).unwrap();
-        let card_2: u32 = *CARDS.get(rnd.random_range(0..(CARDS.len() - 1))).unwrap();
-        let card_3: u32 = *CARDS.get(rnd.random_range(0..(CARDS.len() - 1))).unwrap();
-        let card_4: u32 = *CARDS.get(rnd.random_range(0..(CARDS.len() - 1))).unwrap();
+        let mut rnd = table_rng(seed, table_id, 0);
+        let card_1: u32 = Self::pick_random_card(&mut rnd);
+        let card_2: u32 = Self::pick_random_card(&mut rnd);
+        let card_3: u32 = Self::pick_random_card(&mut rnd);
+        let card_4: u32 = Self::pick_random_card(&mut rnd);
 
         let mut table = Table {
             bet,
+            started_at: block_height,
             ..Default::default()
         };
 
@@ -205,28 +448,37 @@ impl BlackJack {
             } else {
                 self.vitamin_balances.insert(user.clone(), table.bet);
             }
-            self.tables.insert(user.clone(), table);
+            self.tables
+                .entry(user.clone())
+                .or_default()
+                .insert(table_id, table);
             Ok(format!(
-                "Initiated new game for user {user} with block hash {blockhash}, BLACKJACK!!!!",
+                "Initiated new game {table_id} for user {user} with seed {seed}, BLACKJACK!!!!",
                 user = user,
-                blockhash = blockhash.0
+                seed = seed.0
             ))
         } else {
             let bank_score = Self::compute_score(table.bank.as_slice());
             if bank_score == 21_u32 {
                 table.state = TableState::Lost;
-                self.tables.insert(user.clone(), table);
+                self.tables
+                    .entry(user.clone())
+                    .or_default()
+                    .insert(table_id, table);
                 Ok(format!(
-                    "Initiated new game for user {user} with block hash {blockhash} and loose immediately",
+                    "Initiated new game {table_id} for user {user} with seed {seed} and loose immediately",
                     user = user,
-                    blockhash = blockhash.0
+                    seed = seed.0
                 ))
             } else {
-                self.tables.insert(user.clone(), table);
+                self.tables
+                    .entry(user.clone())
+                    .or_default()
+                    .insert(table_id, table);
                 Ok(format!(
-                    "Initiated new game for user {user} with block hash {blockhash}",
+                    "Initiated new game {table_id} for user {user} with seed {seed}",
                     user = user,
-                    blockhash = blockhash.0
+                    seed = seed.0
                 ))
             }
         }
@@ -270,8 +522,17 @@ impl BlackJack {
         *CARDS.get(rnd.random_range(0..(CARDS.len() - 1))).unwrap()
     }
 
-    pub fn hit(&mut self, user: &Identity, blockhash: &BlockHash) -> Result<String, String> {
-        let Some(table) = self.tables.get_mut(user) else {
+    pub fn hit(
+        &mut self,
+        user: &Identity,
+        table_id: TableId,
+        seed: &BlockHash,
+    ) -> Result<String, String> {
+        let Some(table) = self
+            .tables
+            .get_mut(user)
+            .and_then(|tables| tables.get_mut(&table_id))
+        else {
             return Err("Table not setup. Start a new game first".to_string());
         };
 
@@ -279,10 +540,7 @@ impl BlackJack {
             return Err("Cannot hit on finished game!".to_string());
         }
 
-        let mut hasher = SipHasher::new();
-        hasher.write(blockhash.0.as_bytes());
-        let mut rnd = hasher.into_rng();
-
+        let mut rnd = table_rng(seed, table_id, table.user.len() + table.bank.len());
         table.user.push(Self::pick_random_card(&mut rnd));
 
         let user_score = Self::compute_score(table.user.as_slice());
@@ -303,31 +561,40 @@ impl BlackJack {
                     self.vitamin_balances.insert(user.clone(), table.bet);
                 }
                 Ok(format!(
-                    "Hit for user {user} with block hash {blockhash}, BLACKJACK!!!!",
+                    "Hit for user {user} with seed {seed}, BLACKJACK!!!!",
                     user = user,
-                    blockhash = blockhash.0
+                    seed = seed.0
                 ))
             }
             score if score > 21 => {
                 table.state = TableState::Lost;
                 Ok(format!(
-                    "Hit for user {user} with block hash {blockhash}, BURST, you loose",
+                    "Hit for user {user} with seed {seed}, BURST, you loose",
                     user = user,
-                    blockhash = blockhash.0
+                    seed = seed.0
                 ))
             }
             _ => {
                 // Still Ongoing
                 Ok(format!(
-                    "Hit for user {user} with block hash {blockhash}, still ongoing",
+                    "Hit for user {user} with seed {seed}, still ongoing",
                     user = user,
-                    blockhash = blockhash.0
+                    seed = seed.0
                 ))
             }
         }
     }
-    pub fn stand(&mut self, user: &Identity) -> Result<String, String> {
-        let Some(table) = self.tables.get_mut(user) else {
+    pub fn stand(
+        &mut self,
+        user: &Identity,
+        table_id: TableId,
+        seed: &BlockHash,
+    ) -> Result<String, String> {
+        let Some(table) = self
+            .tables
+            .get_mut(user)
+            .and_then(|tables| tables.get_mut(&table_id))
+        else {
             return Err("Table not setup. Start a new game first".to_string());
         };
 
@@ -338,10 +605,7 @@ impl BlackJack {
         let user_score = Self::compute_score(&table.user);
 
         // Bank's turn - keep drawing cards until score > 16
-        let mut hasher = SipHasher::new();
-        hasher.write(user.0.as_bytes());
-        let mut rnd = hasher.into_rng();
-
+        let mut rnd = table_rng(seed, table_id, table.user.len() + table.bank.len());
         while Self::compute_score(&table.bank) <= 16 {
             table.bank.push(Self::pick_random_card(&mut rnd));
         }
@@ -398,8 +662,17 @@ impl BlackJack {
             Ok(format!("Stand for user {user}, you loose", user = user,))
         }
     }
-    pub fn double_down(&mut self, user: &Identity) -> Result<String, String> {
-        let Some(table) = self.tables.get_mut(user) else {
+    pub fn double_down(
+        &mut self,
+        user: &Identity,
+        table_id: TableId,
+        seed: &BlockHash,
+    ) -> Result<String, String> {
+        let Some(table) = self
+            .tables
+            .get_mut(user)
+            .and_then(|tables| tables.get_mut(&table_id))
+        else {
             return Err("Table not setup. Start a new game first".to_string());
         };
 
@@ -422,9 +695,7 @@ impl BlackJack {
         }
 
         // Draw one more card for the player
-        let mut hasher = SipHasher::new();
-        hasher.write(user.0.as_bytes());
-        let mut rnd = hasher.into_rng();
+        let mut rnd = table_rng(seed, table_id, table.user.len() + table.bank.len());
         table.user.push(Self::pick_random_card(&mut rnd));
 
         let user_score = Self::compute_score(table.user.as_slice());
@@ -512,6 +783,142 @@ impl BlackJack {
         }
     }
 
+    /// Resolves a game whose player stopped playing, as if they had sent `Stand`, or
+    /// `SharedStand` when it is their turn at a shared table. Anyone can call it once the
+    /// game, or the shared hand since it was dealt, is older than `game_timeout_blocks`.
+    pub fn auto_stand(
+        &mut self,
+        player: &Identity,
+        table_id: TableId,
+        block_height: u64,
+        seed: &BlockHash,
+    ) -> Result<String, String> {
+        let Some(timeout_blocks) = self.config.game_timeout_blocks else {
+            return Err("Auto-stand is disabled".to_string());
+        };
+        if let Some(table) = self.get_table(player, table_id) {
+            if !table.is_abandoned(block_height, timeout_blocks) {
+                return Err(format!(
+                    "Game {table_id} of user {player} has not timed out yet"
+                ));
+            }
+            return self.stand(player, table_id, seed);
+        }
+
+        let Some(table) = self.shared_tables.get(&table_id) else {
+            return Err("Table not setup. Start a new game first".to_string());
+        };
+        if !matches!(table.phase, SharedTablePhase::Playing(_))
+            || !table.is_abandoned(block_height, timeout_blocks)
+        {
+            return Err(format!(
+                "Hand of shared table {table_id} has not timed out yet"
+            ));
+        }
+        // Fails unless it is the player's turn
+        self.shared_stand(player, table_id, seed)
+    }
+
+    /// Garbage-collects the state. Only the operator may call it.
+    ///
+    /// Finished tables and empty balances are removed. When `expire_after` is set,
+    /// ongoing tables started more than `expire_after` blocks ago are considered
+    /// abandoned: their bet is refunded to the player's Oranj balance and the table
+    /// is removed. Shared tables are expired the same way, refunding every seat.
+    /// `expire_after` can't be shorter than `game_timeout_blocks`, so that the operator
+    /// can't refund hands that are still being played.
+    pub fn clean(
+        &mut self,
+        user: &Identity,
+        block_height: u64,
+        expire_after: Option<u64>,
+    ) -> Result<String, String> {
+        if self.config.operator.as_ref() != Some(user) {
+            return Err("Only the operator can clean the state".to_string());
+        }
+
+        let expire_after = match expire_after {
+            Some(expire_after) => match self.config.game_timeout_blocks {
+                Some(timeout_blocks) if expire_after >= timeout_blocks => Some(expire_after),
+                Some(timeout_blocks) => {
+                    return Err(format!(
+                        "Games can only be expired after {timeout_blocks} blocks"
+                    ))
+                }
+                None => return Err("Expiring games requires a game timeout".to_string()),
+            },
+            None => None,
+        };
+
+        let mut expired = 0;
+        if let Some(expire_after) = expire_after {
+            let abandoned: Vec<(Identity, TableId)> = self
+                .tables
+                .iter()
+                .flat_map(|(player, tables)| {
+                    tables
+                        .iter()
+                        .filter(|(_, table)| table.is_abandoned(block_height, expire_after))
+                        .map(|(table_id, _)| (player.clone(), *table_id))
+                })
+                .collect();
+
+            for (player, table_id) in abandoned {
+                let removed = self
+                    .tables
+                    .get_mut(&player)
+                    .and_then(|tables| tables.remove(&table_id));
+                if let Some(table) = removed {
+                    // Refund the escrowed bet in Oranj tokens
+                    let balance = self.oranj_balances.entry(player).or_default();
+                    *balance = balance
+                        .checked_add(table.bet)
+                        .ok_or_else(|| "Balance overflow".to_string())?;
+                    expired += 1;
+                }
+            }
+
+            let abandoned: Vec<TableId> = self
+                .shared_tables
+                .iter()
+                .filter(|(_, table)| table.is_abandoned(block_height, expire_after))
+                .map(|(table_id, _)| *table_id)
+                .collect();
+
+            for table_id in abandoned {
+                if let Some(table) = self.shared_tables.remove(&table_id) {
+                    for seat in table.seats {
+                        if matches!(
+                            seat.state,
+                            SeatState::Won | SeatState::Lost | SeatState::Push
+                        ) {
+                            continue;
+                        }
+                        let balance = self.oranj_balances.entry(seat.player).or_default();
+                        *balance = balance
+                            .checked_add(seat.bet)
+                            .ok_or_else(|| "Balance overflow".to_string())?;
+                    }
+                    expired += 1;
+                }
+            }
+        }
+
+        // Remove all finished tables and balances that are 0
+        for tables in self.tables.values_mut() {
+            tables.retain(|_, table| matches!(table.state, TableState::Ongoing));
+        }
+        self.tables.retain(|_, tables| !tables.is_empty());
+        self.shared_tables.retain(|_, table| table.is_ongoing());
+        self.oranj_balances.retain(|_, &mut balance| balance > 0);
+        self.vitamin_balances.retain(|_, &mut balance| balance > 0);
+
+        Ok(format!(
+            "Cleaned state, refunded {} abandoned tables",
+            expired
+        ))
+    }
+
     pub fn claim(
         &mut self,
         amount: u32,
@@ -579,10 +986,8 @@ impl BlackJack {
         ctx: &mut ExecutionContext,
     ) -> Result<String, String> {
         // Check if user has an ongoing game
-        if let Some(table) = self.tables.get(user) {
-            if matches!(table.state, TableState::Ongoing) {
-                return Err("Cannot withdraw while a game is in progress".to_string());
-            }
+        if self.has_ongoing_game(user) {
+            return Err("Cannot withdraw while a game is in progress".to_string());
         }
 
         match token.as_str() {
@@ -596,7 +1001,7 @@ impl BlackJack {
                 ctx.is_in_callee_blobs(
                     &"oranj".into(),
                     SmtTokenAction::Transfer {
-                        sender: "blackjack".into(),
+                        sender: ctx.contract_name.0.clone().into(),
                         recipient: user.clone(),
                         amount: amount as u128,
                     },
@@ -619,7 +1024,7 @@ impl BlackJack {
                 ctx.is_in_callee_blobs(
                     &"vitamin".into(),
                     SmtTokenAction::Transfer {
-                        sender: "blackjack".into(),
+                        sender: ctx.contract_name.0.clone().into(),
                         recipient: user.clone(),
                         amount: amount as u128,
                     },
@@ -652,3 +1057,329 @@ fn test_compute_scoress() {
     assert_eq!(BlackJack::compute_score(&[1, 2, 3, 4, 10]), 20);
     assert_eq!(BlackJack::compute_score(&[1, 2, 8, 3, 4, 10]), 28);
 }
+
+#[test]
+fn test_clean_keeps_ongoing_tables() {
+    let operator: Identity = "operator@wallet".into();
+    let player: Identity = "player@wallet".into();
+    let finished: Identity = "finished@wallet".into();
+    let mut blackjack = BlackJack::new(BlackJackConfig {
+        operator: Some(operator.clone()),
+        game_timeout_blocks: Some(50),
+    });
+    blackjack.tables.entry(player.clone()).or_default().insert(
+        0,
+        Table {
+            bet: 10,
+            started_at: 100,
+            ..Default::default()
+        },
+    );
+    blackjack
+        .tables
+        .entry(finished.clone())
+        .or_default()
+        .insert(
+            1,
+            Table {
+                bet: 10,
+                state: TableState::Lost,
+                ..Default::default()
+            },
+        );
+
+    assert!(blackjack.clean(&player, 200, None).is_err());
+
+    blackjack.clean(&operator, 200, None).unwrap();
+    assert!(blackjack.tables.contains_key(&player));
+    assert!(!blackjack.tables.contains_key(&finished));
+
+    // Hands can't be expired before they time out
+    assert!(blackjack.clean(&operator, 200, Some(0)).is_err());
+    assert!(blackjack.tables.contains_key(&player));
+
+    blackjack.clean(&operator, 200, Some(50)).unwrap();
+    assert!(blackjack.tables.is_empty());
+    assert_eq!(blackjack.oranj_balances.get(&player), Some(&10));
+}
+
+#[test]
+fn test_auto_stand_after_timeout() {
+    let player: Identity = "player@wallet".into();
+    let mut blackjack = BlackJack::new(BlackJackConfig {
+        game_timeout_blocks: Some(10),
+        ..Default::default()
+    });
+    blackjack.tables.entry(player.clone()).or_default().insert(
+        0,
+        Table {
+            bank: vec![10, 7],
+            user: vec![10, 9],
+            bet: 10,
+            started_at: 100,
+            ..Default::default()
+        },
+    );
+
+    let seed = BlockHash::default();
+    assert!(blackjack.auto_stand(&player, 0, 105, &seed).is_err());
+    blackjack.auto_stand(&player, 0, 111, &seed).unwrap();
+    assert!(matches!(
+        blackjack.get_table(&player, 0).unwrap().state,
+        TableState::Won
+    ));
+}
+
+#[test]
+fn test_shared_hand_times_out_after_the_deal() {
+    let operator: Identity = "operator@wallet".into();
+    let alice: Identity = "alice@wallet".into();
+    let bob: Identity = "bob@wallet".into();
+    let mut blackjack = BlackJack::new(BlackJackConfig {
+        operator: Some(operator.clone()),
+        game_timeout_blocks: Some(10),
+    });
+    let seat = |player: &Identity, cards: Vec<u32>| Seat {
+        player: player.clone(),
+        cards,
+        bet: 10,
+        state: SeatState::Playing,
+    };
+    // Opened long before the cards were dealt
+    blackjack.shared_tables.insert(
+        0,
+        SharedTable {
+            bank: alloc::vec![10, 6],
+            seats: alloc::vec![
+                seat(&alice, alloc::vec![10, 5]),
+                seat(&bob, alloc::vec![9, 8])
+            ],
+            phase: SharedTablePhase::Playing(0),
+            started_at: 1,
+            dealt_at: 100,
+        },
+    );
+
+    blackjack.clean(&operator, 105, Some(10)).unwrap();
+    assert!(blackjack.shared_tables.contains_key(&0));
+
+    let seed = BlockHash::default();
+    assert!(blackjack.auto_stand(&alice, 0, 105, &seed).is_err());
+    // Only the seat whose turn it is can be stood for
+    assert!(blackjack.auto_stand(&bob, 0, 111, &seed).is_err());
+    blackjack.auto_stand(&alice, 0, 111, &seed).unwrap();
+    assert!(matches!(
+        blackjack.shared_tables[&0].phase,
+        SharedTablePhase::Playing(1)
+    ));
+    blackjack.auto_stand(&bob, 0, 111, &seed).unwrap();
+    assert!(matches!(
+        blackjack.shared_tables[&0].phase,
+        SharedTablePhase::Finished
+    ));
+}
+
+#[test]
+fn test_shared_table_turns() {
+    let alice: Identity = "alice@wallet".into();
+    let bob: Identity = "bob@wallet".into();
+    let blockhash = BlockHash::default();
+    let mut blackjack = BlackJack::default();
+    blackjack.oranj_balances.insert(alice.clone(), 100);
+    blackjack.oranj_balances.insert(bob.clone(), 100);
+
+    blackjack.open_shared_table(1).unwrap();
+    blackjack.join_seat(&alice, 0, 10).unwrap();
+    blackjack.join_seat(&bob, 0, 10).unwrap();
+    assert!(blackjack.join_seat(&bob, 0, 10).is_err());
+    assert!(blackjack.has_ongoing_game(&alice));
+
+    blackjack
+        .deal_shared_table(&alice, 0, &blockhash, 1)
+        .unwrap();
+    assert!(blackjack.join_seat(&alice, 0, 10).is_err());
+
+    while let SharedTablePhase::Playing(turn) = blackjack.shared_tables[&0].phase {
+        let player = blackjack.shared_tables[&0].seats[turn as usize]
+            .player
+            .clone();
+        let other = if player == alice { &bob } else { &alice };
+        assert!(blackjack.shared_stand(other, 0, &blockhash).is_err());
+        blackjack.shared_stand(&player, 0, &blockhash).unwrap();
+    }
+
+    let table = &blackjack.shared_tables[&0];
+    assert!(matches!(table.phase, SharedTablePhase::Finished));
+    assert!(table.seats.iter().all(|seat| matches!(
+        seat.state,
+        SeatState::Won | SeatState::Lost | SeatState::Push
+    )));
+    assert!(!blackjack.has_ongoing_game(&alice));
+}
+
+#[test]
+fn test_shared_table_naturals() {
+    let alice: Identity = "alice@wallet".into();
+    let bob: Identity = "bob@wallet".into();
+    // Alice holds a natural, Bob made 21 with three cards
+    let settle = |bank: Vec<u32>| {
+        let mut blackjack = BlackJack::default();
+        let seat = |player: &Identity, cards: Vec<u32>, state| Seat {
+            player: player.clone(),
+            cards,
+            bet: 10,
+            state,
+        };
+        blackjack.shared_tables.insert(
+            0,
+            SharedTable {
+                bank,
+                seats: alloc::vec![
+                    seat(&alice, alloc::vec![1, 13], SeatState::Stood),
+                    seat(&bob, alloc::vec![10, 5, 6], SeatState::Playing),
+                ],
+                phase: SharedTablePhase::Playing(1),
+                started_at: 1,
+                dealt_at: 1,
+            },
+        );
+        blackjack
+            .shared_stand(&bob, 0, &BlockHash::default())
+            .unwrap();
+        let states: Vec<SeatState> = blackjack.shared_tables[&0]
+            .seats
+            .iter()
+            .map(|seat| seat.state.clone())
+            .collect();
+        (states, blackjack)
+    };
+
+    // The dealer makes 21 with three cards
+    let (states, blackjack) = settle(alloc::vec![10, 4, 7]);
+    assert_eq!(states, alloc::vec![SeatState::Won, SeatState::Push]);
+    assert_eq!(blackjack.vitamin_balances.get(&alice), Some(&10));
+    assert_eq!(blackjack.oranj_balances.get(&bob), Some(&10));
+
+    // The dealer holds a natural
+    let (states, blackjack) = settle(alloc::vec![1, 10]);
+    assert_eq!(states, alloc::vec![SeatState::Push, SeatState::Lost]);
+    assert_eq!(blackjack.oranj_balances.get(&alice), Some(&10));
+    assert_eq!(blackjack.oranj_balances.get(&bob), None);
+}
+
+#[test]
+fn test_batch_is_atomic() {
+    use client_sdk::transaction_builder::TxExecutorHandler;
+
+    let player: Identity = "player@wallet".into();
+    let mut blackjack = BlackJack::default();
+    blackjack.oranj_balances.insert(player.clone(), 100);
+    let calldata = |actions: Vec<BatchableAction>| Calldata {
+        identity: player.clone(),
+        index: BlobIndex(0),
+        blobs: alloc::vec![BlackJackAction::Batch(actions).as_blob("blackjack".into(), None, None)]
+            .into(),
+        tx_blob_count: 1,
+        tx_hash: sdk::TxHash::new("batch"),
+        tx_ctx: Some(TxContext::default()),
+        private_input: alloc::vec![],
+    };
+
+    let failing = calldata(alloc::vec![
+        BatchableAction::Init(10),
+        BatchableAction::Stand(42)
+    ]);
+    let output = blackjack.handle(&failing).unwrap();
+    assert!(!output.success);
+    assert_eq!(output.next_state, output.initial_state);
+    assert!(blackjack.tables.is_empty());
+    assert_eq!(blackjack.oranj_balances.get(&player), Some(&100));
+
+    let batch = calldata(alloc::vec![
+        BatchableAction::OpenSharedTable,
+        BatchableAction::Init(10),
+        BatchableAction::Hit(1),
+    ]);
+    assert!(blackjack.handle(&batch).unwrap().success);
+    assert_eq!(blackjack.shared_tables.len(), 1);
+    assert!(blackjack.get_table(&player, 1).is_some());
+}
+
+#[test]
+fn test_batched_hits_draw_different_cards() {
+    use client_sdk::transaction_builder::TxExecutorHandler;
+
+    let player: Identity = "player@wallet".into();
+    let batch = BlackJackAction::Batch(alloc::vec![
+        BatchableAction::Init(10),
+        BatchableAction::Hit(0),
+        BatchableAction::Hit(0),
+    ]);
+    // The hits of a batch share the seed of its transaction
+    let mut played = 0;
+    let mut differ = false;
+    for i in 0..32 {
+        let mut blackjack = BlackJack::default();
+        blackjack.oranj_balances.insert(player.clone(), 100);
+        let calldata = Calldata {
+            identity: player.clone(),
+            index: BlobIndex(0),
+            blobs: alloc::vec![batch.as_blob("blackjack".into(), None, None)].into(),
+            tx_blob_count: 1,
+            tx_hash: sdk::TxHash::new(&format!("batch {i}")),
+            tx_ctx: Some(TxContext::default()),
+            private_input: alloc::vec![],
+        };
+        // Fails when the deal is a natural, or the first hit busts
+        if !blackjack.handle(&calldata).unwrap().success {
+            continue;
+        }
+        let user = &blackjack.get_table(&player, 0).unwrap().user;
+        assert_eq!(user.len(), 4);
+        played += 1;
+        differ |= user[2] != user[3];
+    }
+    assert!(played > 0);
+    assert!(differ);
+}
+
+#[test]
+fn test_tables_of_a_block_draw_different_cards() {
+    let player: Identity = "player@wallet".into();
+    let mut blackjack = BlackJack::default();
+    blackjack.oranj_balances.insert(player.clone(), 1_000);
+    let block_hash = BlockHash::default();
+
+    // Tables of one transaction, then of two transactions of the same block
+    let seed = tx_seed(&block_hash, &TxHash::new("a"));
+    let mut deals = Vec::new();
+    for seed in [seed.clone(), seed, tx_seed(&block_hash, &TxHash::new("b"))] {
+        let table_id = blackjack.next_table_id;
+        blackjack.new_game(&player, &seed, 1, 10).unwrap();
+        let table = blackjack.get_table(&player, table_id).unwrap();
+        deals.push((table.user.clone(), table.bank.clone()));
+    }
+    assert_ne!(deals[0], deals[1]);
+    assert_ne!(deals[0], deals[2]);
+}
+
+#[test]
+fn test_played_table() {
+    let player: Identity = "player@wallet".into();
+    let mut blackjack = BlackJack::default();
+    blackjack.oranj_balances.insert(player.clone(), 100);
+    assert_eq!(blackjack.played_table(&BlackJackAction::Init(10)), None);
+
+    blackjack.open_shared_table(1).unwrap();
+    blackjack
+        .new_game(&player, &BlockHash::default(), 1, 10)
+        .unwrap();
+    assert_eq!(blackjack.played_table(&BlackJackAction::Init(10)), Some(1));
+    let batch = BlackJackAction::Batch(alloc::vec![
+        BatchableAction::OpenSharedTable,
+        BatchableAction::Init(10)
+    ]);
+    assert_eq!(blackjack.played_table(&batch), Some(1));
+    assert_eq!(blackjack.played_table(&BlackJackAction::Hit(0)), Some(0));
+    assert_eq!(blackjack.played_table(&BlackJackAction::Deposit(10)), None);
+}
@@ -0,0 +1,405 @@
+use core::hash::Hasher;
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use rand_seeder::{SipHasher, SipRng};
+use sdk::{BlockHash, Identity};
+use serde::{Deserialize, Serialize};
+
+use crate::{BlackJack, TableId};
+
+/// Maximum number of players sitting at a shared table
+pub const MAX_SEATS: usize = 7;
+
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default, PartialEq,
+)]
+pub enum SeatState {
+    /// Joined, waiting for the cards to be dealt
+    #[default]
+    Waiting,
+    Playing,
+    Stood,
+    Won,
+    Lost,
+    Push,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Seat {
+    pub player: Identity,
+    pub cards: Vec<u32>,
+    pub bet: u32,
+    pub state: SeatState,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
+pub enum SharedTablePhase {
+    /// Players can join and leave
+    #[default]
+    Betting,
+    /// Cards are dealt, the seat at this index is playing
+    Playing(u32),
+    /// The dealer has played and every seat is settled
+    Finished,
+}
+
+/// A table where several players play against a single dealer hand
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SharedTable {
+    pub bank: Vec<u32>,
+    pub seats: Vec<Seat>,
+    pub phase: SharedTablePhase,
+    /// Block height at which the table was opened
+    pub started_at: u64,
+    /// Block height at which the cards of the hand were dealt
+    pub dealt_at: u64,
+}
+
+impl SharedTable {
+    pub fn seat_of(&self, player: &Identity) -> Option<usize> {
+        self.seats.iter().position(|seat| &seat.player == player)
+    }
+
+    /// Whether the table holds bets that are not settled yet
+    pub fn is_ongoing(&self) -> bool {
+        !matches!(self.phase, SharedTablePhase::Finished) && !self.seats.is_empty()
+    }
+
+    /// Whether `player` has a bet that is not settled yet at this table
+    pub fn has_ongoing_seat(&self, player: &Identity) -> bool {
+        !matches!(self.phase, SharedTablePhase::Finished) && self.seat_of(player).is_some()
+    }
+
+    /// Whether the hand is still not settled more than `timeout_blocks` blocks after the
+    /// cards were dealt, or after the table was opened when they were not dealt yet
+    pub fn is_abandoned(&self, block_height: u64, timeout_blocks: u64) -> bool {
+        let since = match self.phase {
+            SharedTablePhase::Betting => self.started_at,
+            SharedTablePhase::Playing(_) => self.dealt_at,
+            SharedTablePhase::Finished => return false,
+        };
+        !self.seats.is_empty() && block_height.saturating_sub(since) > timeout_blocks
+    }
+
+    fn next_turn(&self, from: usize) -> Option<usize> {
+        (from..self.seats.len()).find(|&i| self.seats[i].state == SeatState::Playing)
+    }
+}
+
+impl BlackJack {
+    /// Generator of the cards drawn by an action at a shared table. The number of cards
+    /// already dealt tells apart the actions played at the table with the same seed.
+    fn shared_rng(seed: &BlockHash, table_id: TableId, table: &SharedTable) -> SipRng {
+        let dealt = table.bank.len()
+            + table
+                .seats
+                .iter()
+                .map(|seat| seat.cards.len())
+                .sum::<usize>();
+        let mut hasher = SipHasher::new();
+        hasher.write(seed.0.as_bytes());
+        hasher.write_u32(table_id);
+        hasher.write_usize(dealt);
+        hasher.into_rng()
+    }
+
+    /// Credits a settled seat: the bet comes back in Oranj tokens on a win or a push,
+    /// and a win is awarded the same amount in Vitamin tokens.
+    fn pay_out(&mut self, player: &Identity, bet: u32, state: &SeatState) -> Result<(), String> {
+        if matches!(state, SeatState::Won | SeatState::Push) {
+            credit(&mut self.oranj_balances, player, bet)?;
+        }
+        if matches!(state, SeatState::Won) {
+            credit(&mut self.vitamin_balances, player, bet)?;
+        }
+        Ok(())
+    }
+
+    pub fn open_shared_table(&mut self, block_height: u64) -> Result<String, String> {
+        let table_id = self.next_table_id;
+        self.next_table_id = table_id
+            .checked_add(1)
+            .ok_or_else(|| "Table id overflow".to_string())?;
+
+        self.shared_tables.insert(
+            table_id,
+            SharedTable {
+                started_at: block_height,
+                ..Default::default()
+            },
+        );
+        Ok(format!("Opened shared table {table_id}"))
+    }
+
+    pub fn join_seat(
+        &mut self,
+        user: &Identity,
+        table_id: TableId,
+        bet: u32,
+    ) -> Result<String, String> {
+        let Some(table) = self.shared_tables.get_mut(&table_id) else {
+            return Err(format!("Shared table {table_id} not found"));
+        };
+        if !matches!(table.phase, SharedTablePhase::Betting) {
+            return Err("Cannot join a shared table once the cards are dealt".to_string());
+        }
+        if table.seat_of(user).is_some() {
+            return Err(format!("User {user} is already seated at table {table_id}"));
+        }
+        if table.seats.len() >= MAX_SEATS {
+            return Err(format!("Shared table {table_id} is full"));
+        }
+        if bet < 10 {
+            return Err("Minimum bet is 10".to_string());
+        }
+
+        let balance = self.oranj_balances.get(user).copied().unwrap_or(0);
+        if balance < bet {
+            return Err(format!(
+                "Insufficient balance. You have {} but bet is {}",
+                balance, bet
+            ));
+        }
+        self.oranj_balances.insert(user.clone(), balance - bet);
+
+        table.seats.push(Seat {
+            player: user.clone(),
+            bet,
+            ..Default::default()
+        });
+        Ok(format!(
+            "User {user} joined shared table {table_id} with a bet of {bet}"
+        ))
+    }
+
+    pub fn leave_seat(&mut self, user: &Identity, table_id: TableId) -> Result<String, String> {
+        let Some(table) = self.shared_tables.get_mut(&table_id) else {
+            return Err(format!("Shared table {table_id} not found"));
+        };
+        let Some(index) = table.seat_of(user) else {
+            return Err(format!("User {user} is not seated at table {table_id}"));
+        };
+
+        match table.phase {
+            SharedTablePhase::Betting => {
+                // Nothing was dealt yet, the bet is refunded
+                let seat = table.seats.remove(index);
+                credit(&mut self.oranj_balances, user, seat.bet)?;
+                Ok(format!(
+                    "User {user} left shared table {table_id}, bet refunded"
+                ))
+            }
+            SharedTablePhase::Playing(_) => {
+                Err("Cannot leave a shared table while the hand is being played".to_string())
+            }
+            SharedTablePhase::Finished => {
+                table.seats.remove(index);
+                Ok(format!("User {user} left shared table {table_id}"))
+            }
+        }
+    }
+
+    pub fn deal_shared_table(
+        &mut self,
+        user: &Identity,
+        table_id: TableId,
+        seed: &BlockHash,
+        block_height: u64,
+    ) -> Result<String, String> {
+        let Some(table) = self.shared_tables.get_mut(&table_id) else {
+            return Err(format!("Shared table {table_id} not found"));
+        };
+        if !matches!(table.phase, SharedTablePhase::Betting) {
+            return Err(format!("Cards already dealt at table {table_id}"));
+        }
+        if table.seat_of(user).is_none() {
+            return Err("Only a seated player can deal the cards".to_string());
+        }
+        table.dealt_at = block_height;
+
+        let mut rnd = Self::shared_rng(seed, table_id, table);
+        for seat in table.seats.iter_mut() {
+            seat.cards.push(Self::pick_random_card(&mut rnd));
+            seat.cards.push(Self::pick_random_card(&mut rnd));
+            // A natural blackjack has nothing left to play
+            seat.state = if Self::compute_score(&seat.cards) == 21 {
+                SeatState::Stood
+            } else {
+                SeatState::Playing
+            };
+        }
+        table.bank.push(Self::pick_random_card(&mut rnd));
+        table.bank.push(Self::pick_random_card(&mut rnd));
+
+        let first_turn = if Self::compute_score(&table.bank) == 21 {
+            // The dealer made 21, no one gets to play
+            None
+        } else {
+            table.next_turn(0)
+        };
+
+        match first_turn {
+            Some(turn) => {
+                table.phase = SharedTablePhase::Playing(turn as u32);
+                Ok(format!(
+                    "Dealt shared table {table_id}, {} to play",
+                    table.seats[turn].player
+                ))
+            }
+            None => {
+                self.resolve_shared_table(table_id, &mut rnd)?;
+                Ok(format!("Dealt shared table {table_id}, hand is over"))
+            }
+        }
+    }
+
+    pub fn shared_hit(
+        &mut self,
+        user: &Identity,
+        table_id: TableId,
+        seed: &BlockHash,
+    ) -> Result<String, String> {
+        let turn = self.check_turn(user, table_id)?;
+        let Some(table) = self.shared_tables.get_mut(&table_id) else {
+            return Err(format!("Shared table {table_id} not found"));
+        };
+        let mut rnd = Self::shared_rng(seed, table_id, table);
+
+        let seat = &mut table.seats[turn];
+        seat.cards.push(Self::pick_random_card(&mut rnd));
+        let score = Self::compute_score(&seat.cards);
+        if score > 21 {
+            seat.state = SeatState::Lost;
+        } else if score == 21 {
+            seat.state = SeatState::Stood;
+        }
+
+        let outcome = self.advance_shared_table(table_id, turn, &mut rnd)?;
+        Ok(format!(
+            "Hit for user {user} at shared table {table_id}, {outcome}"
+        ))
+    }
+
+    pub fn shared_stand(
+        &mut self,
+        user: &Identity,
+        table_id: TableId,
+        seed: &BlockHash,
+    ) -> Result<String, String> {
+        let turn = self.check_turn(user, table_id)?;
+        let Some(table) = self.shared_tables.get_mut(&table_id) else {
+            return Err(format!("Shared table {table_id} not found"));
+        };
+        let mut rnd = Self::shared_rng(seed, table_id, table);
+        table.seats[turn].state = SeatState::Stood;
+
+        let outcome = self.advance_shared_table(table_id, turn, &mut rnd)?;
+        Ok(format!(
+            "Stand for user {user} at shared table {table_id}, {outcome}"
+        ))
+    }
+
+    /// Returns the index of the user's seat if it is their turn to play
+    fn check_turn(&self, user: &Identity, table_id: TableId) -> Result<usize, String> {
+        let Some(table) = self.shared_tables.get(&table_id) else {
+            return Err(format!("Shared table {table_id} not found"));
+        };
+        let SharedTablePhase::Playing(turn) = table.phase else {
+            return Err(format!("No hand is being played at table {table_id}"));
+        };
+        let turn = turn as usize;
+        if table.seats.get(turn).map(|seat| &seat.player) != Some(user) {
+            return Err(format!("Not the turn of user {user} at table {table_id}"));
+        }
+        Ok(turn)
+    }
+
+    /// Moves the turn past the seat at `from` once it stopped playing, and lets the
+    /// dealer play when no seat is left.
+    fn advance_shared_table(
+        &mut self,
+        table_id: TableId,
+        from: usize,
+        rnd: &mut SipRng,
+    ) -> Result<String, String> {
+        let Some(table) = self.shared_tables.get_mut(&table_id) else {
+            return Err(format!("Shared table {table_id} not found"));
+        };
+        if table.seats[from].state == SeatState::Playing {
+            return Ok("still playing".to_string());
+        }
+
+        match table.next_turn(from + 1) {
+            Some(turn) => {
+                table.phase = SharedTablePhase::Playing(turn as u32);
+                Ok(format!("{} to play", table.seats[turn].player))
+            }
+            None => {
+                self.resolve_shared_table(table_id, rnd)?;
+                Ok("hand is over".to_string())
+            }
+        }
+    }
+
+    /// The dealer draws until their score is over 16, then every seat still in the
+    /// hand is settled against the dealer.
+    fn resolve_shared_table(&mut self, table_id: TableId, rnd: &mut SipRng) -> Result<(), String> {
+        let Some(table) = self.shared_tables.get_mut(&table_id) else {
+            return Err(format!("Shared table {table_id} not found"));
+        };
+
+        while Self::compute_score(&table.bank) <= 16 {
+            table.bank.push(Self::pick_random_card(rnd));
+        }
+        let bank_score = Self::compute_score(&table.bank);
+        let bank_natural = bank_score == 21 && table.bank.len() == 2;
+
+        let mut payouts = Vec::new();
+        for seat in table.seats.iter_mut() {
+            if !matches!(seat.state, SeatState::Playing | SeatState::Stood) {
+                continue;
+            }
+            let score = Self::compute_score(&seat.cards);
+            let natural = score == 21 && seat.cards.len() == 2;
+            // A natural beats any other 21, and only ties with the dealer's natural
+            seat.state = if natural && bank_natural {
+                SeatState::Push
+            } else if natural {
+                SeatState::Won
+            } else if bank_natural {
+                SeatState::Lost
+            } else if bank_score > 21 || score > bank_score {
+                SeatState::Won
+            } else if score == bank_score {
+                SeatState::Push
+            } else {
+                SeatState::Lost
+            };
+            payouts.push((seat.player.clone(), seat.bet, seat.state.clone()));
+        }
+        table.phase = SharedTablePhase::Finished;
+
+        for (player, bet, state) in payouts {
+            self.pay_out(&player, bet, &state)?;
+        }
+        Ok(())
+    }
+}
+
+fn credit(
+    balances: &mut BTreeMap<Identity, u32>,
+    player: &Identity,
+    amount: u32,
+) -> Result<(), String> {
+    let balance = balances.entry(player.clone()).or_default();
+    *balance = balance
+        .checked_add(amount)
+        .ok_or_else(|| "Balance overflow".to_string())?;
+    Ok(())
+}
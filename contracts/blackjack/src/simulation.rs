@@ -0,0 +1,345 @@
+//! Monte-Carlo simulation of solo hands, played through the contract's own `new_game`, `hit`,
+//! `stand` and `double_down`, to measure the return to player of its rules.
+//!
+//! Each hand is played by a fresh identity with random block hashes, so the cards are drawn
+//! as they would be on-chain. Vitamin tokens awarded on a win are valued at par with the
+//! oranj tokens bet.
+//!
+//! Payouts are a setting of the simulation, to price other payouts before changing the
+//! contract: they do not change how a hand is played, so the hands won by the contract are
+//! paid again with them. The dealer rule is the contract's own, drawing to 16 and standing
+//! on every 17, as simulating another one would mean playing the bank outside the contract.
+
+use core::hash::Hasher;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use rand::Rng;
+use rand_seeder::{SipHasher, SipRng};
+use sdk::{BlockHash, ConsensusProposalHash, Identity};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    advisor::{Advice, StrategyChart},
+    BlackJack, BlackJackConfig, TableId, TableState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Hit,
+    Stand,
+    DoubleDown,
+}
+
+/// Picks the player's next action on an ongoing hand
+pub trait Strategy {
+    fn name(&self) -> String;
+
+    /// `user` holds the player's cards, `dealer_up` the first card of the bank
+    fn decide(&mut self, user: &[u32], dealer_up: u32) -> Decision;
+}
+
+/// Plays the advice of a strategy chart
+#[derive(Debug, Clone, Default)]
+pub struct BasicStrategy {
+    pub chart: StrategyChart,
+}
+
+impl Strategy for BasicStrategy {
+    fn name(&self) -> String {
+        "basic".into()
+    }
+
+    fn decide(&mut self, user: &[u32], dealer_up: u32) -> Decision {
+        match self.chart.advise(user, dealer_up) {
+            Advice::Stand => Decision::Stand,
+            Advice::DoubleDown => Decision::DoubleDown,
+            Advice::Hit => Decision::Hit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlwaysStand;
+
+impl Strategy for AlwaysStand {
+    fn name(&self) -> String {
+        "always-stand".into()
+    }
+
+    fn decide(&mut self, _user: &[u32], _dealer_up: u32) -> Decision {
+        Decision::Stand
+    }
+}
+
+/// Picks any of the three actions uniformly
+pub struct RandomStrategy {
+    rnd: SipRng,
+}
+
+impl RandomStrategy {
+    pub fn new(seed: u64) -> Self {
+        RandomStrategy {
+            rnd: seeded_rng(seed),
+        }
+    }
+}
+
+impl Strategy for RandomStrategy {
+    fn name(&self) -> String {
+        "random".into()
+    }
+
+    fn decide(&mut self, _user: &[u32], _dealer_up: u32) -> Decision {
+        match self.rnd.random_range(0..3) {
+            0 => Decision::Hit,
+            1 => Decision::Stand,
+            _ => Decision::DoubleDown,
+        }
+    }
+}
+
+/// Winnings paid on top of the returned bet, in basis points of the bet
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Payouts {
+    /// Hand won on the initial deal
+    pub blackjack_bps: u32,
+    /// Any other hand won
+    pub win_bps: u32,
+}
+
+impl Payouts {
+    /// The contract pays every win 1:1
+    pub const CONTRACT: Payouts = Payouts {
+        blackjack_bps: 10_000,
+        win_bps: 10_000,
+    };
+}
+
+impl Default for Payouts {
+    fn default() -> Self {
+        Payouts::CONTRACT
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    pub hands: u64,
+    pub bet: u32,
+    pub seed: u64,
+    /// Settings of the simulated contract
+    pub contract: BlackJackConfig,
+    pub payouts: Payouts,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct OutcomeDistribution {
+    /// Hands won on the initial deal
+    pub blackjacks: u64,
+    pub wins: u64,
+    pub pushes: u64,
+    pub losses: u64,
+    /// Hands that were doubled down, whatever their outcome
+    pub doubled: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SimulationReport {
+    pub strategy: String,
+    pub hands: u64,
+    pub bet: u32,
+    pub payouts: Payouts,
+    /// Tokens bet, including the doubled bets
+    pub wagered: u64,
+    /// Oranj and vitamin tokens paid back to the player
+    pub returned: u64,
+    /// Return to player, `returned / wagered`
+    pub rtp: f64,
+    pub house_edge: f64,
+    /// Mean net result of a hand, in units of the initial bet
+    pub mean: f64,
+    /// Variance of the net result of a hand, in units of the initial bet squared
+    pub variance: f64,
+    pub outcomes: OutcomeDistribution,
+}
+
+impl SimulationReport {
+    pub fn std_dev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+}
+
+fn seeded_rng(seed: u64) -> SipRng {
+    let mut hasher = SipHasher::new();
+    hasher.write_u64(seed);
+    hasher.into_rng()
+}
+
+fn random_block_hash(rnd: &mut SipRng) -> BlockHash {
+    ConsensusProposalHash(format!(
+        "{:016x}{:016x}",
+        rnd.random::<u64>(),
+        rnd.random::<u64>()
+    ))
+}
+
+/// Plays `config.hands` solo hands with the strategy and reports their results
+pub fn simulate(
+    config: &SimulationConfig,
+    strategy: &mut dyn Strategy,
+) -> Result<SimulationReport, String> {
+    let mut rnd = seeded_rng(config.seed);
+    let mut blackjack = BlackJack::new(config.contract.clone());
+    let bet = config.bet as u64;
+    let bankroll = config
+        .bet
+        .checked_mul(2)
+        .ok_or_else(|| "Bet too large to double down".to_string())?;
+
+    let mut outcomes = OutcomeDistribution::default();
+    let mut wagered = 0_u64;
+    let mut returned = 0_u64;
+    // Welford's running mean and variance of the net result
+    let mut mean = 0_f64;
+    let mut m2 = 0_f64;
+
+    for hand in 0..config.hands {
+        let user = Identity(format!("{hand:x}.{}@simulation", config.seed));
+        // Enough to double down
+        blackjack.oranj_balances.insert(user.clone(), bankroll);
+        let table_id: TableId = blackjack.next_table_id;
+        blackjack.new_game(&user, &random_block_hash(&mut rnd), hand, config.bet)?;
+
+        let mut doubled = false;
+        let mut dealt_blackjack = true;
+        while let Some(table) = blackjack.get_table(&user, table_id) {
+            if !matches!(table.state, TableState::Ongoing) {
+                break;
+            }
+            dealt_blackjack = false;
+            let decision = strategy.decide(&table.user, table.bank[0]);
+            match decision {
+                Decision::Hit => blackjack.hit(&user, table_id, &random_block_hash(&mut rnd))?,
+                Decision::Stand => {
+                    blackjack.stand(&user, table_id, &random_block_hash(&mut rnd))?
+                }
+                Decision::DoubleDown => {
+                    doubled = true;
+                    blackjack.double_down(&user, table_id, &random_block_hash(&mut rnd))?
+                }
+            };
+        }
+
+        let table = blackjack
+            .tables
+            .remove(&user)
+            .and_then(|mut tables| tables.remove(&table_id))
+            .ok_or_else(|| format!("Table {table_id} disappeared"))?;
+        let oranj = blackjack.oranj_balances.remove(&user).unwrap_or(0) as u64;
+        let vitamin = blackjack.vitamin_balances.remove(&user).unwrap_or(0) as u64;
+        let staked = table.bet as u64;
+        // The contract pays its winnings in vitamin tokens, the bet itself in oranj
+        let payout_bps = if dealt_blackjack {
+            config.payouts.blackjack_bps
+        } else {
+            config.payouts.win_bps
+        };
+        let winnings = if vitamin > 0 {
+            staked * payout_bps as u64 / 10_000
+        } else {
+            0
+        };
+        let paid = oranj + staked - bet * 2 + winnings;
+
+        wagered += staked;
+        returned += paid;
+        if doubled {
+            outcomes.doubled += 1;
+        }
+        match table.state {
+            TableState::Won if dealt_blackjack => outcomes.blackjacks += 1,
+            TableState::Won if vitamin > 0 => outcomes.wins += 1,
+            TableState::Won => outcomes.pushes += 1,
+            _ => outcomes.losses += 1,
+        }
+
+        let net = (paid as f64 - staked as f64) / bet as f64;
+        let delta = net - mean;
+        mean += delta / (hand + 1) as f64;
+        m2 += delta * (net - mean);
+    }
+
+    let rtp = if wagered == 0 {
+        0.0
+    } else {
+        returned as f64 / wagered as f64
+    };
+    Ok(SimulationReport {
+        strategy: strategy.name(),
+        hands: config.hands,
+        bet: config.bet,
+        payouts: config.payouts,
+        wagered,
+        returned,
+        rtp,
+        house_edge: 1.0 - rtp,
+        mean,
+        variance: if config.hands > 1 {
+            m2 / (config.hands - 1) as f64
+        } else {
+            0.0
+        },
+        outcomes,
+    })
+}
+
+#[test]
+fn test_simulation_accounts_every_hand() {
+    let config = SimulationConfig {
+        hands: 2_000,
+        bet: 10,
+        seed: 42,
+        contract: BlackJackConfig::default(),
+        payouts: Payouts::CONTRACT,
+    };
+
+    let report = simulate(&config, &mut BasicStrategy::default()).unwrap();
+    let outcomes = &report.outcomes;
+    assert_eq!(
+        outcomes.blackjacks + outcomes.wins + outcomes.pushes + outcomes.losses,
+        config.hands
+    );
+    assert!(report.wagered >= config.hands * config.bet as u64);
+    assert!(report.rtp > 0.0 && report.rtp < 2.0);
+    assert!(report.variance > 0.0);
+
+    // Reproducible from its seed
+    assert_eq!(
+        simulate(&config, &mut BasicStrategy::default()).unwrap(),
+        report
+    );
+
+    let stand = simulate(&config, &mut AlwaysStand).unwrap();
+    assert_eq!(stand.outcomes.doubled, 0);
+    assert_eq!(stand.wagered, config.hands * config.bet as u64);
+
+    // Paying blackjacks 3:2 plays the same hands, returning more of the bets
+    let three_to_two = simulate(
+        &SimulationConfig {
+            payouts: Payouts {
+                blackjack_bps: 15_000,
+                ..Payouts::CONTRACT
+            },
+            ..config.clone()
+        },
+        &mut BasicStrategy::default(),
+    )
+    .unwrap();
+    assert_eq!(three_to_two.outcomes, report.outcomes);
+    assert_eq!(
+        three_to_two.returned,
+        report.returned + report.outcomes.blackjacks * config.bet as u64 / 2
+    );
+}
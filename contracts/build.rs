@@ -2,14 +2,23 @@
 #[cfg(any(clippy, not(feature = "build")))]
 fn main() {}
 
-#[cfg(all(feature = "build", not(feature = "blackjack")))]
+#[cfg(all(
+    feature = "build",
+    not(any(feature = "blackjack", feature = "roulette", feature = "dice"))
+))]
 fn main() {
-    compile_error!("When the 'build' feature is enabled, at least one of the following features must also be enabled: all, blackjack.");
+    compile_error!("When the 'build' feature is enabled, at least one of the following features must also be enabled: all, blackjack, roulette, dice.");
 }
 
-#[cfg(all(not(clippy), feature = "build", feature = "blackjack"))]
+#[cfg(all(
+    not(clippy),
+    feature = "build",
+    any(feature = "blackjack", feature = "roulette", feature = "dice")
+))]
 fn main() {
     println!("cargo:rerun-if-changed=blackjack/src");
+    println!("cargo:rerun-if-changed=roulette/src");
+    println!("cargo:rerun-if-changed=dice/src");
     trait CodegenConsts {
         fn codegen_consts(&self) -> String;
     }
@@ -65,6 +74,10 @@ fn main() {
     let methods: Vec<GuestListEntry> = [
         #[cfg(feature = "blackjack")]
         "blackjack",
+        #[cfg(feature = "roulette")]
+        "roulette",
+        #[cfg(feature = "dice")]
+        "dice",
     ]
     .iter()
     .map(|name| {
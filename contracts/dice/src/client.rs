@@ -0,0 +1,273 @@
+use alloc::vec::Vec;
+use anyhow::{anyhow, Context, Result};
+use client_sdk::contract_indexer::{
+    axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router},
+    utoipa::{openapi::OpenApi, ToSchema},
+    utoipa_axum::{router::OpenApiRouter, routes},
+    AppError, ContractHandler, ContractHandlerStore,
+};
+use client_sdk::transaction_builder::TxExecutorHandler;
+use hyle_modules::modules::prover::AutoProverEvent;
+use sdk::{
+    tracing::info, utils::as_hyle_output, Blob, BlobTransaction, Calldata, Hashed,
+    RegisterContractEffect, StateCommitment, TxContext, ZkContract,
+};
+
+use client_sdk::contract_indexer::axum;
+use client_sdk::contract_indexer::utoipa;
+
+use crate::*;
+
+pub mod metadata {
+    pub const DICE_ELF: &[u8] = include_bytes!("../dice.img");
+    pub const PROGRAM_ID: [u8; 32] = sdk::str_to_u8(include_str!("../dice.txt"));
+}
+
+impl TxExecutorHandler for Dice {
+    fn build_commitment_metadata(&self, _blob: &Blob) -> anyhow::Result<Vec<u8>> {
+        borsh::to_vec(self).context("Failed to serialize Dice")
+    }
+
+    fn handle(&mut self, calldata: &Calldata) -> anyhow::Result<sdk::HyleOutput> {
+        let initial_state_commitment = <Self as ZkContract>::commit(self);
+        let mut res = <Self as ZkContract>::execute(self, calldata);
+        let next_state_commitment = <Self as ZkContract>::commit(self);
+        Ok(as_hyle_output(
+            initial_state_commitment,
+            next_state_commitment,
+            calldata,
+            &mut res,
+        ))
+    }
+
+    fn get_state_commitment(&self) -> StateCommitment {
+        <Self as ZkContract>::commit(self)
+    }
+
+    fn construct_state(
+        _register_blob: &RegisterContractEffect,
+        metadata: &Option<Vec<u8>>,
+    ) -> anyhow::Result<Self> {
+        // The house edge is passed as constructor metadata at registration
+        let Some(metadata) = metadata else {
+            return Ok(Self::default());
+        };
+        let config: DiceConfig =
+            borsh::from_slice(metadata).context("Failed to decode Dice config")?;
+        Self::new(config).map_err(|e| anyhow!(e))
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OptimisticDice {
+    pub unsettled_txs: Vec<(BlobTransaction, BlobIndex, TxContext)>,
+}
+
+fn apply_tx_to_state(
+    state: &mut Dice,
+    tx: &BlobTransaction,
+    index: BlobIndex,
+    tx_context: TxContext,
+) -> Result<()> {
+    let calldata = Calldata {
+        identity: tx.identity.clone(),
+        index,
+        blobs: tx.blobs.clone().into(),
+        tx_blob_count: tx.blobs.len(),
+        tx_hash: tx.hashed(),
+        tx_ctx: Some(tx_context),
+        private_input: vec![],
+    };
+
+    let hyle_output = state.handle(&calldata)?;
+    let program_outputs = str::from_utf8(&hyle_output.program_outputs).unwrap_or("no output");
+    // Surfaced as a failed roll to the server waiting on the sequenced transaction
+    if !hyle_output.success {
+        return Err(anyhow!("{}", program_outputs));
+    }
+
+    info!("🚀 Executed dice: {}", program_outputs);
+    Ok(())
+}
+
+impl OptimisticDice {
+    fn compute_optimistic_state(
+        &mut self,
+        mut state: Dice,
+        new_unsettled_tx: Option<(BlobTransaction, BlobIndex, TxContext)>,
+    ) -> Result<Dice> {
+        for (tx, index, tx_context) in &self.unsettled_txs {
+            let _ = apply_tx_to_state(&mut state, tx, *index, tx_context.clone());
+        }
+
+        if let Some(new_unsettled_tx) = new_unsettled_tx {
+            apply_tx_to_state(
+                &mut state,
+                &new_unsettled_tx.0,
+                new_unsettled_tx.1,
+                new_unsettled_tx.2.clone(),
+            )?;
+            self.unsettled_txs.push(new_unsettled_tx);
+        }
+        Ok(state)
+    }
+}
+
+impl ContractHandler<AutoProverEvent<Dice>> for Dice {
+    async fn api(store: ContractHandlerStore<Dice>) -> (Router<()>, OpenApi) {
+        let (router, api) = OpenApiRouter::default()
+            .routes(routes!(get_state))
+            .routes(routes!(get_user_balance))
+            .routes(routes!(get_user_roll))
+            .split_for_parts();
+
+        (router.with_state(store), api)
+    }
+
+    fn handle_transaction_success(
+        &mut self,
+        tx: &BlobTransaction,
+        index: BlobIndex,
+        tx_context: TxContext,
+    ) -> Result<Option<AutoProverEvent<Dice>>> {
+        apply_tx_to_state(self, tx, index, tx_context)
+            .context("Failed to apply transaction to state")?;
+        self.optimistic_state
+            .unsettled_txs
+            .retain(|(t, i, _)| t != tx || *i != index);
+        Ok(None)
+    }
+
+    fn handle_transaction_failed(
+        &mut self,
+        tx: &BlobTransaction,
+        index: BlobIndex,
+        _tx_context: TxContext,
+    ) -> Result<Option<AutoProverEvent<Dice>>> {
+        self.optimistic_state
+            .unsettled_txs
+            .retain(|(t, i, _)| t != tx || *i != index);
+        Ok(None)
+    }
+
+    fn handle_transaction_timeout(
+        &mut self,
+        tx: &BlobTransaction,
+        index: BlobIndex,
+        _tx_context: TxContext,
+    ) -> Result<Option<AutoProverEvent<Dice>>> {
+        self.optimistic_state
+            .unsettled_txs
+            .retain(|(t, i, _)| t != tx || *i != index);
+        Ok(None)
+    }
+
+    fn handle_transaction_sequenced(
+        &mut self,
+        tx: &BlobTransaction,
+        index: BlobIndex,
+        tx_context: TxContext,
+    ) -> Result<Option<AutoProverEvent<Dice>>> {
+        match self
+            .optimistic_state
+            .compute_optimistic_state(self.clone(), Some((tx.clone(), index, tx_context)))
+        {
+            Ok(state) => Ok(Some(AutoProverEvent::SuccessTx(tx.hashed(), state))),
+            Err(e) => Ok(Some(AutoProverEvent::FailedTx(tx.hashed(), e.to_string()))),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/state",
+    tag = "Contract",
+    responses(
+        (status = OK, description = "Get json state of contract")
+    )
+)]
+pub async fn get_state(
+    State(state): State<ContractHandlerStore<Dice>>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    store.state.clone().map(Json).ok_or(AppError(
+        StatusCode::NOT_FOUND,
+        anyhow!("No state found for contract '{}'", store.contract_name),
+    ))
+}
+
+#[derive(Serialize, ToSchema)]
+struct UserBalances {
+    oranj: u32,
+    vitamin: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/{user_id}/balances",
+    tag = "Contract",
+    params(
+        ("user_id" = String, Path, description = "User identity")
+    ),
+    responses(
+        (status = OK, description = "Get user balances, including unsettled rolls", body = UserBalances),
+        (status = NOT_FOUND, description = "No state found")
+    )
+)]
+pub async fn get_user_balance(
+    State(state): State<ContractHandlerStore<Dice>>,
+    axum::extract::Path(user_id): axum::extract::Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut store = state.write().await;
+    let cn = store.contract_name.clone();
+    let dice_state = store.state.as_mut().ok_or(AppError(
+        StatusCode::NOT_FOUND,
+        anyhow!("No state found for contract '{}'", cn),
+    ))?;
+    let state = dice_state
+        .optimistic_state
+        .compute_optimistic_state(dice_state.clone(), None)?;
+
+    let user_identity = Identity(user_id);
+    Ok(Json(UserBalances {
+        oranj: state
+            .oranj_balances
+            .get(&user_identity)
+            .copied()
+            .unwrap_or(0),
+        vitamin: state
+            .vitamin_balances
+            .get(&user_identity)
+            .copied()
+            .unwrap_or(0),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/{user_id}/roll",
+    tag = "Contract",
+    params(
+        ("user_id" = String, Path, description = "User identity")
+    ),
+    responses(
+        (status = OK, description = "Latest settled roll of the user"),
+        (status = NOT_FOUND, description = "No roll found")
+    )
+)]
+pub async fn get_user_roll(
+    State(state): State<ContractHandlerStore<Dice>>,
+    axum::extract::Path(user_id): axum::extract::Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    store
+        .state
+        .as_ref()
+        .and_then(|state| state.rolls.get(&Identity(user_id.clone())))
+        .cloned()
+        .map(Json)
+        .ok_or(AppError(
+            StatusCode::NOT_FOUND,
+            anyhow!("No roll found for user '{}'", user_id),
+        ))
+}
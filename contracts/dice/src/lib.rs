@@ -0,0 +1,452 @@
+extern crate alloc;
+
+use core::hash::Hasher;
+
+use alloc::vec::Vec;
+use alloc::{
+    collections::btree_map::BTreeMap,
+    format,
+    string::{String, ToString},
+};
+use borsh::{io::Error, BorshDeserialize, BorshSerialize};
+use rand::Rng;
+use rand_seeder::SipHasher;
+use sdk::caller::ExecutionContext;
+use sdk::{Blob, BlobData, BlobIndex, Calldata, ContractAction, StructuredBlobData};
+use serde::{Deserialize, Serialize};
+
+use hyle_smt_token::SmtTokenAction;
+use sdk::{BlockHash, ContractName, Identity, RunResult, TxHash};
+
+#[cfg(feature = "client")]
+use crate::client::OptimisticDice;
+
+#[cfg(feature = "client")]
+pub mod client;
+
+impl sdk::FullStateRevert for Dice {}
+
+impl sdk::ZkContract for Dice {
+    /// Entry point of the contract's logic
+    fn execute(&mut self, calldata: &sdk::Calldata) -> RunResult {
+        // Parse contract inputs
+        let (action, mut ctx) = sdk::utils::parse_calldata::<DiceAction>(calldata)?;
+
+        let user = &calldata.identity;
+
+        let Some(tx_ctx) = calldata.tx_ctx.as_ref() else {
+            return Err("Missing tx context necessary for this contract".to_string());
+        };
+
+        // Execute the given action
+        let res = match action {
+            DiceAction::Roll(bet, target, over) => self.roll(
+                user,
+                &tx_ctx.block_hash,
+                &calldata.tx_hash,
+                tx_ctx.block_height.0,
+                bet,
+                target,
+                over,
+            )?,
+            DiceAction::Deposit(amount) => self.claim(amount, user, calldata, &ctx)?,
+            DiceAction::Withdraw(amount, token) => self.withdraw(amount, user, token, &mut ctx)?,
+        };
+
+        Ok((res.into(), ctx, alloc::vec![]))
+    }
+
+    /// The full state is serialized on-chain, as for the blackjack contract.
+    fn commit(&self) -> sdk::StateCommitment {
+        sdk::StateCommitment(self.as_bytes().expect("Failed to encode Dice"))
+    }
+}
+
+pub const MIN_BET: u32 = 10;
+/// Faces of the die, rolls going from 0 to 99
+pub const FACES: u8 = 100;
+/// Win chances offered, in faces out of `FACES`
+pub const MIN_WINNING_FACES: u32 = 1;
+pub const MAX_WINNING_FACES: u32 = 98;
+/// Largest share of the fair payout the house can keep, in basis points
+pub const MAX_HOUSE_EDGE_BPS: u32 = 1_000;
+const BPS: u64 = 10_000;
+
+/// Settings of the contract, given as constructor metadata at registration
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone)]
+pub struct DiceConfig {
+    /// Share of the fair payout kept by the house, in basis points
+    pub house_edge_bps: u32,
+}
+
+impl Default for DiceConfig {
+    fn default() -> Self {
+        DiceConfig {
+            house_edge_bps: 100,
+        }
+    }
+}
+
+impl DiceConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.house_edge_bps > MAX_HOUSE_EDGE_BPS {
+            return Err(format!(
+                "House edge can't be above {MAX_HOUSE_EDGE_BPS} basis points"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Tokens returned for a winning bet, stake included, or `None` when the win chance
+    /// is not offered
+    pub fn payout(&self, bet: u32, target: u8, over: bool) -> Option<u32> {
+        let faces = winning_faces(target, over);
+        if !(MIN_WINNING_FACES..=MAX_WINNING_FACES).contains(&faces) {
+            return None;
+        }
+        let kept = BPS.saturating_sub(self.house_edge_bps as u64);
+        let payout = bet as u64 * FACES as u64 * kept / (faces as u64 * BPS);
+        u32::try_from(payout).ok()
+    }
+}
+
+/// Number of faces that win: the ones above `target` when betting over, below it otherwise
+pub fn winning_faces(target: u8, over: bool) -> u32 {
+    if over {
+        (FACES - 1).saturating_sub(target) as u32
+    } else {
+        target.min(FACES) as u32
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Roll {
+    pub bet: u32,
+    pub target: u8,
+    pub over: bool,
+    pub rolled: u8,
+    pub won: bool,
+    /// Stake returned in Oranj tokens
+    pub returned: u32,
+    /// Winnings, awarded in Vitamin tokens
+    pub winnings: u32,
+    pub block_height: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Dice {
+    pub config: DiceConfig,
+    pub oranj_balances: BTreeMap<Identity, u32>,
+    pub vitamin_balances: BTreeMap<Identity, u32>,
+    /// Latest roll of each player
+    pub rolls: BTreeMap<Identity, Roll>,
+
+    #[cfg(feature = "client")]
+    #[serde(skip)]
+    #[borsh(skip)]
+    pub optimistic_state: OptimisticDice,
+}
+
+/// Enum representing possible calls to the contract functions.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum DiceAction {
+    Roll(u32, u8, bool), // bet, target, over (or under) the target
+    Deposit(u32),
+    Withdraw(u32, String), // amount, token ("oranj" or "vitamin")
+}
+
+impl ContractAction for DiceAction {
+    fn as_blob(
+        &self,
+        contract_name: ContractName,
+        caller: Option<BlobIndex>,
+        callees: Option<Vec<BlobIndex>>,
+    ) -> Blob {
+        Blob {
+            contract_name,
+            data: BlobData::from(StructuredBlobData {
+                caller,
+                callees,
+                parameters: self.clone(),
+            }),
+        }
+    }
+}
+
+impl Dice {
+    pub fn new(config: DiceConfig) -> Result<Self, String> {
+        config.validate()?;
+        Ok(Dice {
+            config,
+            ..Default::default()
+        })
+    }
+
+    pub fn as_bytes(&self) -> Result<Vec<u8>, Error> {
+        borsh::to_vec(self)
+    }
+
+    /// Face the die lands on, drawn from the block hash, the transaction hash and the
+    /// player, so that the rolls of a block land on different faces
+    fn draw(blockhash: &BlockHash, tx_hash: &TxHash, user: &Identity) -> u8 {
+        let mut hasher = SipHasher::new();
+        hasher.write(blockhash.0.as_bytes());
+        hasher.write(tx_hash.0.as_bytes());
+        hasher.write(user.0.as_bytes());
+        let mut rnd = hasher.into_rng();
+        rnd.random_range(0..FACES)
+    }
+
+    pub fn roll(
+        &mut self,
+        user: &Identity,
+        blockhash: &BlockHash,
+        tx_hash: &TxHash,
+        block_height: u64,
+        bet: u32,
+        target: u8,
+        over: bool,
+    ) -> Result<String, String> {
+        if bet < MIN_BET {
+            return Err(format!("Minimum bet is {}", MIN_BET));
+        }
+        let payout = self.config.payout(bet, target, over).ok_or_else(|| {
+            format!(
+                "Win chance must be between {}% and {}%",
+                MIN_WINNING_FACES, MAX_WINNING_FACES
+            )
+        })?;
+
+        let balance = self.oranj_balances.get(user).copied().unwrap_or(0);
+        if balance < bet {
+            return Err(format!(
+                "Insufficient balance. You have {} but bet is {}",
+                balance, bet
+            ));
+        }
+
+        let rolled = Self::draw(blockhash, tx_hash, user);
+        let won = if over {
+            rolled > target
+        } else {
+            rolled < target
+        };
+        let (returned, winnings) = if won {
+            (payout.min(bet), payout.saturating_sub(bet))
+        } else {
+            (0, 0)
+        };
+
+        // The stake comes back in Oranj, winnings are paid in Vitamin
+        let vitamin = self
+            .vitamin_balances
+            .get(user)
+            .copied()
+            .unwrap_or(0)
+            .checked_add(winnings)
+            .ok_or_else(|| "Balance overflow".to_string())?;
+        self.oranj_balances
+            .insert(user.clone(), balance - bet + returned);
+        if winnings > 0 {
+            self.vitamin_balances.insert(user.clone(), vitamin);
+        }
+        self.rolls.insert(
+            user.clone(),
+            Roll {
+                bet,
+                target,
+                over,
+                rolled,
+                won,
+                returned,
+                winnings,
+                block_height,
+            },
+        );
+
+        if won {
+            Ok(format!(
+                "Rolled {}, {} won {} Vitamin tokens",
+                rolled, user, winnings
+            ))
+        } else {
+            Ok(format!(
+                "Rolled {}, {} lost {} Oranj tokens",
+                rolled, user, bet
+            ))
+        }
+    }
+
+    pub fn claim(
+        &mut self,
+        amount: u32,
+        user: &Identity,
+        calldata: &Calldata,
+        ctx: &ExecutionContext,
+    ) -> Result<String, String> {
+        // Find the oranj transfer blob
+        let transfer_blob_index = calldata
+            .blobs
+            .iter()
+            .position(|(_, b)| b.contract_name == ContractName("oranj".to_string()))
+            .ok_or_else(|| "Missing Oranj transfer blob".to_string())?;
+
+        let transfer_action = sdk::utils::parse_structured_blob::<SmtTokenAction>(
+            &calldata.blobs,
+            &sdk::BlobIndex(transfer_blob_index),
+        )
+        .ok_or_else(|| "Failed to decode Oranj transfer action".to_string())?
+        .data
+        .parameters;
+
+        // Verify the transfer is for this contract and extract amount
+        match transfer_action {
+            SmtTokenAction::Transfer {
+                sender,
+                recipient,
+                amount: transfer_amount,
+            } => {
+                if amount as u128 != transfer_amount {
+                    return Err("Transfer amount is not the same as the deposit amount".to_string());
+                }
+                if &sender != user {
+                    return Err("Transfer is not from the user".to_string());
+                }
+                if recipient.0 != ctx.contract_name.0 {
+                    return Err("Transfer is not for the dice contract".to_string());
+                }
+
+                let balance = self.oranj_balances.entry(user.clone()).or_default();
+                *balance = balance
+                    .checked_add(amount)
+                    .ok_or_else(|| "Balance overflow".to_string())?;
+
+                Ok(format!(
+                    "Added {} to balance, new balance is {} for user {}",
+                    amount, balance, user
+                ))
+            }
+            _ => Err("Invalid Oranj action type, expected Transfer".to_string()),
+        }
+    }
+
+    pub fn withdraw(
+        &mut self,
+        amount: u32,
+        user: &Identity,
+        token: String,
+        ctx: &mut ExecutionContext,
+    ) -> Result<String, String> {
+        let balances = match token.as_str() {
+            "oranj" => &mut self.oranj_balances,
+            "vitamin" => &mut self.vitamin_balances,
+            _ => return Err("Invalid token type. Use 'oranj' or 'vitamin'".to_string()),
+        };
+        let Some(current_balance) = balances.get(user).cloned() else {
+            return Err(format!("Unknown user, can't withdraw {}", token));
+        };
+        if amount > current_balance {
+            return Err(format!("Insufficient {} balance to withdraw", token));
+        }
+        ctx.is_in_callee_blobs(
+            &ContractName(token.clone()),
+            SmtTokenAction::Transfer {
+                sender: ctx.contract_name.0.clone().into(),
+                recipient: user.clone(),
+                amount: amount as u128,
+            },
+        )?;
+
+        balances.insert(user.clone(), current_balance - amount);
+        Ok(format!(
+            "Withdrew {} {} tokens to {}'s balance",
+            amount, token, user
+        ))
+    }
+}
+
+impl From<sdk::StateCommitment> for Dice {
+    fn from(state: sdk::StateCommitment) -> Self {
+        borsh::from_slice(&state.0)
+            .map_err(|_| "Could not decode dice state".to_string())
+            .unwrap()
+    }
+}
+
+#[test]
+fn test_payouts() {
+    let config = DiceConfig::default();
+    // Fair odds of 2x on a 50% chance, less 1%
+    assert_eq!(config.payout(1_000, 49, true), Some(1_980));
+    assert_eq!(config.payout(1_000, 50, false), Some(1_980));
+    assert_eq!(config.payout(1_000, 1, false), Some(99_000));
+    assert_eq!(config.payout(1_000, 0, false), None);
+    assert_eq!(config.payout(1_000, 99, true), None);
+    assert_eq!(config.payout(1_000, 1, true), None);
+
+    let edged = |house_edge_bps| DiceConfig { house_edge_bps };
+    assert!(Dice::new(edged(MAX_HOUSE_EDGE_BPS)).is_ok());
+    assert!(Dice::new(edged(MAX_HOUSE_EDGE_BPS + 1)).is_err());
+    assert!(Dice::new(edged(BPS as u32)).is_err());
+
+    // A roll both bets around can be placed on
+    let player: Identity = "player@wallet".into();
+    let blockhash = BlockHash::default();
+    let (tx_hash, rolled) = (0..)
+        .map(|i| TxHash::new(&format!("roll {i}")))
+        .map(|tx_hash| {
+            let rolled = Dice::draw(&blockhash, &tx_hash, &player);
+            (tx_hash, rolled)
+        })
+        .find(|(_, rolled)| (2..=97).contains(rolled))
+        .unwrap();
+    let mut dice = Dice::new(config.clone()).unwrap();
+    dice.oranj_balances.insert(player.clone(), 100);
+
+    // Over the face below the one rolled wins
+    let winnings = config.payout(100, rolled - 1, true).unwrap() - 100;
+    assert!(winnings > 0);
+    dice.roll(&player, &blockhash, &tx_hash, 1, 100, rolled - 1, true)
+        .unwrap();
+    assert!(dice.rolls[&player].won);
+    assert_eq!(dice.oranj_balances.get(&player), Some(&100));
+    assert_eq!(dice.vitamin_balances.get(&player), Some(&winnings));
+
+    // Under the face rolled loses, rolling exactly the target
+    dice.roll(&player, &blockhash, &tx_hash, 2, 10, rolled, false)
+        .unwrap();
+    assert!(!dice.rolls[&player].won);
+    assert_eq!(dice.oranj_balances.get(&player), Some(&90));
+    assert_eq!(dice.vitamin_balances.get(&player), Some(&winnings));
+
+    assert!(dice
+        .roll(&player, &blockhash, &tx_hash, 3, 1_000, 50, true)
+        .is_err());
+    assert!(dice
+        .roll(&player, &blockhash, &tx_hash, 4, 5, 50, true)
+        .is_err());
+
+    // Winnings that would overflow the Vitamin balance leave both balances untouched
+    dice.vitamin_balances.insert(player.clone(), u32::MAX);
+    assert!(dice
+        .roll(&player, &blockhash, &tx_hash, 5, 10, rolled - 1, true)
+        .is_err());
+    assert_eq!(dice.oranj_balances[&player], 90);
+    assert_eq!(dice.vitamin_balances[&player], u32::MAX);
+}
+
+#[test]
+fn test_rolls_of_a_block_land_apart() {
+    let blockhash = BlockHash::default();
+    let player: Identity = "player@wallet".into();
+    let faces: Vec<u8> = (0..16)
+        .map(|i| Dice::draw(&blockhash, &TxHash::new(&format!("{i}")), &player))
+        .collect();
+    assert!(faces.iter().any(|face| *face != faces[0]));
+
+    let tx_hash = TxHash::new("roll");
+    let faces: Vec<u8> = (0..16)
+        .map(|i| Dice::draw(&blockhash, &tx_hash, &Identity(format!("{i}@wallet"))))
+        .collect();
+    assert!(faces.iter().any(|face| *face != faces[0]));
+}
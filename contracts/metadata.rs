@@ -8,12 +8,20 @@ mod methods {
 mod metadata {
     pub const BLACKJACK_ELF: &[u8] = crate::methods::BLACKJACK_ELF;
     pub const BLACKJACK_ID: [u8; 32] = sdk::to_u8_array(&crate::methods::BLACKJACK_ID);
+    pub const ROULETTE_ELF: &[u8] = crate::methods::ROULETTE_ELF;
+    pub const ROULETTE_ID: [u8; 32] = sdk::to_u8_array(&crate::methods::ROULETTE_ID);
+    pub const DICE_ELF: &[u8] = crate::methods::DICE_ELF;
+    pub const DICE_ID: [u8; 32] = sdk::to_u8_array(&crate::methods::DICE_ID);
 }
 
 #[cfg(any(clippy, not(feature = "nonreproducible")))]
 mod metadata {
     pub const BLACKJACK_ELF: &[u8] = blackjack::client::metadata::BLACKJACK_ELF;
     pub const BLACKJACK_ID: [u8; 32] = blackjack::client::metadata::PROGRAM_ID;
+    pub const ROULETTE_ELF: &[u8] = roulette::client::metadata::ROULETTE_ELF;
+    pub const ROULETTE_ID: [u8; 32] = roulette::client::metadata::PROGRAM_ID;
+    pub const DICE_ELF: &[u8] = dice::client::metadata::DICE_ELF;
+    pub const DICE_ID: [u8; 32] = dice::client::metadata::PROGRAM_ID;
 }
 
 pub use metadata::*;
@@ -0,0 +1,177 @@
+use alloc::vec::Vec;
+use anyhow::{anyhow, Context, Result};
+use client_sdk::contract_indexer::{
+    axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router},
+    utoipa::{openapi::OpenApi, ToSchema},
+    utoipa_axum::{router::OpenApiRouter, routes},
+    AppError, ContractHandler, ContractHandlerStore,
+};
+use client_sdk::transaction_builder::TxExecutorHandler;
+use sdk::{
+    tracing::info, utils::as_hyle_output, Blob, BlobTransaction, Calldata, Hashed,
+    RegisterContractEffect, StateCommitment, TxContext, ZkContract,
+};
+
+use client_sdk::contract_indexer::axum;
+use client_sdk::contract_indexer::utoipa;
+
+use crate::*;
+
+pub mod metadata {
+    pub const ROULETTE_ELF: &[u8] = include_bytes!("../roulette.img");
+    pub const PROGRAM_ID: [u8; 32] = sdk::str_to_u8(include_str!("../roulette.txt"));
+}
+
+impl TxExecutorHandler for Roulette {
+    fn build_commitment_metadata(&self, _blob: &Blob) -> anyhow::Result<Vec<u8>> {
+        borsh::to_vec(self).context("Failed to serialize Roulette")
+    }
+
+    fn handle(&mut self, calldata: &Calldata) -> anyhow::Result<sdk::HyleOutput> {
+        let initial_state_commitment = <Self as ZkContract>::commit(self);
+        let mut res = <Self as ZkContract>::execute(self, calldata);
+        let next_state_commitment = <Self as ZkContract>::commit(self);
+        Ok(as_hyle_output(
+            initial_state_commitment,
+            next_state_commitment,
+            calldata,
+            &mut res,
+        ))
+    }
+
+    fn get_state_commitment(&self) -> StateCommitment {
+        <Self as ZkContract>::commit(self)
+    }
+
+    fn construct_state(
+        _register_blob: &RegisterContractEffect,
+        _metadata: &Option<Vec<u8>>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::default())
+    }
+}
+
+impl ContractHandler for Roulette {
+    async fn api(store: ContractHandlerStore<Roulette>) -> (Router<()>, OpenApi) {
+        let (router, api) = OpenApiRouter::default()
+            .routes(routes!(get_state))
+            .routes(routes!(get_user_balance))
+            .routes(routes!(get_user_spin))
+            .split_for_parts();
+
+        (router.with_state(store), api)
+    }
+
+    fn handle_transaction_success(
+        &mut self,
+        tx: &BlobTransaction,
+        index: BlobIndex,
+        tx_context: TxContext,
+    ) -> Result<Option<()>> {
+        let calldata = Calldata {
+            identity: tx.identity.clone(),
+            index,
+            blobs: tx.blobs.clone().into(),
+            tx_blob_count: tx.blobs.len(),
+            tx_hash: tx.hashed(),
+            tx_ctx: Some(tx_context),
+            private_input: vec![],
+        };
+
+        let hyle_output = self.handle(&calldata)?;
+        let program_outputs = str::from_utf8(&hyle_output.program_outputs).unwrap_or("no output");
+
+        info!("🚀 Executed roulette: {}", program_outputs);
+        Ok(None)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/state",
+    tag = "Contract",
+    responses(
+        (status = OK, description = "Get json state of contract")
+    )
+)]
+pub async fn get_state(
+    State(state): State<ContractHandlerStore<Roulette>>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    store.state.clone().map(Json).ok_or(AppError(
+        StatusCode::NOT_FOUND,
+        anyhow!("No state found for contract '{}'", store.contract_name),
+    ))
+}
+
+#[derive(Serialize, ToSchema)]
+struct UserBalances {
+    oranj: u32,
+    vitamin: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/{user_id}/balances",
+    tag = "Contract",
+    params(
+        ("user_id" = String, Path, description = "User identity")
+    ),
+    responses(
+        (status = OK, description = "Get user balances", body = UserBalances),
+        (status = NOT_FOUND, description = "No state found")
+    )
+)]
+pub async fn get_user_balance(
+    State(state): State<ContractHandlerStore<Roulette>>,
+    axum::extract::Path(user_id): axum::extract::Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    let state = store.state.as_ref().ok_or(AppError(
+        StatusCode::NOT_FOUND,
+        anyhow!("No state found for contract '{}'", store.contract_name),
+    ))?;
+
+    let user_identity = Identity(user_id);
+    Ok(Json(UserBalances {
+        oranj: state
+            .oranj_balances
+            .get(&user_identity)
+            .copied()
+            .unwrap_or(0),
+        vitamin: state
+            .vitamin_balances
+            .get(&user_identity)
+            .copied()
+            .unwrap_or(0),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/{user_id}/spin",
+    tag = "Contract",
+    params(
+        ("user_id" = String, Path, description = "User identity")
+    ),
+    responses(
+        (status = OK, description = "Latest spin of the user"),
+        (status = NOT_FOUND, description = "No spin found")
+    )
+)]
+pub async fn get_user_spin(
+    State(state): State<ContractHandlerStore<Roulette>>,
+    axum::extract::Path(user_id): axum::extract::Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    store
+        .state
+        .as_ref()
+        .and_then(|state| state.spins.get(&Identity(user_id.clone())))
+        .cloned()
+        .map(Json)
+        .ok_or(AppError(
+            StatusCode::NOT_FOUND,
+            anyhow!("No spin found for user '{}'", user_id),
+        ))
+}
@@ -0,0 +1,460 @@
+extern crate alloc;
+
+use core::hash::Hasher;
+
+use alloc::vec::Vec;
+use alloc::{
+    collections::btree_map::BTreeMap,
+    format,
+    string::{String, ToString},
+};
+use borsh::{io::Error, BorshDeserialize, BorshSerialize};
+use rand::Rng;
+use rand_seeder::SipHasher;
+use sdk::caller::ExecutionContext;
+use sdk::{Blob, BlobData, BlobIndex, Calldata, ContractAction, StructuredBlobData};
+use serde::{Deserialize, Serialize};
+
+use hyle_smt_token::SmtTokenAction;
+use sdk::{BlockHash, ContractName, Identity, RunResult, TxHash};
+
+#[cfg(feature = "client")]
+pub mod client;
+
+impl sdk::FullStateRevert for Roulette {}
+
+impl sdk::ZkContract for Roulette {
+    /// Entry point of the contract's logic
+    fn execute(&mut self, calldata: &sdk::Calldata) -> RunResult {
+        // Parse contract inputs
+        let (action, mut ctx) = sdk::utils::parse_calldata::<RouletteAction>(calldata)?;
+
+        let user = &calldata.identity;
+
+        let Some(tx_ctx) = calldata.tx_ctx.as_ref() else {
+            return Err("Missing tx context necessary for this contract".to_string());
+        };
+
+        // Execute the given action
+        let res = match action {
+            RouletteAction::Spin(bets) => self.spin(
+                user,
+                &tx_ctx.block_hash,
+                &calldata.tx_hash,
+                tx_ctx.block_height.0,
+                bets,
+            )?,
+            RouletteAction::Deposit(amount) => self.claim(amount, user, calldata, &ctx)?,
+            RouletteAction::Withdraw(amount, token) => {
+                self.withdraw(amount, user, token, &mut ctx)?
+            }
+        };
+
+        Ok((res.into(), ctx, alloc::vec![]))
+    }
+
+    /// The full state is serialized on-chain, as for the blackjack contract.
+    fn commit(&self) -> sdk::StateCommitment {
+        sdk::StateCommitment(self.as_bytes().expect("Failed to encode Roulette"))
+    }
+}
+
+pub const MIN_BET: u32 = 10;
+pub const MAX_BETS_PER_SPIN: usize = 16;
+/// Pockets of a European wheel: a single zero, then 1 to 36
+pub const POCKETS: u8 = 37;
+const RED_NUMBERS: [u8; 18] = [
+    1, 3, 5, 7, 9, 12, 14, 16, 18, 19, 21, 23, 25, 27, 30, 32, 34, 36,
+];
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Color {
+    Red,
+    Black,
+}
+
+impl Color {
+    /// Color of the pocket, the zero having none
+    pub fn of(number: u8) -> Option<Color> {
+        match number {
+            0 => None,
+            n if RED_NUMBERS.contains(&n) => Some(Color::Red),
+            _ => Some(Color::Black),
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum BetKind {
+    Straight(u8),
+    /// Two numbers next to each other on the layout
+    Split(u8, u8),
+    Color(Color),
+    /// 1 to 3, for 1-12, 13-24 and 25-36
+    Dozen(u8),
+    /// 1 to 3, the first column holding 1, 4, 7...
+    Column(u8),
+}
+
+impl BetKind {
+    pub fn validate(&self) -> Result<(), String> {
+        let valid = match self {
+            BetKind::Straight(number) => *number < POCKETS,
+            BetKind::Split(a, b) => Self::adjacent(*a, *b),
+            BetKind::Color(_) => true,
+            BetKind::Dozen(third) | BetKind::Column(third) => (1..=3).contains(third),
+        };
+        if !valid {
+            return Err(format!("Invalid bet {:?}", self));
+        }
+        Ok(())
+    }
+
+    /// Whether the numbers share an edge on the layout, the zero touching 1, 2 and 3
+    fn adjacent(a: u8, b: u8) -> bool {
+        let (low, high) = (a.min(b), a.max(b));
+        if high >= POCKETS {
+            return false;
+        }
+        match low {
+            0 => (1..=3).contains(&high),
+            _ => high == low + 3 || (high == low + 1 && low % 3 != 0),
+        }
+    }
+
+    /// Tokens won per token staked
+    pub fn payout(&self) -> u32 {
+        match self {
+            BetKind::Straight(_) => 35,
+            BetKind::Split(..) => 17,
+            BetKind::Color(_) => 1,
+            BetKind::Dozen(_) | BetKind::Column(_) => 2,
+        }
+    }
+
+    /// Whether the bet wins when the ball lands on `number`. Outside bets all lose on
+    /// the zero.
+    pub fn wins(&self, number: u8) -> bool {
+        match self {
+            BetKind::Straight(n) => *n == number,
+            BetKind::Split(a, b) => *a == number || *b == number,
+            BetKind::Color(color) => Color::of(number).as_ref() == Some(color),
+            BetKind::Dozen(dozen) => number != 0 && (number - 1) / 12 + 1 == *dozen,
+            BetKind::Column(column) => number != 0 && (number - 1) % 3 + 1 == *column,
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Bet {
+    pub kind: BetKind,
+    pub amount: u32,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Spin {
+    pub number: u8,
+    pub bets: Vec<Bet>,
+    /// Oranj tokens staked on the spin
+    pub staked: u32,
+    /// Stakes of the winning bets, returned in Oranj tokens
+    pub returned: u32,
+    /// Winnings, awarded in Vitamin tokens
+    pub won: u32,
+    pub block_height: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Roulette {
+    pub oranj_balances: BTreeMap<Identity, u32>,
+    pub vitamin_balances: BTreeMap<Identity, u32>,
+    /// Latest spin of each player
+    pub spins: BTreeMap<Identity, Spin>,
+}
+
+/// Enum representing possible calls to the contract functions.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum RouletteAction {
+    /// Places the bets and spins the wheel in the same transaction
+    Spin(Vec<Bet>),
+    Deposit(u32),
+    Withdraw(u32, String), // amount, token ("oranj" or "vitamin")
+}
+
+impl ContractAction for RouletteAction {
+    fn as_blob(
+        &self,
+        contract_name: ContractName,
+        caller: Option<BlobIndex>,
+        callees: Option<Vec<BlobIndex>>,
+    ) -> Blob {
+        Blob {
+            contract_name,
+            data: BlobData::from(StructuredBlobData {
+                caller,
+                callees,
+                parameters: self.clone(),
+            }),
+        }
+    }
+}
+
+impl Roulette {
+    pub fn as_bytes(&self) -> Result<Vec<u8>, Error> {
+        borsh::to_vec(self)
+    }
+
+    /// Pocket the ball lands on, drawn from the block hash, the transaction hash and the
+    /// player, so that the spins of a block land on different pockets
+    fn draw(blockhash: &BlockHash, tx_hash: &TxHash, user: &Identity) -> u8 {
+        let mut hasher = SipHasher::new();
+        hasher.write(blockhash.0.as_bytes());
+        hasher.write(tx_hash.0.as_bytes());
+        hasher.write(user.0.as_bytes());
+        let mut rnd = hasher.into_rng();
+        rnd.random_range(0..POCKETS)
+    }
+
+    pub fn spin(
+        &mut self,
+        user: &Identity,
+        blockhash: &BlockHash,
+        tx_hash: &TxHash,
+        block_height: u64,
+        bets: Vec<Bet>,
+    ) -> Result<String, String> {
+        if bets.is_empty() || bets.len() > MAX_BETS_PER_SPIN {
+            return Err(format!("A spin takes from 1 to {} bets", MAX_BETS_PER_SPIN));
+        }
+        let mut staked: u32 = 0;
+        for bet in bets.iter() {
+            bet.kind.validate()?;
+            if bet.amount < MIN_BET {
+                return Err(format!("Minimum bet is {}", MIN_BET));
+            }
+            staked = staked
+                .checked_add(bet.amount)
+                .ok_or_else(|| "Stake overflow".to_string())?;
+        }
+
+        let balance = self.oranj_balances.get(user).copied().unwrap_or(0);
+        if balance < staked {
+            return Err(format!(
+                "Insufficient balance. You have {} but bets total {}",
+                balance, staked
+            ));
+        }
+
+        let number = Self::draw(blockhash, tx_hash, user);
+        let mut returned: u32 = 0;
+        let mut won: u32 = 0;
+        for bet in bets.iter().filter(|bet| bet.kind.wins(number)) {
+            returned += bet.amount;
+            won = bet
+                .amount
+                .checked_mul(bet.kind.payout())
+                .and_then(|winnings| won.checked_add(winnings))
+                .ok_or_else(|| "Winnings overflow".to_string())?;
+        }
+
+        // Stakes of the winning bets come back in Oranj, winnings are paid in Vitamin
+        let vitamin = self
+            .vitamin_balances
+            .get(user)
+            .copied()
+            .unwrap_or(0)
+            .checked_add(won)
+            .ok_or_else(|| "Balance overflow".to_string())?;
+        self.oranj_balances
+            .insert(user.clone(), balance - staked + returned);
+        if won > 0 {
+            self.vitamin_balances.insert(user.clone(), vitamin);
+        }
+        self.spins.insert(
+            user.clone(),
+            Spin {
+                number,
+                bets,
+                staked,
+                returned,
+                won,
+                block_height,
+            },
+        );
+
+        Ok(format!(
+            "Ball landed on {}, {} won {} Vitamin tokens",
+            number, user, won
+        ))
+    }
+
+    pub fn claim(
+        &mut self,
+        amount: u32,
+        user: &Identity,
+        calldata: &Calldata,
+        ctx: &ExecutionContext,
+    ) -> Result<String, String> {
+        // Find the oranj transfer blob
+        let transfer_blob_index = calldata
+            .blobs
+            .iter()
+            .position(|(_, b)| b.contract_name == ContractName("oranj".to_string()))
+            .ok_or_else(|| "Missing Oranj transfer blob".to_string())?;
+
+        let transfer_action = sdk::utils::parse_structured_blob::<SmtTokenAction>(
+            &calldata.blobs,
+            &sdk::BlobIndex(transfer_blob_index),
+        )
+        .ok_or_else(|| "Failed to decode Oranj transfer action".to_string())?
+        .data
+        .parameters;
+
+        // Verify the transfer is for this contract and extract amount
+        match transfer_action {
+            SmtTokenAction::Transfer {
+                sender,
+                recipient,
+                amount: transfer_amount,
+            } => {
+                if amount as u128 != transfer_amount {
+                    return Err("Transfer amount is not the same as the deposit amount".to_string());
+                }
+                if &sender != user {
+                    return Err("Transfer is not from the user".to_string());
+                }
+                if recipient.0 != ctx.contract_name.0 {
+                    return Err("Transfer is not for the roulette contract".to_string());
+                }
+
+                let balance = self.oranj_balances.entry(user.clone()).or_default();
+                *balance = balance
+                    .checked_add(amount)
+                    .ok_or_else(|| "Balance overflow".to_string())?;
+
+                Ok(format!(
+                    "Added {} to balance, new balance is {} for user {}",
+                    amount, balance, user
+                ))
+            }
+            _ => Err("Invalid Oranj action type, expected Transfer".to_string()),
+        }
+    }
+
+    pub fn withdraw(
+        &mut self,
+        amount: u32,
+        user: &Identity,
+        token: String,
+        ctx: &mut ExecutionContext,
+    ) -> Result<String, String> {
+        let balances = match token.as_str() {
+            "oranj" => &mut self.oranj_balances,
+            "vitamin" => &mut self.vitamin_balances,
+            _ => return Err("Invalid token type. Use 'oranj' or 'vitamin'".to_string()),
+        };
+        let Some(current_balance) = balances.get(user).cloned() else {
+            return Err(format!("Unknown user, can't withdraw {}", token));
+        };
+        if amount > current_balance {
+            return Err(format!("Insufficient {} balance to withdraw", token));
+        }
+        ctx.is_in_callee_blobs(
+            &ContractName(token.clone()),
+            SmtTokenAction::Transfer {
+                sender: ctx.contract_name.0.clone().into(),
+                recipient: user.clone(),
+                amount: amount as u128,
+            },
+        )?;
+
+        balances.insert(user.clone(), current_balance - amount);
+        Ok(format!(
+            "Withdrew {} {} tokens to {}'s balance",
+            amount, token, user
+        ))
+    }
+}
+
+impl From<sdk::StateCommitment> for Roulette {
+    fn from(state: sdk::StateCommitment) -> Self {
+        borsh::from_slice(&state.0)
+            .map_err(|_| "Could not decode roulette state".to_string())
+            .unwrap()
+    }
+}
+
+#[test]
+fn test_bets() {
+    assert!(BetKind::Split(0, 3).validate().is_ok());
+    assert!(BetKind::Split(5, 8).validate().is_ok());
+    assert!(BetKind::Split(3, 4).validate().is_err());
+    assert!(BetKind::Straight(37).validate().is_err());
+    assert!(BetKind::Column(0).validate().is_err());
+
+    assert!(BetKind::Color(Color::Red).wins(36));
+    assert!(!BetKind::Color(Color::Black).wins(0));
+    assert!(BetKind::Dozen(2).wins(24));
+    assert!(!BetKind::Dozen(1).wins(0));
+    assert!(BetKind::Column(1).wins(34));
+    assert!(BetKind::Column(3).wins(36));
+}
+
+#[test]
+fn test_spin_pays_winning_bets() {
+    let player: Identity = "player@wallet".into();
+    let blockhash = BlockHash::default();
+    let tx_hash = TxHash::new("spin");
+    let number = Roulette::draw(&blockhash, &tx_hash, &player);
+    let mut roulette = Roulette::default();
+    roulette.oranj_balances.insert(player.clone(), 100);
+
+    let bets = alloc::vec![
+        Bet {
+            kind: BetKind::Straight(number),
+            amount: 10,
+        },
+        Bet {
+            kind: BetKind::Straight((number + 1) % POCKETS),
+            amount: 20,
+        },
+    ];
+    roulette
+        .spin(&player, &blockhash, &tx_hash, 1, bets.clone())
+        .unwrap();
+    assert_eq!(roulette.oranj_balances.get(&player), Some(&80));
+    assert_eq!(roulette.vitamin_balances.get(&player), Some(&350));
+    assert_eq!(roulette.spins[&player].number, number);
+
+    let too_much = alloc::vec![Bet {
+        kind: BetKind::Color(Color::Red),
+        amount: 90,
+    }];
+    assert!(roulette
+        .spin(&player, &blockhash, &tx_hash, 2, too_much)
+        .is_err());
+
+    // Winnings that would overflow the Vitamin balance leave both balances untouched
+    roulette.vitamin_balances.insert(player.clone(), u32::MAX);
+    assert!(roulette
+        .spin(&player, &blockhash, &tx_hash, 3, bets)
+        .is_err());
+    assert_eq!(roulette.oranj_balances.get(&player), Some(&80));
+    assert_eq!(roulette.vitamin_balances.get(&player), Some(&u32::MAX));
+}
+
+#[test]
+fn test_spins_of_a_block_land_apart() {
+    let blockhash = BlockHash::default();
+    let player: Identity = "player@wallet".into();
+    let numbers: Vec<u8> = (0..16)
+        .map(|i| Roulette::draw(&blockhash, &TxHash::new(&format!("{i}")), &player))
+        .collect();
+    assert!(numbers.iter().any(|number| *number != numbers[0]));
+
+    let tx_hash = TxHash::new("spin");
+    let numbers: Vec<u8> = (0..16)
+        .map(|i| Roulette::draw(&blockhash, &tx_hash, &Identity(format!("{i}@wallet"))))
+        .collect();
+    assert!(numbers.iter().any(|number| *number != numbers[0]));
+}
@@ -53,6 +53,7 @@ const Game: React.FC<GameProps> = ({ theme, toggleWeatherWidget }) => {
   const [isLoading, setIsLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const [gameState, setGameState] = useState<GameState | null>(null);
+  const [tableId, setTableId] = useState<number | null>(null);
   const [tokenBalances, setTokenBalances] = useState<TokenBalances | null>(null);
   const [showGameMenu, setShowGameMenu] = useState(false);
   const [showDepositButton, setShowDepositButton] = useState(false);
@@ -125,6 +126,7 @@ const Game: React.FC<GameProps> = ({ theme, toggleWeatherWidget }) => {
       if (existingGameState) {
         // Resume existing game
         console.log('Found existing game, resuming...', existingGameState);
+        setTableId(existingGameState.table_id ?? null);
         updateGameState(existingGameState, false, true);
         setShowStartGame(false);
       } else {
@@ -253,6 +255,9 @@ const Game: React.FC<GameProps> = ({ theme, toggleWeatherWidget }) => {
 
   const handleGameResponse = (response: GameResponse, isDepositing: boolean = false) => {
     addNotification(response.tx_hash);
+    if (response.table_id !== null) {
+      setTableId(response.table_id);
+    }
     updateGameState(response.table, isDepositing, false);
   };
 
@@ -338,8 +343,11 @@ const Game: React.FC<GameProps> = ({ theme, toggleWeatherWidget }) => {
       }
       setIsLoading(true);
       setError(null);
+      if (tableId === null) {
+        throw new Error('No game in progress');
+      }
       const wallet_blobs = createIdentityBlobs();
-      const gameStateResult = await gameService.hit(wallet_blobs, wallet.address);
+      const gameStateResult = await gameService.hit(wallet_blobs, wallet.address, tableId);
       handleGameResponse(gameStateResult);
       setTimeout(() => loadAllBalances(), 2000);
     } catch (err: any) {
@@ -361,8 +369,11 @@ const Game: React.FC<GameProps> = ({ theme, toggleWeatherWidget }) => {
       }
       setIsLoading(true);
       setError(null);
+      if (tableId === null) {
+        throw new Error('No game in progress');
+      }
       const wallet_blobs = createIdentityBlobs();
-      const gameStateResult = await gameService.stand(wallet_blobs, wallet.address);
+      const gameStateResult = await gameService.stand(wallet_blobs, wallet.address, tableId);
       handleGameResponse(gameStateResult);
       setTimeout(() => loadAllBalances(), 1000);
     } catch (err: any) {
@@ -384,8 +395,11 @@ const Game: React.FC<GameProps> = ({ theme, toggleWeatherWidget }) => {
       }
       setIsLoading(true);
       setError(null);
+      if (tableId === null) {
+        throw new Error('No game in progress');
+      }
       const wallet_blobs = createIdentityBlobs();
-      const gameStateResult = await gameService.doubleDown(wallet_blobs, wallet.address);
+      const gameStateResult = await gameService.doubleDown(wallet_blobs, wallet.address, tableId);
       handleGameResponse(gameStateResult);
       setTimeout(() => loadAllBalances(), 1000);
     } catch (err: any) {
@@ -5,6 +5,20 @@ const API_BASE_URL = import.meta.env.VITE_API_BASE_URL;
 const INDEXER_BASE_URL = import.meta.env.VITE_INDEXER_BASE_URL;
 
 class GameService {
+  // Name of the blackjack contract, given by the server
+  private contractName?: Promise<string>;
+
+  private async contractPath(): Promise<string> {
+    if (!this.contractName) {
+      this.contractName = this.getConfig()
+        .then((config) => config.contract_name)
+        .catch((error) => {
+          this.contractName = undefined;
+          throw error;
+        });
+    }
+    return `/v1/indexer/contract/${await this.contractName}`;
+  }
 
   private async makeRequest(endpoint: string, method: string = 'GET', body?: any, identity?: string, baseUrl: string = API_BASE_URL) {
     const headers: HeadersInit = {
@@ -39,16 +53,16 @@ class GameService {
     return this.makeRequest('/api/init', 'POST', body, identity);
   }
 
-  async hit(wallet_blobs: [Blob, Blob], identity: string): Promise<GameResponse> {
-    return this.makeRequest('/api/hit', 'POST', wallet_blobs, identity);
+  async hit(wallet_blobs: [Blob, Blob], identity: string, table_id: number): Promise<GameResponse> {
+    return this.makeRequest('/api/hit', 'POST', { wallet_blobs, table_id }, identity);
   }
 
-  async stand(wallet_blobs: [Blob, Blob], identity: string): Promise<GameResponse> {
-    return this.makeRequest('/api/stand', 'POST', wallet_blobs, identity);
+  async stand(wallet_blobs: [Blob, Blob], identity: string, table_id: number): Promise<GameResponse> {
+    return this.makeRequest('/api/stand', 'POST', { wallet_blobs, table_id }, identity);
   }
 
-  async doubleDown(wallet_blobs: [Blob, Blob], identity: string): Promise<GameResponse> {
-    return this.makeRequest('/api/double_down', 'POST', wallet_blobs, identity);
+  async doubleDown(wallet_blobs: [Blob, Blob], identity: string, table_id: number): Promise<GameResponse> {
+    return this.makeRequest('/api/double_down', 'POST', { wallet_blobs, table_id }, identity);
   }
 
   async deposit(wallet_blobs: [Blob, Blob], identity: string, deposit: number): Promise<GameResponse> {
@@ -69,7 +83,7 @@ class GameService {
   }
 
   async getBalances(identity: string): Promise<{ oranj: number; vitamin: number }> {
-    return this.makeRequest(`/v1/indexer/contract/blackjack/user/${identity}/balances`, 'GET');
+    return this.makeRequest(`${await this.contractPath()}/user/${identity}/balances`, 'GET');
   }
 
   async getConfig(): Promise<{ contract_name: string }> {
@@ -79,13 +93,15 @@ class GameService {
   async getCurrentGameState(identity: string): Promise<GameState | null> {
     try {
       // Get the full contract state
-      const contractState = await this.makeRequest('/v1/indexer/contract/blackjack/state', 'GET');
+      const contractState = await this.makeRequest(`${await this.contractPath()}/state`, 'GET');
 
       // Check if the user has an ongoing table
-      const userTable = contractState.tables[identity];
-      if (!userTable) {
+      const userTables: Record<string, any> = contractState.tables[identity] || {};
+      const ongoing = Object.entries(userTables).find(([, table]) => table.state === 'Ongoing');
+      if (!ongoing) {
         return null; // No ongoing game
       }
+      const [tableId, userTable] = ongoing;
 
       // Get user balances
       const balances = await this.getBalances(identity);
@@ -100,6 +116,7 @@ class GameService {
         bet: userTable.bet,
         state: userTable.state,
         balance: balances.oranj,
+        table_id: Number(tableId),
       };
 
       return gameState;
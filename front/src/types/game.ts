@@ -6,10 +6,12 @@ export interface GameState {
   bet: number;
   state: 'Ongoing' | 'Lost' | 'Won';
   balance: number;
+  table_id?: number;
 }
 
 export interface GameResponse {
   tx_hash: string;
+  table_id: number | null;
   table: GameState;
 }
 
@@ -1,17 +1,37 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
 
-use crate::utils::AppError;
-use anyhow::Result;
+use crate::{
+    auth::WalletAuth,
+    dice_api::{self, DiceCtx},
+    idempotency::{idempotent, IdempotencyCache},
+    rate_limit::{rate_limit, RateLimiter},
+    session::{OpenedSession, SessionCharge, SessionStore, SessionView},
+    utils::AppError,
+};
+use anyhow::{Context, Result};
 use axum::{
-    extract::{Json, State},
+    extract::{Json, Path, Query, State},
     http::{HeaderMap, Method, StatusCode},
-    response::IntoResponse,
-    routing::{get, post},
-    Router,
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
 };
-use blackjack::{BlackJack, BlackJackAction, Table, TableState};
-use client_sdk::rest_client::NodeApiClient;
-use client_sdk::rest_client::NodeApiHttpClient;
+use blackjack::{
+    advisor::{self, Advice, StrategyChart},
+    history::GameHistory,
+    BatchableAction, BlackJack, BlackJackAction, SharedTable, SharedTablePhase, Table, TableId,
+    TableState, MAX_BATCH_ACTIONS,
+};
+use dice::{Dice, DiceConfig};
 use hyle_smt_token::SmtTokenAction;
 
 use hyle_modules::{
@@ -20,20 +40,142 @@ use hyle_modules::{
     modules::{
         contract_state_indexer::CSIBusEvent, prover::AutoProverEvent, BuildApiContextInner, Module,
     },
+    node_state::module::NodeStateEvent,
+};
+use sdk::{
+    Blob, BlobIndex, BlobTransaction, ContractAction, ContractName, Hashed, Identity, TxHash,
 };
-use sdk::{Blob, BlobIndex, BlobTransaction, ContractAction, ContractName, Identity};
-use serde::Serialize;
-use tokio::sync::Mutex;
-use tower_http::cors::{Any, CorsLayer};
+use serde::{Deserialize, Serialize};
+use server::{conf::CorsConf, node_client::NodeClient, wallet::WalletIndexer};
+use tokio::sync::{broadcast, oneshot};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+use tracing::{info, warn};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+/// Identity used to submit auto-stands, verified by the blackjack contract itself
+const AUTO_STAND_IDENTITY: &str = "autostand";
+const AUTO_STAND_INTERVAL: Duration = Duration::from_secs(10);
+/// Number of events buffered for slow `/api/stream` clients before they miss some
+const STREAM_CAPACITY: usize = 1024;
+/// How long `/api/tx/{tx_hash}` keeps reporting a transaction after its last status change
+const TX_RETENTION: Duration = Duration::from_secs(3600);
+/// How long synchronous requests wait for the optimistic execution of their transaction
+const EXECUTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Result of the optimistic execution of a transaction: the resulting state or the error
+type ExecutionResult<State> = Result<State, String>;
+
+/// Requests waiting for the optimistic execution of their transaction on a contract,
+/// fed by the module
+pub(crate) struct Executions<State> {
+    waiters: HashMap<TxHash, oneshot::Sender<ExecutionResult<State>>>,
+    /// Executions that happened before their request started waiting
+    early: HashMap<TxHash, (ExecutionResult<State>, Instant)>,
+}
+
+impl<State> Default for Executions<State> {
+    fn default() -> Self {
+        Executions {
+            waiters: HashMap::new(),
+            early: HashMap::new(),
+        }
+    }
+}
+
+pub(crate) type SharedExecutions<State> = Arc<std::sync::Mutex<Executions<State>>>;
+
+/// Latest state of the contract as executed by the indexer
+type LatestState = Arc<RwLock<Option<BlackJack>>>;
+
+/// Transactions sent by this server, with their latest status
+pub(crate) type TrackedTxs = Arc<std::sync::Mutex<HashMap<TxHash, TrackedTx>>>;
+
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TxStatus {
+    /// Accepted by the node, waiting for the indexer
+    Sequenced,
+    /// Executed by the indexer, waiting for its proof to settle
+    OptimisticSuccess,
+    /// Failed the optimistic execution
+    Failed,
+    Settled,
+    SettlementFailed,
+    TimedOut,
+}
+
+/// Action of a transaction sent by this server, on the contract it plays
+#[derive(Debug, Clone)]
+pub(crate) enum TrackedAction {
+    BlackJack(BlackJackAction),
+    /// Dice transactions don't play on a table
+    Dice,
+}
+
+impl TrackedAction {
+    fn table_id(&self) -> Option<TableId> {
+        match self {
+            TrackedAction::BlackJack(action) => action.table_id(),
+            TrackedAction::Dice => None,
+        }
+    }
+}
+
+impl From<BlackJackAction> for TrackedAction {
+    fn from(action: BlackJackAction) -> Self {
+        TrackedAction::BlackJack(action)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TrackedTx {
+    /// Player the transaction plays for
+    identity: Identity,
+    action: TrackedAction,
+    /// Table played on, known once the transaction is executed for an `Init`
+    table_id: Option<TableId>,
+    status: TxStatus,
+    error: Option<String>,
+    updated_at: Instant,
+}
 
 pub struct AppModule {
     bus: AppModuleBusClient,
+    node_client: Arc<dyn NodeClient>,
+    blackjack_cn: ContractName,
+    dice_cn: ContractName,
+    /// Latest optimistic state seen on the bus, used to find abandoned tables
+    latest_state: LatestState,
+    /// Tables an auto-stand is pending for, with its transaction
+    auto_stands: BTreeMap<(Identity, TableId), TxHash>,
+    events: broadcast::Sender<TxEvent>,
+    tracked_txs: TrackedTxs,
+    executions: SharedExecutions<BlackJack>,
+    dice_executions: SharedExecutions<Dice>,
 }
 
 pub struct AppModuleCtx {
     pub api: Arc<BuildApiContextInner>,
-    pub node_client: Arc<NodeApiHttpClient>,
+    pub node_client: Arc<dyn NodeClient>,
     pub blackjack_cn: ContractName,
+    pub dice_cn: ContractName,
+    pub dice_config: DiceConfig,
+    /// Indexer of the wallet contract, serving the session keys of the players
+    pub wallet_indexer: Arc<dyn WalletIndexer>,
+    pub operator: Option<Identity>,
+    pub clean_expire_after_blocks: Option<u64>,
+    pub data_directory: PathBuf,
+    /// Hex-encoded key sealing the persisted session keys, kept in memory when unset
+    pub session_encryption_key: Option<String>,
+    pub rate_limit_identity_per_minute: Option<u32>,
+    pub rate_limit_ip_per_minute: Option<u32>,
+    /// Reverse proxies in front of the server, reporting the client address
+    pub rate_limit_trusted_proxies: usize,
+    pub cors: CorsConf,
+    pub admin_api_key: Option<String>,
+    pub admin_identities: Vec<Identity>,
 }
 
 module_bus_client! {
@@ -41,6 +183,8 @@ module_bus_client! {
 pub struct AppModuleBusClient {
     receiver(AutoProverEvent<BlackJack>),
     receiver(CSIBusEvent<AutoProverEvent<BlackJack>>),
+    receiver(CSIBusEvent<AutoProverEvent<Dice>>),
+    receiver(NodeStateEvent),
 }
 }
 
@@ -48,61 +192,445 @@ impl Module for AppModule {
     type Context = Arc<AppModuleCtx>;
 
     async fn build(bus: SharedMessageBus, ctx: Self::Context) -> Result<Self> {
+        let (events, _) = broadcast::channel(STREAM_CAPACITY);
+        let tracked_txs = TrackedTxs::default();
+        let executions = SharedExecutions::default();
+        let dice_executions = SharedExecutions::default();
+        let latest_state = LatestState::default();
+        if ctx.session_encryption_key.is_none() {
+            warn!("No session encryption key, sessions are lost on restart");
+        }
+        // Before the indexed state is built, so that it records in the file
+        GameHistory::open_shared(&ctx.data_directory)?;
+        let sessions =
+            SessionStore::open(&ctx.data_directory, ctx.session_encryption_key.as_deref())?;
+        let wallet_auth = Arc::new(WalletAuth::open(
+            ctx.wallet_indexer.clone(),
+            &ctx.data_directory,
+        )?);
+        let rate_limiter = Arc::new(RateLimiter::new(
+            ctx.rate_limit_identity_per_minute,
+            ctx.rate_limit_ip_per_minute,
+            ctx.rate_limit_trusted_proxies,
+        )?);
+
         let state = RouterCtx {
             blackjack_cn: ctx.blackjack_cn.clone(),
-            app: Arc::new(Mutex::new(HyleOofCtx {
-                bus: bus.new_handle(),
-            })),
             client: ctx.node_client.clone(),
+            operator: ctx.operator.clone(),
+            admin_api_key: ctx.admin_api_key.clone(),
+            admin_identities: ctx.admin_identities.clone(),
+            clean_expire_after_blocks: ctx.clean_expire_after_blocks,
+            events: events.clone(),
+            tracked_txs: tracked_txs.clone(),
+            executions: executions.clone(),
+            latest_state: latest_state.clone(),
+            sessions: Arc::new(tokio::sync::Mutex::new(sessions)),
+            wallet_auth: wallet_auth.clone(),
+            rate_limiter: rate_limiter.clone(),
         };
 
-        // Créer un middleware CORS
-        let cors = CorsLayer::new()
-            .allow_origin(Any) // Permet toutes les origines (peut être restreint)
-            .allow_methods(vec![Method::GET, Method::POST]) // Permet les méthodes nécessaires
-            .allow_headers(Any); // Permet tous les en-têtes
+        let cors = cors_layer(&ctx.cors)?;
 
-        let api = Router::new()
+        let (api, openapi) = OpenApiRouter::new()
             .route("/_health", get(health))
-            .route("/api/deposit", post(deposit))
-            .route("/api/withdraw", post(withdraw))
-            .route("/api/init", post(init))
-            .route("/api/hit", post(hit))
-            .route("/api/stand", post(stand))
-            .route("/api/double_down", post(double_down))
-            .route("/api/clean_state", post(clean_state))
-            .route("/api/config", get(get_config))
+            .routes(routes!(deposit))
+            .routes(routes!(withdraw))
+            .routes(routes!(init))
+            .routes(routes!(hit))
+            .routes(routes!(stand))
+            .routes(routes!(double_down))
+            .routes(routes!(shared_open))
+            .routes(routes!(shared_join))
+            .routes(routes!(shared_leave))
+            .routes(routes!(shared_deal))
+            .routes(routes!(shared_hit))
+            .routes(routes!(shared_stand))
+            .routes(routes!(batch))
+            .routes(routes!(clean_state))
+            .routes(routes!(get_config))
+            .routes(routes!(stream))
+            .routes(routes!(get_tx))
+            .routes(routes!(table_hint))
+            .routes(routes!(open_session))
+            .routes(routes!(revoke_session))
+            .routes(routes!(get_sessions))
             .with_state(state)
-            .layer(cors); // Appliquer le middleware CORS
+            .merge(dice_api::router(DiceCtx {
+                client: ctx.node_client.clone(),
+                dice_cn: ctx.dice_cn.clone(),
+                config: ctx.dice_config.clone(),
+                tracked_txs: tracked_txs.clone(),
+                executions: dice_executions.clone(),
+                wallet_auth,
+                rate_limiter: rate_limiter.clone(),
+            }))
+            .split_for_parts();
+        let api = api
+            .layer(middleware::from_fn_with_state(
+                Arc::new(IdempotencyCache::default()),
+                idempotent,
+            ))
+            .layer(middleware::from_fn_with_state(rate_limiter, rate_limit))
+            .layer(cors);
 
         if let Ok(mut guard) = ctx.api.router.lock() {
             if let Some(router) = guard.take() {
                 guard.replace(router.merge(api));
             }
         }
+        if let Ok(mut guard) = ctx.api.openapi.lock() {
+            guard.merge(openapi);
+        }
         let bus = AppModuleBusClient::new_from_bus(bus.new_handle()).await;
 
-        Ok(AppModule { bus })
+        Ok(AppModule {
+            bus,
+            node_client: ctx.node_client.clone(),
+            blackjack_cn: ctx.blackjack_cn.clone(),
+            dice_cn: ctx.dice_cn.clone(),
+            latest_state,
+            auto_stands: BTreeMap::new(),
+            events,
+            tracked_txs,
+            executions,
+            dice_executions,
+        })
     }
 
     async fn run(&mut self) -> Result<()> {
+        let mut auto_stand_interval = tokio::time::interval(AUTO_STAND_INTERVAL);
+
         module_handle_messages! {
             on_self self,
+            listen<CSIBusEvent<AutoProverEvent<BlackJack>>> event => {
+                match event.event {
+                    AutoProverEvent::SuccessTx(tx_hash, state) => {
+                        self.publish_success(&tx_hash, &state);
+                        self.dispatch_execution(&self.executions, tx_hash, Ok(state.clone()));
+                        if let Ok(mut latest_state) = self.latest_state.write() {
+                            *latest_state = Some(state);
+                        }
+                    }
+                    AutoProverEvent::FailedTx(tx_hash, error) => {
+                        let tracked =
+                            self.set_status(&tx_hash, TxStatus::Failed, Some(error.clone()));
+                        self.publish(TxEventKind::Failed, &tx_hash, tracked.as_ref(), Some(error.clone()));
+                        self.dispatch_execution(&self.executions, tx_hash, Err(error));
+                    }
+                }
+            }
+            listen<CSIBusEvent<AutoProverEvent<Dice>>> event => {
+                match event.event {
+                    AutoProverEvent::SuccessTx(tx_hash, state) => {
+                        let tracked =
+                            self.set_status(&tx_hash, TxStatus::OptimisticSuccess, None);
+                        self.publish(TxEventKind::OptimisticSuccess, &tx_hash, tracked.as_ref(), None);
+                        self.dispatch_execution(&self.dice_executions, tx_hash, Ok(state));
+                    }
+                    AutoProverEvent::FailedTx(tx_hash, error) => {
+                        let tracked =
+                            self.set_status(&tx_hash, TxStatus::Failed, Some(error.clone()));
+                        self.publish(TxEventKind::Failed, &tx_hash, tracked.as_ref(), Some(error.clone()));
+                        self.dispatch_execution(&self.dice_executions, tx_hash, Err(error));
+                    }
+                }
+            }
+            listen<NodeStateEvent> event => {
+                let NodeStateEvent::NewBlock(block) = event;
+                self.publish_block(&block);
+            }
+            _ = auto_stand_interval.tick() => {
+                self.prune_tracked_txs();
+                prune_early_executions(&self.executions);
+                prune_early_executions(&self.dice_executions);
+                if let Err(e) = self.submit_auto_stands().await {
+                    warn!("Failed to submit auto-stands: {:#}", e);
+                }
+            }
         };
 
         Ok(())
     }
 }
 
+impl AppModule {
+    fn tracked_tx(&self, tx_hash: &TxHash) -> Option<TrackedTx> {
+        self.tracked_txs.lock().ok()?.get(tx_hash).cloned()
+    }
+
+    /// Moves a transaction sent by this server to a new status, returning it
+    fn set_status(
+        &self,
+        tx_hash: &TxHash,
+        status: TxStatus,
+        error: Option<String>,
+    ) -> Option<TrackedTx> {
+        let mut tracked_txs = self.tracked_txs.lock().ok()?;
+        let tracked = tracked_txs.get_mut(tx_hash)?;
+        tracked.status = status;
+        if error.is_some() {
+            tracked.error = error;
+        }
+        tracked.updated_at = Instant::now();
+        Some(tracked.clone())
+    }
+
+    /// Hands the execution of a transaction sent by this server to the request waiting
+    /// for it, or keeps it until the request starts waiting.
+    fn dispatch_execution<State>(
+        &self,
+        executions: &SharedExecutions<State>,
+        tx_hash: TxHash,
+        result: ExecutionResult<State>,
+    ) {
+        if self.tracked_tx(&tx_hash).is_none() {
+            return;
+        }
+        let Ok(mut executions) = executions.lock() else {
+            return;
+        };
+        match executions.waiters.remove(&tx_hash) {
+            // The request may have timed out in the meantime
+            Some(waiter) => {
+                let _ = waiter.send(result);
+            }
+            None => {
+                executions.early.insert(tx_hash, (result, Instant::now()));
+            }
+        }
+    }
+
+    fn prune_tracked_txs(&self) {
+        if let Ok(mut tracked_txs) = self.tracked_txs.lock() {
+            tracked_txs.retain(|_, tracked| tracked.updated_at.elapsed() < TX_RETENTION);
+        }
+    }
+
+    /// Publishes the optimistic execution of a transaction, with the table it played on
+    fn publish_success(&self, tx_hash: &TxHash, state: &BlackJack) {
+        let tracked = self.set_status(tx_hash, TxStatus::OptimisticSuccess, None);
+        let mut event = TxEvent::new(TxEventKind::OptimisticSuccess, tx_hash, tracked.as_ref());
+        if let Some(TrackedTx {
+            identity,
+            action: TrackedAction::BlackJack(action),
+            ..
+        }) = tracked.as_ref()
+        {
+            // Auto-stands play on shared tables too
+            let auto_stood_seat = matches!(action, BlackJackAction::AutoStand(_, table_id)
+                if state.shared_tables.contains_key(table_id));
+            if is_shared_action(action) || auto_stood_seat {
+                let (table_id, table) = shared_table_view(state, action);
+                event.table_id = table_id;
+                event.shared_table = table;
+            } else {
+                let (table_id, table) = table_view(state, identity, action);
+                event.table_id = table_id;
+                event.table = Some(table);
+            }
+        }
+        if let (Some(table_id), Ok(mut tracked_txs)) = (event.table_id, self.tracked_txs.lock()) {
+            if let Some(tracked) = tracked_txs.get_mut(tx_hash) {
+                tracked.table_id = Some(table_id);
+            }
+        }
+        // Sending only fails when no one is listening
+        let _ = self.events.send(event);
+    }
+
+    fn publish(
+        &self,
+        kind: TxEventKind,
+        tx_hash: &TxHash,
+        tracked: Option<&TrackedTx>,
+        error: Option<String>,
+    ) {
+        let mut event = TxEvent::new(kind, tx_hash, tracked);
+        event.error = error;
+        let _ = self.events.send(event);
+    }
+
+    /// Publishes the proofs and settlements of the transactions sent by this server
+    fn publish_block(&self, block: &sdk::Block) {
+        for proof in block.blob_proof_outputs.iter() {
+            if proof.contract_name == self.blackjack_cn || proof.contract_name == self.dice_cn {
+                let tx_hash = &proof.blob_tx_hash;
+                let tracked = self.tracked_tx(tx_hash);
+                self.publish(TxEventKind::Proved, tx_hash, tracked.as_ref(), None);
+            }
+        }
+
+        let settled = [
+            (
+                TxEventKind::Settled,
+                TxStatus::Settled,
+                &block.successful_txs,
+            ),
+            (
+                TxEventKind::SettlementFailed,
+                TxStatus::SettlementFailed,
+                &block.failed_txs,
+            ),
+            (
+                TxEventKind::TimedOut,
+                TxStatus::TimedOut,
+                &block.timed_out_txs,
+            ),
+        ];
+        for (kind, status, tx_hashes) in settled {
+            for tx_hash in tx_hashes.iter() {
+                let Some(tracked) = self.set_status(tx_hash, status.clone(), None) else {
+                    continue;
+                };
+                self.publish(kind.clone(), tx_hash, Some(&tracked), None);
+            }
+        }
+    }
+
+    /// Submits an `AutoStand` for every table that has been ongoing for longer than the
+    /// contract's game timeout, and for the seat holding up every shared hand dealt for
+    /// longer than that, so abandoned bets get resolved.
+    async fn submit_auto_stands(&mut self) -> Result<()> {
+        // Settled auto-stands are done, the ones that failed, timed out or were forgotten
+        // are submitted again
+        if let Ok(tracked_txs) = self.tracked_txs.lock() {
+            self.auto_stands.retain(|_, tx_hash| {
+                tracked_txs.get(tx_hash).is_some_and(|tracked| {
+                    matches!(
+                        tracked.status,
+                        TxStatus::Sequenced | TxStatus::OptimisticSuccess
+                    )
+                })
+            });
+        }
+        let abandoned: Vec<(Identity, TableId)> = {
+            let timeout_blocks = match self.latest_state.read() {
+                Ok(state) => state
+                    .as_ref()
+                    .and_then(|state| state.config.game_timeout_blocks),
+                Err(_) => None,
+            };
+            let Some(timeout_blocks) = timeout_blocks else {
+                return Ok(());
+            };
+            let block_height = self.node_client.get_block_height().await?.0;
+            let Ok(state) = self.latest_state.read() else {
+                return Ok(());
+            };
+            let Some(state) = state.as_ref() else {
+                return Ok(());
+            };
+            let seats = state.shared_tables.iter().filter_map(|(table_id, table)| {
+                let SharedTablePhase::Playing(turn) = table.phase else {
+                    return None;
+                };
+                if !table.is_abandoned(block_height, timeout_blocks) {
+                    return None;
+                }
+                let seat = table.seats.get(turn as usize)?;
+                Some((seat.player.clone(), *table_id))
+            });
+            state
+                .tables
+                .iter()
+                .flat_map(|(player, tables)| {
+                    tables
+                        .iter()
+                        .filter(|(_, table)| table.is_abandoned(block_height, timeout_blocks))
+                        .map(|(table_id, _)| (player.clone(), *table_id))
+                })
+                .chain(seats)
+                .collect()
+        };
+
+        for (player, table_id) in abandoned {
+            if self.auto_stands.contains_key(&(player.clone(), table_id)) {
+                continue;
+            }
+            let identity = Identity(format!("{AUTO_STAND_IDENTITY}@{}", self.blackjack_cn.0));
+            let action = BlackJackAction::AutoStand(player.clone(), table_id);
+            let tx = BlobTransaction::new(
+                identity,
+                vec![action.as_blob(self.blackjack_cn.clone(), None, None)],
+            );
+            let tx_hash = match self.node_client.send_tx_blob(tx).await {
+                Ok(tx_hash) => tx_hash,
+                Err(e) => {
+                    warn!("Failed to submit auto-stand for table {table_id} of {player}: {e:#}");
+                    continue;
+                }
+            };
+            // The loop handles bus events after this returns, so tracking can wait for the hash
+            track_tx(
+                &self.tracked_txs,
+                tx_hash.clone(),
+                player.clone(),
+                action.into(),
+            );
+            info!("⏰ Submitted auto-stand for table {table_id} of {player} in tx {tx_hash}");
+            self.auto_stands.insert((player, table_id), tx_hash);
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 struct RouterCtx {
-    pub app: Arc<Mutex<HyleOofCtx>>,
-    pub client: Arc<NodeApiHttpClient>,
+    pub client: Arc<dyn NodeClient>,
     pub blackjack_cn: ContractName,
+    pub operator: Option<Identity>,
+    pub admin_api_key: Option<String>,
+    pub admin_identities: Vec<Identity>,
+    pub clean_expire_after_blocks: Option<u64>,
+    pub events: broadcast::Sender<TxEvent>,
+    tracked_txs: TrackedTxs,
+    executions: SharedExecutions<BlackJack>,
+    latest_state: LatestState,
+    sessions: Arc<tokio::sync::Mutex<SessionStore>>,
+    wallet_auth: Arc<WalletAuth>,
+    rate_limiter: Arc<RateLimiter>,
 }
 
-pub struct HyleOofCtx {
-    pub bus: SharedMessageBus,
+/// CORS middleware allowing the configured origins, methods and headers
+fn cors_layer(conf: &CorsConf) -> Result<CorsLayer> {
+    let any = |values: &[String]| values.iter().any(|value| value == "*");
+
+    let origins = if any(&conf.allowed_origins) {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            conf.allowed_origins
+                .iter()
+                .map(|origin| origin.parse())
+                .collect::<Result<Vec<_>, _>>()
+                .context("Invalid CORS origin")?,
+        )
+    };
+    let methods = AllowMethods::list(
+        conf.allowed_methods
+            .iter()
+            .map(|method| method.parse::<Method>())
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid CORS method")?,
+    );
+    let headers = if any(&conf.allowed_headers) {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(
+            conf.allowed_headers
+                .iter()
+                .map(|header| header.parse())
+                .collect::<Result<Vec<_>, _>>()
+                .context("Invalid CORS header")?,
+        )
+    };
+
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers))
 }
 
 async fn health() -> impl IntoResponse {
@@ -114,14 +642,22 @@ async fn health() -> impl IntoResponse {
 // --------------------------------------------------------
 
 const IDENTITY_HEADER: &str = "x-identity";
+/// Operator API key giving access to the administrative routes
+const API_KEY_HEADER: &str = "x-api-key";
+/// `Prefer: respond-async` (RFC 7240) returns the tx hash without waiting for the execution
+const PREFER_HEADER: &str = "prefer";
+/// Token of a session opened with `/api/session`, used instead of wallet blobs
+const SESSION_KEY_HEADER: &str = "x-session-key";
 
 #[derive(Debug)]
-struct AuthHeaders {
-    identity: String,
+pub(crate) struct AuthHeaders {
+    pub(crate) identity: String,
+    pub(crate) respond_async: bool,
+    session_key: Option<String>,
 }
 
 impl AuthHeaders {
-    fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
         let identity = headers
             .get(IDENTITY_HEADER)
             .and_then(|v| v.to_str().ok())
@@ -132,8 +668,23 @@ impl AuthHeaders {
                 )
             })?
             .to_string();
+        let respond_async = headers
+            .get_all(PREFER_HEADER)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|preference| preference.trim().eq_ignore_ascii_case("respond-async"));
 
-        Ok(AuthHeaders { identity })
+        let session_key = headers
+            .get(SESSION_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        Ok(AuthHeaders {
+            identity,
+            respond_async,
+            session_key,
+        })
     }
 }
 
@@ -141,23 +692,104 @@ impl AuthHeaders {
 //     Types
 // --------------------------------------------------------
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, ToSchema)]
 pub struct ApiTable {
     pub bank: Vec<u32>,
     pub bank_count: u32,
     pub user: Vec<u32>,
     pub user_count: u32,
     pub bet: u32,
+    #[schema(value_type = String)]
     pub state: TableState,
     pub balance: u32,
 }
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, ToSchema)]
 pub struct Resp {
     pub tx_hash: String,
+    pub table_id: Option<TableId>,
     pub table: ApiTable,
 }
 
+/// Response of a request sent with `Prefer: respond-async`
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct PendingResp {
+    pub tx_hash: String,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct TxStatusResp {
+    pub tx_hash: String,
+    pub status: TxStatus,
+    pub identity: String,
+    pub table_id: Option<TableId>,
+    pub error: Option<String>,
+}
+
+/// Basic-strategy move for the current hand of a solo table
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct HintResp {
+    pub table_id: TableId,
+    #[schema(value_type = String)]
+    pub advice: Advice,
+    pub user_count: u32,
+    /// Whether an ace still counts as 11 in `user_count`
+    pub soft: bool,
+    pub dealer_up: u32,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct SharedResp {
+    pub tx_hash: String,
+    pub table_id: Option<TableId>,
+    #[schema(value_type = Option<Object>)]
+    pub table: Option<SharedTable>,
+    pub balance: u32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TxEventKind {
+    /// Executed by the indexer
+    OptimisticSuccess,
+    /// Failed the optimistic execution
+    Failed,
+    /// A proof of the blackjack or dice blob was verified on-chain
+    Proved,
+    Settled,
+    SettlementFailed,
+    TimedOut,
+}
+
+/// Update pushed to the `/api/stream` clients
+#[derive(Serialize, Debug, Clone)]
+pub struct TxEvent {
+    pub kind: TxEventKind,
+    pub tx_hash: String,
+    /// Player the transaction plays for, when it was sent by this server
+    pub identity: Option<String>,
+    pub table_id: Option<TableId>,
+    /// Table after the optimistic execution, for `optimistic_success` events
+    pub table: Option<ApiTable>,
+    /// Shared table after the optimistic execution, for `optimistic_success` events
+    pub shared_table: Option<SharedTable>,
+    pub error: Option<String>,
+}
+
+impl TxEvent {
+    fn new(kind: TxEventKind, tx_hash: &TxHash, tracked: Option<&TrackedTx>) -> Self {
+        TxEvent {
+            kind,
+            tx_hash: tx_hash.to_string(),
+            identity: tracked.map(|tracked| tracked.identity.0.clone()),
+            table_id: tracked.and_then(|tracked| tracked.table_id),
+            table: None,
+            shared_table: None,
+            error: None,
+        }
+    }
+}
+
 impl From<Table> for ApiTable {
     fn from(table: Table) -> Self {
         ApiTable {
@@ -172,26 +804,95 @@ impl From<Table> for ApiTable {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct ConfigResponse {
     contract_name: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, ToSchema)]
 struct WithdrawRequest {
+    #[schema(value_type = Vec<Object>)]
     wallet_blobs: [Blob; 2],
     withdraw: u32,
     token: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, ToSchema)]
 struct InitRequest {
+    #[schema(value_type = Vec<Object>)]
+    wallet_blobs: [Blob; 2],
+    bet: u32,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+struct TableRequest {
+    /// Can be left out for actions authorised by the `x-session-key` header
+    #[schema(value_type = Option<Vec<Object>>)]
+    wallet_blobs: Option<[Blob; 2]>,
+    table_id: TableId,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+struct BatchRequest {
+    /// Can be left out when the session of the `x-session-key` header can play every action
+    #[schema(value_type = Option<Vec<Object>>)]
+    wallet_blobs: Option<[Blob; 2]>,
+    /// Actions played in order, e.g. `[{"Hit": 3}, {"Stand": 3}]`
+    #[schema(value_type = Vec<Object>)]
+    actions: Vec<BatchableAction>,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+struct OpenSessionRequest {
+    #[schema(value_type = Vec<Object>)]
     wallet_blobs: [Blob; 2],
+    duration_ms: u64,
+    max_actions: u32,
+    max_spend: u32,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+struct RevokeSessionRequest {
+    #[schema(value_type = Vec<Object>)]
+    wallet_blobs: [Blob; 2],
+    public_key: String,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+struct JoinSeatRequest {
+    #[schema(value_type = Vec<Object>)]
+    wallet_blobs: [Blob; 2],
+    table_id: TableId,
     bet: u32,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StreamFilter {
+    identity: Option<String>,
+    table_id: Option<TableId>,
+}
+
+impl StreamFilter {
+    fn matches(&self, event: &TxEvent) -> bool {
+        self.identity
+            .as_ref()
+            .is_none_or(|identity| event.identity.as_ref() == Some(identity))
+            && self
+                .table_id
+                .is_none_or(|table_id| event.table_id == Some(table_id))
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HintQuery {
+    table_id: TableId,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
 struct DepositRequest {
+    #[schema(value_type = Vec<Object>)]
     wallet_blobs: [Blob; 2],
     deposit: u32,
 }
@@ -200,6 +901,19 @@ struct DepositRequest {
 //     Routes
 // --------------------------------------------------------
 
+#[utoipa::path(
+    post,
+    path = "/api/withdraw",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = WithdrawRequest,
+    responses(
+        (status = OK, description = "Table after the transaction", body = Resp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
 async fn withdraw(
     State(ctx): State<RouterCtx>,
     headers: HeaderMap,
@@ -215,6 +929,19 @@ async fn withdraw(
     .await
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/deposit",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = DepositRequest,
+    responses(
+        (status = OK, description = "Table after the transaction", body = Resp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
 async fn deposit(
     State(ctx): State<RouterCtx>,
     headers: HeaderMap,
@@ -230,6 +957,19 @@ async fn deposit(
     .await
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/init",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = InitRequest,
+    responses(
+        (status = OK, description = "Table after the transaction", body = Resp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
 async fn init(
     State(ctx): State<RouterCtx>,
     headers: HeaderMap,
@@ -245,62 +985,667 @@ async fn init(
     .await
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/hit",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+        ("x-session-key" = Option<String>, Header, description = "Token of a session, used instead of wallet blobs"),
+    ),
+    request_body = TableRequest,
+    responses(
+        (status = OK, description = "Table after the transaction", body = Resp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
 async fn hit(
     State(ctx): State<RouterCtx>,
     headers: HeaderMap,
-    Json(wallet_blobs): Json<[Blob; 2]>,
+    Json(request): Json<TableRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     let auth = AuthHeaders::from_headers(&headers)?;
-    send(ctx, BlackJackAction::Hit, auth, wallet_blobs).await
+    let action = BlackJackAction::Hit(request.table_id);
+    let authorisation = authorise(&ctx, &auth, &action, request.wallet_blobs).await?;
+    send(ctx, action, auth, authorisation).await
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/stand",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+        ("x-session-key" = Option<String>, Header, description = "Token of a session, used instead of wallet blobs"),
+    ),
+    request_body = TableRequest,
+    responses(
+        (status = OK, description = "Table after the transaction", body = Resp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
 async fn stand(
     State(ctx): State<RouterCtx>,
     headers: HeaderMap,
-    Json(wallet_blobs): Json<[Blob; 2]>,
+    Json(request): Json<TableRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     let auth = AuthHeaders::from_headers(&headers)?;
-    send(ctx, BlackJackAction::Stand, auth, wallet_blobs).await
+    let action = BlackJackAction::Stand(request.table_id);
+    let authorisation = authorise(&ctx, &auth, &action, request.wallet_blobs).await?;
+    send(ctx, action, auth, authorisation).await
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/double_down",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+        ("x-session-key" = Option<String>, Header, description = "Token of a session, used instead of wallet blobs"),
+    ),
+    request_body = TableRequest,
+    responses(
+        (status = OK, description = "Table after the transaction", body = Resp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
 async fn double_down(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<TableRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    let action = BlackJackAction::DoubleDown(request.table_id);
+    let authorisation = authorise(&ctx, &auth, &action, request.wallet_blobs).await?;
+    send(ctx, action, auth, authorisation).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/shared/open",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = Vec<Object>,
+    responses(
+        (status = OK, description = "Shared table after the transaction", body = SharedResp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
+async fn shared_open(
     State(ctx): State<RouterCtx>,
     headers: HeaderMap,
     Json(wallet_blobs): Json<[Blob; 2]>,
 ) -> Result<impl IntoResponse, AppError> {
     let auth = AuthHeaders::from_headers(&headers)?;
-    send(ctx, BlackJackAction::DoubleDown, auth, wallet_blobs).await
+    send_shared(ctx, BlackJackAction::OpenSharedTable, auth, wallet_blobs).await
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/shared/join",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = JoinSeatRequest,
+    responses(
+        (status = OK, description = "Shared table after the transaction", body = SharedResp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
+async fn shared_join(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<JoinSeatRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    send_shared(
+        ctx,
+        BlackJackAction::JoinSeat(request.table_id, request.bet),
+        auth,
+        request.wallet_blobs,
+    )
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/shared/leave",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = TableRequest,
+    responses(
+        (status = OK, description = "Shared table after the transaction", body = SharedResp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
+async fn shared_leave(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<TableRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    let action = BlackJackAction::LeaveSeat(request.table_id);
+    let authorisation = authorise(&ctx, &auth, &action, request.wallet_blobs).await?;
+    send_shared(ctx, action, auth, authorisation).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/shared/deal",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = TableRequest,
+    responses(
+        (status = OK, description = "Shared table after the transaction", body = SharedResp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
+async fn shared_deal(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<TableRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    let action = BlackJackAction::DealSharedTable(request.table_id);
+    let authorisation = authorise(&ctx, &auth, &action, request.wallet_blobs).await?;
+    send_shared(ctx, action, auth, authorisation).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/shared/hit",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = TableRequest,
+    responses(
+        (status = OK, description = "Shared table after the transaction", body = SharedResp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
+async fn shared_hit(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<TableRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    let action = BlackJackAction::SharedHit(request.table_id);
+    let authorisation = authorise(&ctx, &auth, &action, request.wallet_blobs).await?;
+    send_shared(ctx, action, auth, authorisation).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/shared/stand",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = TableRequest,
+    responses(
+        (status = OK, description = "Shared table after the transaction", body = SharedResp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
+async fn shared_stand(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<TableRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    let action = BlackJackAction::SharedStand(request.table_id);
+    let authorisation = authorise(&ctx, &auth, &action, request.wallet_blobs).await?;
+    send_shared(ctx, action, auth, authorisation).await
+}
+
+/// Plays several actions in a single transaction, all or none of them
+#[utoipa::path(
+    post,
+    path = "/api/batch",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+        ("x-session-key" = Option<String>, Header, description = "Token of a session, used instead of wallet blobs"),
+    ),
+    request_body = BatchRequest,
+    responses(
+        (status = OK, description = "Table the batch ends on, a `SharedResp` when every action is played at a shared table", body = Resp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+        (status = BAD_REQUEST, description = "Empty batch or too many actions"),
+        (status = UNPROCESSABLE_ENTITY, description = "An action that cannot be batched"),
+    )
+)]
+async fn batch(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<BatchRequest>,
+) -> Result<Response, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    if request.actions.is_empty() || request.actions.len() > MAX_BATCH_ACTIONS {
+        return Err(AppError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("A batch holds from 1 to {MAX_BATCH_ACTIONS} actions"),
+        ));
+    }
+
+    let action = BlackJackAction::Batch(request.actions);
+    let authorisation = authorise(&ctx, &auth, &action, request.wallet_blobs).await?;
+    if is_shared_action(&action) {
+        send_shared(ctx, action, auth, authorisation).await
+    } else {
+        send(ctx, action, auth, authorisation).await
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/clean_state",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Operator identity, the only one the contract accepts"),
+        ("x-api-key" = Option<String>, Header, description = "Operator API key"),
+    ),
+    request_body = Vec<Object>,
+    responses(
+        (status = OK, description = "Table after the transaction", body = Resp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+        (status = FORBIDDEN, description = "Not an administrator, or not sent as the operator"),
+    )
+)]
 async fn clean_state(
     State(ctx): State<RouterCtx>,
     headers: HeaderMap,
     Json(wallet_blobs): Json<[Blob; 2]>,
 ) -> Result<impl IntoResponse, AppError> {
     let auth = AuthHeaders::from_headers(&headers)?;
+    require_admin(&ctx, &headers, &auth)?;
+    // The contract only accepts `CleanTick` from its operator, whoever is allowed to ask
+    if ctx.operator.as_ref() != Some(&Identity(auth.identity.clone())) {
+        return Err(AppError(
+            StatusCode::FORBIDDEN,
+            anyhow::anyhow!("The state is cleaned by the operator, send it as the operator"),
+        ));
+    }
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_millis();
-    send(ctx, BlackJackAction::CleanTick(now), auth, wallet_blobs).await
+    let expire_after = ctx.clean_expire_after_blocks;
+    send(
+        ctx,
+        BlackJackAction::CleanTick(now, expire_after),
+        auth,
+        wallet_blobs,
+    )
+    .await
+}
+
+/// Administrative routes are open to the holders of the operator API key, to the operator
+/// and to the configured admin identities.
+fn require_admin(ctx: &RouterCtx, headers: &HeaderMap, auth: &AuthHeaders) -> Result<(), AppError> {
+    let api_key = headers.get(API_KEY_HEADER).map(|v| v.as_bytes());
+    if let (Some(expected), Some(api_key)) = (&ctx.admin_api_key, api_key) {
+        if constant_time_eq(expected.as_bytes(), api_key) {
+            return Ok(());
+        }
+        return Err(AppError(
+            StatusCode::UNAUTHORIZED,
+            anyhow::anyhow!("Invalid API key"),
+        ));
+    }
+
+    let identity = Identity(auth.identity.clone());
+    if ctx.operator.as_ref() == Some(&identity) || ctx.admin_identities.contains(&identity) {
+        return Ok(());
+    }
+    Err(AppError(
+        StatusCode::FORBIDDEN,
+        anyhow::anyhow!("Only administrators can use this route"),
+    ))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/config",
+    tag = "Blackjack",
+    responses(
+        (status = OK, description = "Server configuration", body = ConfigResponse),
+    )
+)]
 async fn get_config(State(ctx): State<RouterCtx>) -> impl IntoResponse {
     Json(ConfigResponse {
         contract_name: ctx.blackjack_cn.0,
     })
 }
 
+/// Server-Sent Events stream of the contract transactions, optionally restricted to
+/// one player or one table with the `identity` and `table_id` query parameters.
+#[utoipa::path(
+    get,
+    path = "/api/stream",
+    tag = "Blackjack",
+    params(
+        StreamFilter,
+    ),
+    responses(
+        (status = OK, description = "Server-Sent Events of the transactions, as `TxEvent` JSON", content_type = "text/event-stream"),
+    )
+)]
+async fn stream(
+    State(ctx): State<RouterCtx>,
+    Query(filter): Query<StreamFilter>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events = BroadcastStream::new(ctx.events.subscribe()).filter_map(move |event| {
+        // Lagging clients skip the events they missed
+        let event = event.ok()?;
+        if !filter.matches(&event) {
+            return None;
+        }
+        Event::default().json_data(&event).ok().map(Ok)
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// Opens a session for the player: the returned public key must then be registered as a
+/// session key of their wallet account.
+#[utoipa::path(
+    post,
+    path = "/api/session",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = OpenSessionRequest,
+    responses(
+        (status = OK, description = "Session opened, its public key must be registered in the wallet", body = OpenedSession),
+    )
+)]
+async fn open_session(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<OpenSessionRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    let identity = Identity(auth.identity);
+    verify_wallet_blobs(
+        &ctx.wallet_auth,
+        &ctx.rate_limiter,
+        &identity,
+        &request.wallet_blobs,
+    )
+    .await?;
+
+    let session = ctx
+        .sessions
+        .lock()
+        .await
+        .create(
+            identity,
+            request.duration_ms,
+            request.max_actions,
+            request.max_spend,
+        )
+        .await
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(session))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/session/revoke",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = RevokeSessionRequest,
+    responses(
+        (status = OK, description = "Session revoked"),
+        (status = NOT_FOUND, description = "Session not found"),
+    )
+)]
+async fn revoke_session(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<RevokeSessionRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    let identity = Identity(auth.identity);
+    verify_wallet_blobs(
+        &ctx.wallet_auth,
+        &ctx.rate_limiter,
+        &identity,
+        &request.wallet_blobs,
+    )
+    .await?;
+
+    ctx.sessions
+        .lock()
+        .await
+        .revoke(&identity, &request.public_key)
+        .await
+        .map_err(|e| AppError(StatusCode::NOT_FOUND, e))?;
+    Ok(Json("OK"))
+}
+
+/// Sessions of the player that can still be used, listed for the holder of one of them
+#[utoipa::path(
+    get,
+    path = "/api/sessions",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+        ("x-session-key" = String, Header, description = "Token of one of the player's sessions"),
+    ),
+    responses(
+        (status = OK, description = "Active sessions of the player", body = Vec<SessionView>),
+        (status = UNAUTHORIZED, description = "Missing or unknown session token"),
+    )
+)]
+async fn get_sessions(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<SessionView>>, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    let identity = Identity(auth.identity);
+    let sessions = ctx.sessions.lock().await;
+    let is_holder = auth
+        .session_key
+        .as_deref()
+        .is_some_and(|token| sessions.is_holder(&identity, token));
+    if !is_holder {
+        return Err(AppError(
+            StatusCode::UNAUTHORIZED,
+            anyhow::anyhow!("Missing or unknown session token"),
+        ));
+    }
+    Ok(Json(sessions.active(&identity)))
+}
+
+/// Status of a transaction sent by this server in the last hour
+#[utoipa::path(
+    get,
+    path = "/api/tx/{tx_hash}",
+    tag = "Blackjack",
+    params(
+        ("tx_hash" = String, Path, description = "Transaction hash"),
+    ),
+    responses(
+        (status = OK, description = "Transaction status", body = TxStatusResp),
+        (status = NOT_FOUND, description = "Transaction not sent by this server"),
+    )
+)]
+async fn get_tx(
+    State(ctx): State<RouterCtx>,
+    Path(tx_hash): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let tracked = ctx
+        .tracked_txs
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Transaction tracker lock poisoned"))?
+        .get(&TxHash::new(&tx_hash))
+        .cloned();
+    let Some(tracked) = tracked else {
+        return Err(AppError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Transaction {tx_hash} is not tracked by this server"),
+        ));
+    };
+
+    Ok(Json(TxStatusResp {
+        tx_hash,
+        status: tracked.status,
+        identity: tracked.identity.0,
+        table_id: tracked.table_id,
+        error: tracked.error,
+    }))
+}
+
+/// Move basic strategy recommends on the player's ongoing table, for the tutorial mode
+#[utoipa::path(
+    get,
+    path = "/api/table/hint",
+    tag = "Blackjack",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+        HintQuery,
+    ),
+    responses(
+        (status = OK, description = "Recommended move", body = HintResp),
+        (status = NOT_FOUND, description = "Table not found"),
+        (status = CONFLICT, description = "The game on the table is over"),
+    )
+)]
+async fn table_hint(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Query(query): Query<HintQuery>,
+) -> Result<Json<HintResp>, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    let identity = Identity(auth.identity);
+    let table = ctx
+        .latest_state
+        .read()
+        .map_err(|_| anyhow::anyhow!("State lock poisoned"))?
+        .as_ref()
+        .and_then(|state| state.get_table(&identity, query.table_id))
+        .cloned()
+        .ok_or_else(|| {
+            AppError(
+                StatusCode::NOT_FOUND,
+                anyhow::anyhow!("Table {} not found", query.table_id),
+            )
+        })?;
+
+    let Some(advice) = advisor::advise(&table, &StrategyChart::default()) else {
+        return Err(AppError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!("The game on table {} is over", query.table_id),
+        ));
+    };
+    Ok(Json(HintResp {
+        table_id: query.table_id,
+        advice,
+        user_count: BlackJack::compute_score(&table.user),
+        soft: advisor::is_soft(&table.user),
+        dealer_up: table.bank[0],
+    }))
+}
+
+fn is_shared_action(action: &BlackJackAction) -> bool {
+    match action {
+        BlackJackAction::OpenSharedTable
+        | BlackJackAction::JoinSeat(..)
+        | BlackJackAction::LeaveSeat(_)
+        | BlackJackAction::DealSharedTable(_)
+        | BlackJackAction::SharedHit(_)
+        | BlackJackAction::SharedStand(_) => true,
+        BlackJackAction::Batch(actions) => {
+            !actions.is_empty()
+                && actions
+                    .iter()
+                    .all(|action| is_shared_action(&action.clone().into()))
+        }
+        _ => false,
+    }
+}
+
+/// The player's table after a transaction
+fn table_view(
+    state: &BlackJack,
+    identity: &Identity,
+    action: &BlackJackAction,
+) -> (Option<TableId>, ApiTable) {
+    let table_id = state.played_table(action);
+    let mut table: ApiTable = table_id
+        .and_then(|table_id| state.get_table(identity, table_id))
+        .cloned()
+        .unwrap_or_default()
+        .into();
+    table.balance = state.oranj_balances.get(identity).copied().unwrap_or(0);
+    (table_id, table)
+}
+
+/// The shared table after a transaction
+fn shared_table_view(
+    state: &BlackJack,
+    action: &BlackJackAction,
+) -> (Option<TableId>, Option<SharedTable>) {
+    let table_id = state.played_table(action);
+    let table = table_id.and_then(|table_id| state.shared_tables.get(&table_id).cloned());
+    (table_id, table)
+}
+
+fn track_tx(tracked_txs: &TrackedTxs, tx_hash: TxHash, identity: Identity, action: TrackedAction) {
+    if let Ok(mut tracked_txs) = tracked_txs.lock() {
+        let table_id = action.table_id();
+        tracked_txs.insert(
+            tx_hash,
+            TrackedTx {
+                identity,
+                action,
+                table_id,
+                status: TxStatus::Sequenced,
+                error: None,
+                updated_at: Instant::now(),
+            },
+        );
+    }
+}
+
 async fn send(
     ctx: RouterCtx,
     action: BlackJackAction,
     auth: AuthHeaders,
-    wallet_blobs: [Blob; 2],
-) -> Result<impl IntoResponse, AppError> {
+    authorisation: impl Into<Authorisation>,
+) -> Result<Response, AppError> {
+    let Authorisation {
+        wallet_blobs,
+        session,
+    } = authorisation.into();
     let identity = Identity(auth.identity);
+    verify_wallet_blobs(
+        &ctx.wallet_auth,
+        &ctx.rate_limiter,
+        &identity,
+        &wallet_blobs,
+    )
+    .await?;
     let mut blobs = vec![];
 
-    match action {
+    match action.clone() {
         BlackJackAction::Deposit(amount) => {
             handle_deposit_action(amount, &ctx, &identity, &mut blobs).await?;
         }
@@ -314,7 +1659,203 @@ async fn send(
 
     blobs.extend_from_slice(&wallet_blobs);
 
-    execute_transaction(ctx, identity, blobs).await
+    let tx_hash = submit_transaction(
+        ctx.client.as_ref(),
+        &ctx.tracked_txs,
+        identity.clone(),
+        action.clone().into(),
+        blobs,
+    )
+    .await?;
+    charge_session(&ctx, session).await;
+    if auth.respond_async {
+        return Ok(pending(tx_hash));
+    }
+    let state = wait_for_execution(&ctx.executions, &tx_hash).await?;
+
+    let (table_id, table) = table_view(&state, &identity, &action);
+    Ok(Json(Resp {
+        tx_hash: tx_hash.to_string(),
+        table_id,
+        table,
+    })
+    .into_response())
+}
+
+async fn send_shared(
+    ctx: RouterCtx,
+    action: BlackJackAction,
+    auth: AuthHeaders,
+    authorisation: impl Into<Authorisation>,
+) -> Result<Response, AppError> {
+    let Authorisation {
+        wallet_blobs,
+        session,
+    } = authorisation.into();
+    let identity = Identity(auth.identity);
+    verify_wallet_blobs(
+        &ctx.wallet_auth,
+        &ctx.rate_limiter,
+        &identity,
+        &wallet_blobs,
+    )
+    .await?;
+    let mut blobs = vec![action.as_blob(ctx.blackjack_cn.clone(), None, None)];
+    blobs.extend_from_slice(&wallet_blobs);
+
+    let tx_hash = submit_transaction(
+        ctx.client.as_ref(),
+        &ctx.tracked_txs,
+        identity.clone(),
+        action.clone().into(),
+        blobs,
+    )
+    .await?;
+    charge_session(&ctx, session).await;
+    if auth.respond_async {
+        return Ok(pending(tx_hash));
+    }
+    let state = wait_for_execution(&ctx.executions, &tx_hash).await?;
+
+    let balance = state.oranj_balances.get(&identity).copied().unwrap_or(0);
+    let (table_id, table) = shared_table_view(&state, &action);
+    Ok(Json(SharedResp {
+        tx_hash: tx_hash.to_string(),
+        table_id,
+        table,
+        balance,
+    })
+    .into_response())
+}
+
+/// Wallet blobs authorising an action, with the session to charge once it is accepted
+struct Authorisation {
+    wallet_blobs: [Blob; 2],
+    session: Option<SessionCharge>,
+}
+
+impl From<[Blob; 2]> for Authorisation {
+    fn from(wallet_blobs: [Blob; 2]) -> Self {
+        Authorisation {
+            wallet_blobs,
+            session: None,
+        }
+    }
+}
+
+/// Wallet blobs of the request, or blobs signed with the player's session key for the
+/// actions a session can play.
+async fn authorise(
+    ctx: &RouterCtx,
+    auth: &AuthHeaders,
+    action: &BlackJackAction,
+    wallet_blobs: Option<[Blob; 2]>,
+) -> Result<Authorisation, AppError> {
+    if let Some(wallet_blobs) = wallet_blobs {
+        return Ok(wallet_blobs.into());
+    }
+    let unauthorized = |e: anyhow::Error| AppError(StatusCode::UNAUTHORIZED, e);
+    let Some(session_key) = &auth.session_key else {
+        return Err(unauthorized(anyhow::anyhow!(
+            "Missing wallet blobs or session key"
+        )));
+    };
+
+    let identity = Identity(auth.identity.clone());
+    let Some(spend) = session_spend(ctx, &identity, action)? else {
+        return Err(unauthorized(anyhow::anyhow!(
+            "Session keys can only hit, stand or double down"
+        )));
+    };
+
+    let (wallet_blobs, charge) = ctx
+        .sessions
+        .lock()
+        .await
+        .authorise(&identity, session_key, spend)
+        .map_err(unauthorized)?;
+    Ok(Authorisation {
+        wallet_blobs,
+        session: Some(charge),
+    })
+}
+
+/// Charges the session of the authorisation, once its transaction is sent
+async fn charge_session(ctx: &RouterCtx, session: Option<SessionCharge>) {
+    let Some(charge) = session else {
+        return;
+    };
+    if let Err(e) = ctx.sessions.lock().await.charge(charge).await {
+        warn!("Failed to charge session: {:#}", e);
+    }
+}
+
+/// Tokens the action bets, if a session key can play it
+fn session_spend(
+    ctx: &RouterCtx,
+    identity: &Identity,
+    action: &BlackJackAction,
+) -> Result<Option<u32>, AppError> {
+    let spend = match action {
+        BlackJackAction::Hit(_) | BlackJackAction::Stand(_) => 0,
+        // Doubling down bets the table's bet once more
+        BlackJackAction::DoubleDown(table_id) => ctx
+            .latest_state
+            .read()
+            .map_err(|_| anyhow::anyhow!("State lock poisoned"))?
+            .as_ref()
+            .and_then(|state| state.get_table(identity, *table_id))
+            .map(|table| table.bet)
+            .ok_or_else(|| {
+                AppError(
+                    StatusCode::NOT_FOUND,
+                    anyhow::anyhow!("Table {table_id} not found"),
+                )
+            })?,
+        BlackJackAction::Batch(actions) => {
+            let mut spend = 0_u32;
+            for action in actions {
+                let Some(action_spend) = session_spend(ctx, identity, &action.clone().into())?
+                else {
+                    return Ok(None);
+                };
+                spend = spend.saturating_add(action_spend);
+            }
+            spend
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(spend))
+}
+
+pub(crate) async fn verify_wallet_blobs(
+    wallet_auth: &WalletAuth,
+    rate_limiter: &RateLimiter,
+    identity: &Identity,
+    wallet_blobs: &[Blob; 2],
+) -> Result<(), AppError> {
+    wallet_auth
+        .verify(identity, wallet_blobs)
+        .await
+        .map_err(|e| {
+            AppError(
+                StatusCode::UNAUTHORIZED,
+                e.context("Unauthorized wallet blobs"),
+            )
+        })?;
+    // Only an authenticated identity is counted, so that nobody can exhaust another
+    // player's requests with their identity header
+    rate_limiter.acquire_identity(identity)
+}
+
+pub(crate) fn pending(tx_hash: TxHash) -> Response {
+    (
+        StatusCode::ACCEPTED,
+        Json(PendingResp {
+            tx_hash: tx_hash.to_string(),
+        }),
+    )
+        .into_response()
 }
 
 async fn handle_deposit_action(
@@ -364,52 +1905,70 @@ async fn handle_withdraw_action(
     Ok(())
 }
 
-async fn execute_transaction(
-    ctx: RouterCtx,
+/// Sends the transaction, tracking its status for `/api/tx/{tx_hash}`
+pub(crate) async fn submit_transaction(
+    client: &dyn NodeClient,
+    tracked_txs: &TrackedTxs,
     identity: Identity,
+    action: TrackedAction,
     blobs: Vec<Blob>,
-) -> Result<impl IntoResponse, AppError> {
-    let tx_hash = ctx
-        .client
-        .send_tx_blob(BlobTransaction::new(identity.clone(), blobs))
-        .await?;
-
-    let mut bus = {
-        let app = ctx.app.lock().await;
-        AppModuleBusClient::new_from_bus(app.bus.new_handle()).await
-    };
+) -> Result<TxHash, AppError> {
+    let tx = BlobTransaction::new(identity.clone(), blobs);
+    // Tracked before sending so that events of the transaction are attributed
+    let tx_hash = tx.hashed();
+    track_tx(tracked_txs, tx_hash.clone(), identity, action);
+    if let Err(error) = client.send_tx_blob(tx).await {
+        if let Ok(mut tracked_txs) = tracked_txs.lock() {
+            tracked_txs.remove(&tx_hash);
+        }
+        return Err(error.into());
+    }
+    Ok(tx_hash)
+}
 
-    tokio::time::timeout(Duration::from_secs(5), async {
-        loop {
-            let event = bus.recv().await?;
-            match event {
-                CSIBusEvent {
-                    event: AutoProverEvent::SuccessTx(sequenced_tx_hash, state),
-                } => {
-                    if sequenced_tx_hash == tx_hash {
-                        let balance = state.oranj_balances.get(&identity).copied().unwrap_or(0);
-                        let mut table: ApiTable = state
-                            .tables
-                            .get(&identity)
-                            .cloned()
-                            .unwrap_or_default()
-                            .into();
-                        table.balance = balance;
-                        return Ok(Json(Resp {
-                            tx_hash: sequenced_tx_hash.to_string(),
-                            table,
-                        }));
-                    }
-                }
-                CSIBusEvent {
-                    event: AutoProverEvent::FailedTx(sequenced_tx_hash, error),
-                } => {
-                    if sequenced_tx_hash == tx_hash {
-                        return Err(AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(error)));
-                    }
-                }
+/// Waits for the optimistic execution of the transaction by the indexer, returning the
+/// resulting contract state.
+pub(crate) async fn wait_for_execution<State>(
+    executions: &SharedExecutions<State>,
+    tx_hash: &TxHash,
+) -> Result<State, AppError> {
+    let receiver = {
+        let mut executions = executions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Executions lock poisoned"))?;
+        let (sender, receiver) = oneshot::channel();
+        match executions.early.remove(tx_hash) {
+            // Executed before we started waiting
+            Some((result, _)) => {
+                let _ = sender.send(result);
+            }
+            None => {
+                executions.waiters.insert(tx_hash.clone(), sender);
             }
         }
-    })
-    .await?
+        receiver
+    };
+
+    let result = tokio::time::timeout(EXECUTION_TIMEOUT, receiver)
+        .await
+        .map_err(|_| {
+            // The transaction was sent and may still be executed later
+            AppError(
+                StatusCode::GATEWAY_TIMEOUT,
+                anyhow::anyhow!(
+                    "Transaction {tx_hash} not executed yet, follow it on /api/tx/{tx_hash}"
+                ),
+            )
+        })??;
+    result.map_err(|error| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(error)))
+}
+
+/// Drops the executions no request came to wait for, e.g. those of `respond-async` ones
+fn prune_early_executions<State>(executions: &SharedExecutions<State>) {
+    if let Ok(mut executions) = executions.lock() {
+        executions
+            .early
+            .retain(|_, (_, executed_at)| executed_at.elapsed() < EXECUTION_TIMEOUT);
+        executions.waiters.retain(|_, waiter| !waiter.is_closed());
+    }
 }
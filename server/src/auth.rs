@@ -0,0 +1,295 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use hyle_wallet::WalletAction;
+use k256::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+use sdk::{verifiers::Secp256k1Blob, Blob, Identity};
+use server::wallet::{session_key_message, WalletIndexer, SECP256K1_CONTRACT};
+use tokio::sync::Mutex;
+
+pub const NONCES_FILE: &str = "wallet_nonces.bin";
+
+type Nonces = BTreeMap<(Identity, String), u128>;
+
+/// Checks that the wallet blobs attached to requests authorise the identity header, with a
+/// session key registered for the account in the wallet contract.
+///
+/// Each signed nonce is accepted once, so that blobs seen in a transaction cannot be
+/// attached to another request. The nonces are persisted, restarts included.
+pub struct WalletAuth {
+    indexer: Arc<dyn WalletIndexer>,
+    /// Highest nonce accepted for each identity and session key, held until it is persisted
+    nonces: Mutex<Nonces>,
+    path: PathBuf,
+}
+
+/// Session key use authorised by a pair of wallet blobs
+struct SignedNonce<'a> {
+    wallet_contract: &'a str,
+    account: &'a str,
+    /// Hex-encoded compressed public key
+    public_key: String,
+    nonce: u128,
+}
+
+impl WalletAuth {
+    /// Loads the nonces accepted so far from `data_directory`
+    pub fn open(indexer: Arc<dyn WalletIndexer>, data_directory: &Path) -> Result<Self> {
+        let path = data_directory.join(NONCES_FILE);
+        let nonces = if path.exists() {
+            let file = std::fs::read(&path).context("Reading wallet nonces")?;
+            borsh::from_slice(&file).context("Failed to decode wallet nonces")?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(WalletAuth {
+            indexer,
+            nonces: Mutex::new(nonces),
+            path,
+        })
+    }
+
+    pub async fn verify(&self, identity: &Identity, wallet_blobs: &[Blob; 2]) -> Result<()> {
+        let signed = verify_signature(identity, wallet_blobs)?;
+
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let registered = self
+            .indexer
+            .session_keys(signed.wallet_contract, signed.account)
+            .await
+            .context("Fetching the session keys of the account")?;
+        if !registered.iter().any(|session_key| {
+            session_key.key.eq_ignore_ascii_case(&signed.public_key)
+                && session_key.expiration_date > now_ms
+        }) {
+            bail!(
+                "Key {} is not a session key of {identity}",
+                signed.public_key
+            );
+        }
+
+        let mut nonces = self.nonces.lock().await;
+        let last_nonce = nonces
+            .entry((identity.clone(), signed.public_key))
+            .or_default();
+        if signed.nonce <= *last_nonce {
+            bail!("Nonce {} was already used", signed.nonce);
+        }
+        *last_nonce = signed.nonce;
+        // Written before the request goes on, under the lock so that writes land in order
+        let bytes = borsh::to_vec(&*nonces).context("Failed to encode wallet nonces")?;
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || save(&path, &bytes))
+            .await
+            .context("Writing wallet nonces")??;
+        Ok(())
+    }
+}
+
+fn save(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("bin.tmp");
+    std::fs::write(&tmp, bytes).context("Writing wallet nonces")?;
+    std::fs::rename(&tmp, path).context("Writing wallet nonces")
+}
+
+/// Checks the blobs that can be checked without the wallet contract: the wallet blob must
+/// use a session key of the account named in the identity (`account@wallet`), and the
+/// secp256k1 blob must be that key's signature of the nonce, made for the identity.
+fn verify_signature<'a>(
+    identity: &'a Identity,
+    wallet_blobs: &[Blob; 2],
+) -> Result<SignedNonce<'a>> {
+    let Some((account, wallet_contract)) = identity.0.rsplit_once('@') else {
+        bail!("Identity {identity} is not a wallet identity");
+    };
+
+    let secp256k1_blob = wallet_blobs
+        .iter()
+        .find(|blob| blob.contract_name.0 == SECP256K1_CONTRACT)
+        .ok_or_else(|| anyhow!("Missing secp256k1 blob"))?;
+    let wallet_blob = wallet_blobs
+        .iter()
+        .find(|blob| blob.contract_name.0 == wallet_contract)
+        .ok_or_else(|| anyhow!("Missing {wallet_contract} blob"))?;
+
+    let WalletAction::UseSessionKey {
+        account: used_by,
+        nonce,
+    } = borsh::from_slice(&wallet_blob.data.0).context("Invalid wallet blob")?
+    else {
+        bail!("Wallet blob does not use a session key");
+    };
+    if used_by != account {
+        bail!("Wallet blob uses a session key of {used_by}, not {account}");
+    }
+
+    let secp256k1: Secp256k1Blob =
+        borsh::from_slice(&secp256k1_blob.data.0).context("Invalid secp256k1 blob")?;
+    if &secp256k1.identity != identity {
+        bail!(
+            "Wallet blobs are signed for {}, not {identity}",
+            secp256k1.identity
+        );
+    }
+    // Ties the signature to the nonce the wallet blob uses
+    if secp256k1.data != session_key_message(nonce) {
+        bail!("Signed message is not the nonce of the wallet blob");
+    }
+
+    let public_key =
+        VerifyingKey::from_sec1_bytes(&secp256k1.public_key).context("Invalid public key")?;
+    let signature = Signature::from_slice(&secp256k1.signature).context("Invalid signature")?;
+    public_key
+        .verify_prehash(&secp256k1.data, &signature)
+        .context("Invalid signature")?;
+
+    Ok(SignedNonce {
+        wallet_contract,
+        account,
+        public_key: hex::encode(secp256k1.public_key),
+        nonce,
+    })
+}
+
+/// Wallet indexer of the tests, knowing the session keys registered with `register`
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockWallet {
+    session_keys: std::sync::Mutex<BTreeMap<Identity, Vec<String>>>,
+}
+
+#[cfg(test)]
+impl MockWallet {
+    /// Registers the hex-encoded compressed public key as a session key of the identity
+    pub fn register(&self, identity: &Identity, public_key: String) {
+        self.session_keys
+            .lock()
+            .unwrap()
+            .entry(identity.clone())
+            .or_default()
+            .push(public_key);
+    }
+}
+
+#[cfg(test)]
+impl WalletIndexer for MockWallet {
+    fn session_keys(
+        &self,
+        wallet_contract: &str,
+        account: &str,
+    ) -> server::node_client::NodeFuture<'_, Vec<server::wallet::RegisteredSessionKey>> {
+        let identity = Identity(format!("{account}@{wallet_contract}"));
+        let keys = self
+            .session_keys
+            .lock()
+            .unwrap()
+            .get(&identity)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|key| server::wallet::RegisteredSessionKey {
+                key,
+                expiration_date: u128::MAX,
+            })
+            .collect();
+        Box::pin(async move { Ok(keys) })
+    }
+}
+
+#[cfg(test)]
+fn signing_key(seed: u8) -> k256::ecdsa::SigningKey {
+    k256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap()
+}
+
+#[cfg(test)]
+fn public_key(seed: u8) -> String {
+    hex::encode(signing_key(seed).verifying_key().to_sec1_bytes())
+}
+
+#[cfg(test)]
+fn test_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("{name}-{}", rand::random::<u64>()));
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[tokio::test]
+async fn test_wallet_blobs_authorise_identity() {
+    use server::wallet::session_key_blobs;
+
+    let alice: Identity = "alice@wallet".into();
+    let wallet = Arc::new(MockWallet::default());
+    let data_directory = test_directory("wallet-auth");
+    let auth = WalletAuth::open(wallet.clone(), &data_directory).unwrap();
+    let key = signing_key(7);
+
+    // Signed with a key the wallet does not know
+    let blobs = session_key_blobs(&alice, &key, 1).unwrap();
+    assert!(auth.verify(&alice, &blobs).await.is_err());
+
+    wallet.register(&alice, public_key(7));
+    assert!(auth.verify(&alice, &blobs).await.is_ok());
+    // A nonce is used once
+    assert!(auth.verify(&alice, &blobs).await.is_err());
+    let blobs = session_key_blobs(&alice, &key, 2).unwrap();
+    assert!(auth.verify(&alice, &blobs).await.is_ok());
+
+    // Nor after a restart
+    let auth = WalletAuth::open(wallet.clone(), &data_directory).unwrap();
+    assert!(auth.verify(&alice, &blobs).await.is_err());
+    let blobs = session_key_blobs(&alice, &key, 3).unwrap();
+    assert!(auth.verify(&alice, &blobs).await.is_ok());
+
+    std::fs::remove_dir_all(&data_directory).unwrap();
+}
+
+#[tokio::test]
+async fn test_forged_wallet_blobs_are_rejected() {
+    use server::wallet::session_key_blobs;
+
+    let alice: Identity = "alice@wallet".into();
+    let mallory: Identity = "mallory@wallet".into();
+    let wallet = Arc::new(MockWallet::default());
+    let data_directory = test_directory("wallet-auth");
+    let auth = WalletAuth::open(wallet.clone(), &data_directory).unwrap();
+    wallet.register(&alice, public_key(7));
+    wallet.register(&mallory, public_key(8));
+
+    // Mallory's own blobs sent with Alice's identity header
+    let blobs = session_key_blobs(&mallory, &signing_key(8), 1).unwrap();
+    assert!(auth.verify(&alice, &blobs).await.is_err());
+
+    // Signed by Mallory's key for Alice, a key Alice never registered
+    let blobs = session_key_blobs(&alice, &signing_key(8), 1).unwrap();
+    assert!(auth.verify(&alice, &blobs).await.is_err());
+
+    // Alice's blobs with a tampered signature
+    let mut blobs = session_key_blobs(&alice, &signing_key(7), 1).unwrap();
+    let last = blobs[0].data.0.len() - 1;
+    blobs[0].data.0[last] ^= 1;
+    assert!(auth.verify(&alice, &blobs).await.is_err());
+
+    // Alice's signature attached to a wallet blob with another nonce
+    let mut blobs = session_key_blobs(&alice, &signing_key(7), 2).unwrap();
+    blobs[1] = session_key_blobs(&alice, &signing_key(7), 3).unwrap()[1].clone();
+    assert!(auth.verify(&alice, &blobs).await.is_err());
+
+    // Blobs for another wallet contract
+    let other: Identity = "alice@other_wallet".into();
+    let blobs = session_key_blobs(&other, &signing_key(7), 4).unwrap();
+    assert!(auth.verify(&alice, &blobs).await.is_err());
+
+    // Not a wallet identity at all
+    let operator: Identity = "operator".into();
+    assert!(auth.verify(&operator, &blobs).await.is_err());
+
+    std::fs::remove_dir_all(&data_directory).unwrap();
+}
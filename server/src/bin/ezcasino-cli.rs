@@ -0,0 +1,509 @@
+use std::{
+    io::{BufRead, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use blackjack::{
+    simulation::{
+        simulate, AlwaysStand, BasicStrategy, Payouts, RandomStrategy, SimulationConfig,
+        SimulationReport, Strategy,
+    },
+    BlackJack, BlackJackConfig, Table, TableId, TableState,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use client_sdk::rest_client::NodeApiHttpClient;
+use ezcasino_client::{ApiTable, Authorisation, EzCasinoClient};
+use hyle_modules::{
+    bus::{metrics::BusMetrics, BusClientReceiver, SharedMessageBus},
+    module_bus_client,
+    modules::{
+        da_listener::{DAListener, DAListenerConf},
+        ModulesHandler,
+    },
+    node_state::module::NodeStateEvent,
+};
+use k256::ecdsa::SigningKey;
+use sdk::{Blob, Block, Identity};
+use server::{
+    conf::Conf,
+    init,
+    replay::{read_dump, DumpWriter, Replayer},
+    wallet::session_key_blobs,
+};
+
+module_bus_client! {
+#[derive(Debug)]
+struct ReplayBusClient {
+    receiver(NodeStateEvent),
+}
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Operate and debug the ezcasino blackjack contract", long_about = None)]
+struct Args {
+    #[arg(long, default_value = "config.toml")]
+    config_file: Vec<String>,
+
+    #[arg(long, default_value = blackjack::CONTRACT_NAME)]
+    contract_name: String,
+
+    /// URL of the ezcasino server, defaults to the local one from the config
+    #[arg(long)]
+    server_url: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Wallet identity of the player, authorised with one of its session keys
+#[derive(clap::Args, Debug)]
+struct Player {
+    #[arg(long)]
+    identity: String,
+
+    /// Hex-encoded secret of a session key registered in the player's wallet
+    #[arg(long, env = "EZCASINO_SESSION_KEY")]
+    session_key: String,
+}
+
+impl Player {
+    fn identity(&self) -> Identity {
+        Identity(self.identity.clone())
+    }
+
+    /// Fresh wallet blobs for the next transaction
+    fn wallet_blobs(&self) -> Result<[Blob; 2]> {
+        let signing_key = SigningKey::from_slice(&hex::decode(&self.session_key)?)
+            .context("Invalid session key")?;
+        let nonce = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        session_key_blobs(&self.identity(), &signing_key, nonce)
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum StrategyKind {
+    Basic,
+    AlwaysStand,
+    Random,
+}
+
+impl StrategyKind {
+    fn build(self, seed: u64) -> Box<dyn Strategy> {
+        match self {
+            StrategyKind::Basic => Box::new(BasicStrategy::default()),
+            StrategyKind::AlwaysStand => Box::new(AlwaysStand),
+            StrategyKind::Random => Box::new(RandomStrategy::new(seed)),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Registers the contract on the node, or checks the registered one is up to date
+    Register,
+    Deposit {
+        #[command(flatten)]
+        player: Player,
+        amount: u32,
+    },
+    Withdraw {
+        #[command(flatten)]
+        player: Player,
+        amount: u32,
+        #[arg(default_value = "oranj")]
+        token: String,
+    },
+    /// Plays a hand interactively
+    Play {
+        #[command(flatten)]
+        player: Player,
+        bet: u32,
+    },
+    /// Dumps the contract state as JSON
+    State,
+    /// Shows the tables of a player
+    Table {
+        identity: String,
+        table_id: Option<TableId>,
+    },
+    /// Simulates hands offline to measure the return to player of the contract's rules, or of
+    /// other payouts
+    Simulate {
+        #[arg(long, default_value_t = 1_000_000)]
+        hands: u64,
+        /// Bets to simulate, one report each
+        #[arg(long, value_delimiter = ',', default_value = "10")]
+        bet: Vec<u32>,
+        /// Strategies to simulate, one report each
+        #[arg(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            default_value = "basic,always-stand,random"
+        )]
+        strategy: Vec<StrategyKind>,
+        /// Winnings of a blackjack to simulate, in basis points of the bet, one report each
+        #[arg(long, value_delimiter = ',', default_value = "10000")]
+        blackjack_payout_bps: Vec<u32>,
+        /// Winnings of any other win to simulate, in basis points of the bet, one report each
+        #[arg(long, value_delimiter = ',', default_value = "10000")]
+        win_payout_bps: Vec<u32>,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Prints the reports as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Replays the settled blackjack transactions from genesis, reporting the executions
+    /// of the blocks in the range
+    Replay {
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+        /// Last block to replay. Reading from the DA, follows it until interrupted when unset.
+        #[arg(long)]
+        to: Option<u64>,
+        /// Replays the blocks of a dump file instead of reading the DA
+        #[arg(long)]
+        dump_file: Option<PathBuf>,
+        /// Dumps the blocks read from the DA to this file
+        #[arg(long, conflicts_with = "dump_file")]
+        write_dump: Option<PathBuf>,
+        /// Prints the executions as JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let config = Conf::new(args.config_file).context("reading config file")?;
+    let server_url = args
+        .server_url
+        .unwrap_or_else(|| format!("http://localhost:{}", config.rest_server_port));
+    let client = EzCasinoClient::new(server_url).with_contract_name(&args.contract_name);
+
+    match args.command {
+        Command::Register => {
+            let node =
+                NodeApiHttpClient::new(config.node_url.clone()).context("build node client")?;
+            let blackjack_config = config.blackjack_config();
+            init::init_contract(&node, args.contract_name.into(), blackjack_config).await?;
+        }
+        Command::Deposit { player, amount } => {
+            let resp = client
+                .deposit(&player.identity(), player.wallet_blobs()?, amount)
+                .await?;
+            println!("Deposited {amount} in tx {}", resp.tx_hash);
+            println!("Balance: {}", resp.table.balance);
+        }
+        Command::Withdraw {
+            player,
+            amount,
+            token,
+        } => {
+            let resp = client
+                .withdraw(&player.identity(), player.wallet_blobs()?, amount, &token)
+                .await?;
+            println!("Withdrew {amount} {token} in tx {}", resp.tx_hash);
+            println!("Balance: {}", resp.table.balance);
+        }
+        Command::Play { player, bet } => play(&client, &player, bet).await?,
+        Command::State => {
+            let state = client.state().await?;
+            println!("{}", serde_json::to_string_pretty(&state)?);
+        }
+        Command::Table { identity, table_id } => {
+            let state = client.state().await?;
+            print_tables(&state, &Identity(identity), table_id)?;
+        }
+        Command::Simulate {
+            hands,
+            bet,
+            strategy,
+            blackjack_payout_bps,
+            win_payout_bps,
+            seed,
+            json,
+        } => {
+            let payouts: Vec<Payouts> = blackjack_payout_bps
+                .iter()
+                .flat_map(|&blackjack_bps| {
+                    win_payout_bps.iter().map(move |&win_bps| Payouts {
+                        blackjack_bps,
+                        win_bps,
+                    })
+                })
+                .collect();
+            run_simulations(hands, &bet, &payouts, &strategy, seed, json)?
+        }
+        Command::Replay {
+            from,
+            to,
+            dump_file,
+            write_dump,
+            json,
+        } => {
+            let mut replay = ReplayRun {
+                replayer: Replayer::new(args.contract_name.into(), config.blackjack_config()),
+                from,
+                to,
+                json,
+                dump: write_dump.as_deref().map(DumpWriter::create).transpose()?,
+            };
+            match dump_file {
+                Some(dump_file) => {
+                    for block in read_dump(&dump_file)? {
+                        if !replay.block(&block)? {
+                            break;
+                        }
+                    }
+                }
+                None => replay_da(&config, &mut replay).await?,
+            }
+            println!(
+                "Final state commitment: {}",
+                hex::encode(replay.replayer.state_commitment().0)
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn play(client: &EzCasinoClient, player: &Player, bet: u32) -> Result<()> {
+    let identity = player.identity();
+    let mut resp = client.init(&identity, player.wallet_blobs()?, bet).await?;
+    let Some(table_id) = resp.table_id else {
+        bail!("The server did not report the new table");
+    };
+    println!("Started table {table_id} with a bet of {bet}");
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    while matches!(resp.table.state, TableState::Ongoing) {
+        print_table(&resp.table);
+        print!("[h]it, [s]tand or [d]ouble down? ");
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            println!();
+            println!("Table {table_id} left ongoing");
+            return Ok(());
+        };
+        let auth = Authorisation::WalletBlobs(player.wallet_blobs()?);
+        let result = match line?.trim() {
+            "h" | "hit" => client.hit(&identity, auth, table_id).await,
+            "s" | "stand" => client.stand(&identity, auth, table_id).await,
+            "d" | "double" => client.double_down(&identity, auth, table_id).await,
+            other => {
+                println!("Unknown action {other:?}");
+                continue;
+            }
+        };
+        match result {
+            Ok(next) => resp = next,
+            Err(e) => println!("Action failed: {e:#}"),
+        }
+    }
+
+    print_table(&resp.table);
+    println!(
+        "{:?}, balance is now {}",
+        resp.table.state, resp.table.balance
+    );
+    Ok(())
+}
+
+struct ReplayRun {
+    replayer: Replayer,
+    from: u64,
+    to: Option<u64>,
+    json: bool,
+    dump: Option<DumpWriter>,
+}
+
+impl ReplayRun {
+    /// Replays the block, and returns whether the next ones are still in the range
+    fn block(&mut self, block: &Block) -> Result<bool> {
+        let height = block.block_height.0;
+        if self.to.is_some_and(|to| height > to) {
+            return Ok(false);
+        }
+        if let Some(dump) = &mut self.dump {
+            dump.write(block)?;
+        }
+
+        let steps = self.replayer.replay_block(block)?;
+        if height >= self.from {
+            for step in steps {
+                if self.json {
+                    println!("{}", serde_json::to_string(&step)?);
+                    continue;
+                }
+                let proof = match step.matches_proof {
+                    Some(true) => "matches proof",
+                    Some(false) => "DIFFERS FROM PROOF",
+                    None => "no proof seen",
+                };
+                println!(
+                    "#{} {} blob {} by {}: {} -> {} ({proof})",
+                    step.block_height,
+                    step.tx_hash,
+                    step.blob_index,
+                    step.identity,
+                    step.initial_state,
+                    step.next_state
+                );
+                println!(
+                    "  {}: {}",
+                    if step.success { "ok" } else { "failed" },
+                    step.output
+                );
+            }
+        }
+        Ok(self.to.is_none_or(|to| height < to))
+    }
+}
+
+/// Replays the blocks streamed by the DA, processed by a node state of its own
+async fn replay_da(config: &Conf, replay: &mut ReplayRun) -> Result<()> {
+    let data_directory =
+        std::env::temp_dir().join(format!("ezcasino-replay-{}", rand::random::<u64>()));
+    std::fs::create_dir_all(&data_directory).context("creating data directory")?;
+
+    let bus = SharedMessageBus::new(BusMetrics::global("ezcasino-cli".to_string()));
+    let mut blocks = ReplayBusClient::new_from_bus(bus.new_handle()).await;
+    let mut handler = ModulesHandler::new(&bus).await;
+    handler
+        .build_module::<DAListener>(DAListenerConf {
+            start_block: None,
+            data_directory: data_directory.clone(),
+            da_read_from: config.da_read_from.clone(),
+            timeout_client_secs: 10,
+        })
+        .await?;
+    handler.start_modules().await?;
+
+    let result = follow_da(&mut blocks, replay).await;
+    std::fs::remove_dir_all(&data_directory).context("removing data directory")?;
+    result
+}
+
+async fn follow_da(blocks: &mut ReplayBusClient, replay: &mut ReplayRun) -> Result<()> {
+    loop {
+        tokio::select! {
+            event = blocks.recv() => {
+                let NodeStateEvent::NewBlock(block) = event?;
+                if !replay.block(&block)? {
+                    return Ok(());
+                }
+            }
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
+fn run_simulations(
+    hands: u64,
+    bets: &[u32],
+    payouts: &[Payouts],
+    strategies: &[StrategyKind],
+    seed: u64,
+    json: bool,
+) -> Result<()> {
+    let mut reports = vec![];
+    for bet in bets {
+        for payouts in payouts {
+            for kind in strategies {
+                let config = SimulationConfig {
+                    hands,
+                    bet: *bet,
+                    seed,
+                    contract: BlackJackConfig::default(),
+                    payouts: *payouts,
+                };
+                let report =
+                    simulate(&config, kind.build(seed).as_mut()).map_err(|e| anyhow::anyhow!(e))?;
+                if !json {
+                    print_report(&report);
+                }
+                reports.push(report);
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    }
+    Ok(())
+}
+
+fn print_report(report: &SimulationReport) {
+    let percent = |count: u64| 100.0 * count as f64 / report.hands.max(1) as f64;
+    let outcomes = &report.outcomes;
+    println!(
+        "{} strategy, bet {}, {} hands, blackjack pays {:.2}, win pays {:.2}",
+        report.strategy,
+        report.bet,
+        report.hands,
+        report.payouts.blackjack_bps as f64 / 10_000.0,
+        report.payouts.win_bps as f64 / 10_000.0
+    );
+    println!(
+        "  RTP {:.3}%  house edge {:.3}%  mean {:+.4}  variance {:.4}  std dev {:.4}",
+        100.0 * report.rtp,
+        100.0 * report.house_edge,
+        report.mean,
+        report.variance,
+        report.std_dev()
+    );
+    println!(
+        "  blackjack {:.2}%  win {:.2}%  push {:.2}%  loss {:.2}%  doubled {:.2}%",
+        percent(outcomes.blackjacks),
+        percent(outcomes.wins),
+        percent(outcomes.pushes),
+        percent(outcomes.losses),
+        percent(outcomes.doubled)
+    );
+}
+
+fn print_table(table: &ApiTable) {
+    println!(
+        "  Bank: {:?} ({})  You: {:?} ({})  Bet: {}",
+        table.bank, table.bank_count, table.user, table.user_count, table.bet
+    );
+}
+
+fn print_tables(state: &BlackJack, identity: &Identity, table_id: Option<TableId>) -> Result<()> {
+    let tables = state.tables.get(identity);
+    let tables: Vec<(&TableId, &Table)> = match table_id {
+        Some(table_id) => tables
+            .and_then(|tables| tables.get_key_value(&table_id))
+            .into_iter()
+            .collect(),
+        None => tables.into_iter().flatten().collect(),
+    };
+    if tables.is_empty() {
+        bail!("No table found for {identity}");
+    }
+
+    for (table_id, table) in tables {
+        println!(
+            "Table {table_id}: {:?}, bet {}, started at block {}",
+            table.state, table.bet, table.started_at
+        );
+        println!(
+            "  Bank: {:?} ({})  Player: {:?} ({})",
+            table.bank,
+            BlackJack::compute_score(&table.bank),
+            table.user,
+            BlackJack::compute_score(&table.user)
+        );
+    }
+    println!(
+        "Balance: {} oranj, {} vitamin",
+        state.oranj_balances.get(identity).copied().unwrap_or(0),
+        state.vitamin_balances.get(identity).copied().unwrap_or(0)
+    );
+    Ok(())
+}
@@ -1,7 +1,19 @@
+use blackjack::BlackJackConfig;
 use config::{Config, Environment, File};
+use dice::DiceConfig;
+use sdk::Identity;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CorsConf {
+    /// Origins allowed to call the API, `*` allows any
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers allowed in cross-origin calls, `*` allows any
+    pub allowed_headers: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Conf {
     /// The log format to use - "json", "node" or "full" (default)
@@ -11,6 +23,8 @@ pub struct Conf {
     /// When running only the indexer, the address of the DA server to connect to
     pub da_read_from: String,
     pub node_url: String,
+    /// Indexer of the wallet contract, checked for the session keys signing the wallet blobs
+    pub wallet_indexer_url: String,
 
     pub rest_server_port: u16,
     pub rest_server_max_body_size: usize,
@@ -19,6 +33,35 @@ pub struct Conf {
     pub max_txs_per_proof: usize,
     pub tx_working_window_size: usize,
 
+    /// Identity allowed to clean the contract state. Cleaning is disabled when unset.
+    pub operator_identity: Option<String>,
+    /// Ongoing tables older than this many blocks are refunded when cleaning the state.
+    /// Requires `game_timeout_blocks`, and can't be shorter.
+    pub clean_expire_after_blocks: Option<u64>,
+    /// Ongoing tables older than this many blocks can be auto-stood by anyone
+    pub game_timeout_blocks: Option<u64>,
+    /// Share of the fair dice payout kept by the house, in basis points
+    pub dice_house_edge_bps: u32,
+
+    /// Requests allowed per minute for each identity. Unlimited when unset.
+    pub rate_limit_identity_per_minute: Option<u32>,
+    /// Requests allowed per minute for each IP address. Unlimited when unset, and requires
+    /// `rate_limit_trusted_proxies`.
+    pub rate_limit_ip_per_minute: Option<u32>,
+    /// Reverse proxies in front of the server appending to the `X-Forwarded-For` header. The
+    /// client address is the entry this many hops from the right.
+    pub rate_limit_trusted_proxies: usize,
+
+    /// Hex-encoded 32 bytes key sealing the session keys persisted under the data directory.
+    /// Sessions are only kept in memory when unset.
+    pub session_encryption_key: Option<String>,
+
+    pub cors: CorsConf,
+    /// Key giving access to the administrative routes, sent in the `x-api-key` header
+    pub admin_api_key: Option<String>,
+    /// Identities given access to the administrative routes, besides the operator
+    pub admin_identities: Vec<String>,
+
     pub run_admin_server: bool,
     pub admin_server_port: u16,
     pub admin_server_max_body_size: usize,
@@ -40,10 +83,46 @@ impl Conf {
                     .separator("__")
                     .prefix_separator("_")
                     .list_separator(",")
+                    .with_list_parse_key("cors.allowed_origins")
+                    .with_list_parse_key("cors.allowed_methods")
+                    .with_list_parse_key("cors.allowed_headers")
+                    .with_list_parse_key("admin_identities")
                     .try_parsing(true),
             )
             .build()?
             .try_deserialize()?;
+        conf.validate()?;
         Ok(conf)
     }
+
+    /// Rejects the settings the contracts would refuse
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        self.dice_config().validate().map_err(anyhow::Error::msg)?;
+        if let Some(expire_after) = self.clean_expire_after_blocks {
+            if self
+                .game_timeout_blocks
+                .is_none_or(|timeout_blocks| expire_after < timeout_blocks)
+            {
+                anyhow::bail!(
+                    "clean_expire_after_blocks requires a game_timeout_blocks no longer than it"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Settings the dice contract is registered with
+    pub fn dice_config(&self) -> DiceConfig {
+        DiceConfig {
+            house_edge_bps: self.dice_house_edge_bps,
+        }
+    }
+
+    /// Settings the blackjack contract is registered with
+    pub fn blackjack_config(&self) -> BlackJackConfig {
+        BlackJackConfig {
+            operator: self.operator_identity.clone().map(Identity),
+            game_timeout_blocks: self.game_timeout_blocks,
+        }
+    }
 }
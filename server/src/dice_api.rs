@@ -0,0 +1,307 @@
+//! Routes playing the dice contract, answering with the roll and balances once the
+//! indexer executed the transaction.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Json, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use dice::{
+    winning_faces, Dice, DiceAction, DiceConfig, Roll, MAX_WINNING_FACES, MIN_WINNING_FACES,
+};
+use hyle_smt_token::SmtTokenAction;
+use sdk::{Blob, BlobIndex, ContractAction, ContractName, Identity};
+use serde::{Deserialize, Serialize};
+use server::node_client::NodeClient;
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    app::{
+        pending, submit_transaction, verify_wallet_blobs, wait_for_execution, AuthHeaders,
+        PendingResp, SharedExecutions, TrackedAction, TrackedTxs,
+    },
+    auth::WalletAuth,
+    rate_limit::RateLimiter,
+    utils::AppError,
+};
+
+#[derive(Clone)]
+pub struct DiceCtx {
+    pub client: Arc<dyn NodeClient>,
+    pub dice_cn: ContractName,
+    pub config: DiceConfig,
+    pub tracked_txs: TrackedTxs,
+    pub executions: SharedExecutions<Dice>,
+    pub wallet_auth: Arc<WalletAuth>,
+    pub rate_limiter: Arc<RateLimiter>,
+}
+
+/// Routes of the dice game, merged into the app router to share its layers
+pub fn router(ctx: DiceCtx) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(roll))
+        .routes(routes!(deposit))
+        .routes(routes!(withdraw))
+        .routes(routes!(odds))
+        .with_state(ctx)
+}
+
+// --------------------------------------------------------
+//     Types
+// --------------------------------------------------------
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct DiceResp {
+    pub tx_hash: String,
+    /// Roll played by the transaction, for `/api/dice/roll`
+    #[schema(value_type = Option<Object>)]
+    pub roll: Option<Roll>,
+    pub oranj: u32,
+    pub vitamin: u32,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct OddsResp {
+    /// Chance to win, in percent
+    pub win_chance: u32,
+    pub house_edge_bps: u32,
+    /// Tokens returned per token bet, stake included, in basis points
+    pub multiplier_bps: u32,
+    /// Tokens returned for `bet` when given
+    pub payout: Option<u32>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RollRequest {
+    #[schema(value_type = Vec<Object>)]
+    wallet_blobs: [Blob; 2],
+    bet: u32,
+    /// Face from 0 to 99 the roll must land over, or under
+    target: u8,
+    over: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct DepositRequest {
+    #[schema(value_type = Vec<Object>)]
+    wallet_blobs: [Blob; 2],
+    deposit: u32,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct WithdrawRequest {
+    #[schema(value_type = Vec<Object>)]
+    wallet_blobs: [Blob; 2],
+    withdraw: u32,
+    token: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct OddsQuery {
+    target: u8,
+    over: bool,
+    bet: Option<u32>,
+}
+
+// --------------------------------------------------------
+//     Routes
+// --------------------------------------------------------
+
+#[utoipa::path(
+    post,
+    path = "/api/dice/roll",
+    tag = "Dice",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = RollRequest,
+    responses(
+        (status = OK, description = "Roll and balances after the transaction", body = DiceResp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
+async fn roll(
+    State(ctx): State<DiceCtx>,
+    headers: HeaderMap,
+    Json(request): Json<RollRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    // Rejected here rather than by a failed transaction
+    if ctx
+        .config
+        .payout(request.bet, request.target, request.over)
+        .is_none()
+    {
+        return Err(unavailable_odds());
+    }
+    send(
+        ctx,
+        DiceAction::Roll(request.bet, request.target, request.over),
+        auth,
+        request.wallet_blobs,
+    )
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/dice/deposit",
+    tag = "Dice",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = DepositRequest,
+    responses(
+        (status = OK, description = "Balances after the transaction", body = DiceResp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
+async fn deposit(
+    State(ctx): State<DiceCtx>,
+    headers: HeaderMap,
+    Json(request): Json<DepositRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    send(
+        ctx,
+        DiceAction::Deposit(request.deposit),
+        auth,
+        request.wallet_blobs,
+    )
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/dice/withdraw",
+    tag = "Dice",
+    params(
+        ("x-identity" = String, Header, description = "Player identity"),
+    ),
+    request_body = WithdrawRequest,
+    responses(
+        (status = OK, description = "Balances after the transaction", body = DiceResp),
+        (status = ACCEPTED, description = "Transaction sent, with `Prefer: respond-async`", body = PendingResp),
+    )
+)]
+async fn withdraw(
+    State(ctx): State<DiceCtx>,
+    headers: HeaderMap,
+    Json(request): Json<WithdrawRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers)?;
+    send(
+        ctx,
+        DiceAction::Withdraw(request.withdraw, request.token),
+        auth,
+        request.wallet_blobs,
+    )
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/dice/odds",
+    tag = "Dice",
+    params(
+        OddsQuery,
+    ),
+    responses(
+        (status = OK, description = "Chance to win and payout of a roll", body = OddsResp),
+        (status = BAD_REQUEST, description = "Win chance not offered"),
+    )
+)]
+async fn odds(
+    State(ctx): State<DiceCtx>,
+    Query(query): Query<OddsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let multiplier_bps = ctx
+        .config
+        .payout(10_000, query.target, query.over)
+        .ok_or_else(unavailable_odds)?;
+    Ok(Json(OddsResp {
+        win_chance: winning_faces(query.target, query.over),
+        house_edge_bps: ctx.config.house_edge_bps,
+        multiplier_bps,
+        payout: query
+            .bet
+            .and_then(|bet| ctx.config.payout(bet, query.target, query.over)),
+    }))
+}
+
+fn unavailable_odds() -> AppError {
+    AppError(
+        StatusCode::BAD_REQUEST,
+        anyhow::anyhow!("Win chance must be between {MIN_WINNING_FACES}% and {MAX_WINNING_FACES}%"),
+    )
+}
+
+/// Sends the action with the token transfer it needs, then waits for its optimistic
+/// execution unless the request prefers to respond asynchronously
+async fn send(
+    ctx: DiceCtx,
+    action: DiceAction,
+    auth: AuthHeaders,
+    wallet_blobs: [Blob; 2],
+) -> Result<Response, AppError> {
+    let identity = Identity(auth.identity);
+    verify_wallet_blobs(
+        &ctx.wallet_auth,
+        &ctx.rate_limiter,
+        &identity,
+        &wallet_blobs,
+    )
+    .await?;
+
+    let mut blobs = match action.clone() {
+        DiceAction::Deposit(amount) => vec![
+            action.as_blob(ctx.dice_cn.clone(), None, None),
+            SmtTokenAction::Transfer {
+                sender: identity.clone(),
+                recipient: ctx.dice_cn.0.clone().into(),
+                amount: amount as u128,
+            }
+            .as_blob("oranj".into(), None, None),
+        ],
+        DiceAction::Withdraw(amount, token) => vec![
+            action.as_blob(ctx.dice_cn.clone(), None, Some(vec![BlobIndex(1)])),
+            SmtTokenAction::Transfer {
+                sender: ctx.dice_cn.0.clone().into(),
+                recipient: identity.clone(),
+                amount: amount as u128,
+            }
+            .as_blob(token.into(), Some(BlobIndex(0)), None),
+        ],
+        DiceAction::Roll(..) => vec![action.as_blob(ctx.dice_cn.clone(), None, None)],
+    };
+    blobs.extend_from_slice(&wallet_blobs);
+
+    let tx_hash = submit_transaction(
+        ctx.client.as_ref(),
+        &ctx.tracked_txs,
+        identity.clone(),
+        TrackedAction::Dice,
+        blobs,
+    )
+    .await?;
+    if auth.respond_async {
+        return Ok(pending(tx_hash));
+    }
+    let state = wait_for_execution(&ctx.executions, &tx_hash).await?;
+
+    let roll = match action {
+        DiceAction::Roll(..) => state.rolls.get(&identity).cloned(),
+        _ => None,
+    };
+    Ok(Json(DiceResp {
+        tx_hash: tx_hash.to_string(),
+        roll,
+        oranj: state.oranj_balances.get(&identity).copied().unwrap_or(0),
+        vitamin: state.vitamin_balances.get(&identity).copied().unwrap_or(0),
+    })
+    .into_response())
+}
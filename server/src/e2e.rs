@@ -0,0 +1,375 @@
+//! In-process stand-ins for the node and its DA, to run the server end to end in tests.
+//!
+//! The mock node accepts every transaction. The fake DA then sequences the transactions
+//! sent since its previous block and settles the ones it sequenced in that block, as a
+//! node would once their proofs landed.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use axum::Router;
+use blackjack::{BlackJack, BlackJackConfig, TableState};
+use dice::DiceConfig;
+use ezcasino_client::EzCasinoClient;
+use hyle_modules::{
+    bus::{metrics::BusMetrics, BusClientSender, SharedMessageBus},
+    module_bus_client,
+    modules::{
+        contract_state_indexer::{ContractStateIndexer, ContractStateIndexerCtx},
+        prover::AutoProverEvent,
+        BuildApiContextInner, ModulesHandler,
+    },
+    node_state::module::NodeStateEvent,
+};
+use k256::ecdsa::SigningKey;
+use sdk::{
+    api::APIRegisterContract, Blob, BlobTransaction, Block, BlockHeight, ConsensusProposalHash,
+    Contract, ContractName, DataProposalHash, Hashed, Identity, RegisterContractEffect,
+    Transaction, TransactionData, TxHash, TxId,
+};
+use server::{
+    conf::CorsConf,
+    init,
+    node_client::{NodeClient, NodeFuture},
+    wallet::session_key_blobs,
+};
+
+use crate::{
+    app::{AppModule, AppModuleCtx},
+    auth::MockWallet,
+};
+
+/// Interval between two blocks of the fake DA
+const BLOCK_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Default)]
+struct MockNodeState {
+    contracts: BTreeMap<ContractName, Contract>,
+    registrations: Vec<APIRegisterContract>,
+    sent_txs: Vec<BlobTransaction>,
+    /// Number of sent transactions already sequenced in a block
+    sequenced: usize,
+    /// Transactions sequenced in the latest block
+    unsettled: Vec<TxHash>,
+    block_height: u64,
+}
+
+#[derive(Default)]
+pub struct MockNode {
+    state: Mutex<MockNodeState>,
+}
+
+impl MockNode {
+    fn state(&self) -> std::sync::MutexGuard<'_, MockNodeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Transactions sent to the node so far, settled or not
+    pub fn sent_txs(&self) -> usize {
+        self.state().sent_txs.len()
+    }
+
+    /// Next block of the fake DA
+    fn next_block(&self) -> Block {
+        let mut state = self.state();
+        state.block_height += 1;
+        let block_height = BlockHeight(state.block_height);
+
+        let mut registered_contracts = BTreeMap::new();
+        for registration in std::mem::take(&mut state.registrations) {
+            let effect = RegisterContractEffect {
+                verifier: registration.verifier.clone(),
+                program_id: registration.program_id.clone(),
+                state_commitment: registration.state_commitment.clone(),
+                contract_name: registration.contract_name.clone(),
+                ..Default::default()
+            };
+            state.contracts.insert(
+                registration.contract_name.clone(),
+                Contract {
+                    name: registration.contract_name.clone(),
+                    program_id: registration.program_id,
+                    state: registration.state_commitment,
+                    verifier: registration.verifier,
+                    ..Default::default()
+                },
+            );
+            registered_contracts.insert(
+                registration.contract_name.clone(),
+                (
+                    TxHash::new(&format!("register-{}", registration.contract_name)),
+                    effect,
+                    registration.constructor_metadata,
+                ),
+            );
+        }
+
+        let txs: Vec<(TxId, Transaction)> = state.sent_txs[state.sequenced..]
+            .iter()
+            .map(|tx| {
+                (
+                    TxId(DataProposalHash::default(), tx.hashed()),
+                    Transaction {
+                        version: 1,
+                        transaction_data: TransactionData::Blob(tx.clone()),
+                    },
+                )
+            })
+            .collect();
+        state.sequenced = state.sent_txs.len();
+        let successful_txs = std::mem::replace(
+            &mut state.unsettled,
+            txs.iter().map(|(id, _)| id.1.clone()).collect(),
+        );
+
+        Block {
+            hash: ConsensusProposalHash(format!("block-{block_height}")),
+            block_height,
+            txs,
+            successful_txs,
+            registered_contracts,
+            ..Default::default()
+        }
+    }
+}
+
+impl NodeClient for MockNode {
+    fn send_tx_blob(&self, tx: BlobTransaction) -> NodeFuture<'_, TxHash> {
+        let tx_hash = tx.hashed();
+        self.state().sent_txs.push(tx);
+        Box::pin(async move { Ok(tx_hash) })
+    }
+
+    fn get_contract(&self, contract_name: ContractName) -> NodeFuture<'_, Contract> {
+        let contract = self.state().contracts.get(&contract_name).cloned();
+        Box::pin(
+            async move { contract.ok_or_else(|| anyhow!("Contract {contract_name} not found")) },
+        )
+    }
+
+    fn register_contract(&self, tx: APIRegisterContract) -> NodeFuture<'_, TxHash> {
+        let tx_hash = TxHash::new(&format!("register-{}", tx.contract_name));
+        self.state().registrations.push(tx);
+        Box::pin(async move { Ok(tx_hash) })
+    }
+
+    fn get_block_height(&self) -> NodeFuture<'_, BlockHeight> {
+        let block_height = BlockHeight(self.state().block_height);
+        Box::pin(async move { Ok(block_height) })
+    }
+}
+
+module_bus_client! {
+#[derive(Debug)]
+struct FakeDaBusClient {
+    sender(NodeStateEvent),
+}
+}
+
+/// Streams the blocks of the mock node on the bus, as the `DAListener` does
+async fn run_fake_da(node: Arc<MockNode>, mut bus: FakeDaBusClient) {
+    let mut interval = tokio::time::interval(BLOCK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let block = node.next_block();
+        if bus.send(NodeStateEvent::NewBlock(block.into())).is_err() {
+            return;
+        }
+    }
+}
+
+/// Server running on a local port against a mock node, with its data in a temporary
+/// directory
+pub struct Harness {
+    pub node: Arc<MockNode>,
+    pub wallet: Arc<MockWallet>,
+    pub client: EzCasinoClient,
+    data_directory: PathBuf,
+    _handler: ModulesHandler,
+}
+
+impl Harness {
+    pub async fn start() -> Result<Self> {
+        let contract_name: ContractName = "blackjack".into();
+        let data_directory =
+            std::env::temp_dir().join(format!("ezcasino-e2e-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&data_directory)?;
+
+        let node = Arc::new(MockNode::default());
+        let wallet = Arc::new(MockWallet::default());
+        let bus = SharedMessageBus::new(BusMetrics::global("ezcasino-e2e".to_string()));
+        let mut handler = ModulesHandler::new(&bus).await;
+        let api = Arc::new(BuildApiContextInner {
+            router: Mutex::new(Some(Router::new())),
+            openapi: Mutex::new(Default::default()),
+        });
+
+        handler
+            .build_module::<AppModule>(Arc::new(AppModuleCtx {
+                api: api.clone(),
+                node_client: node.clone(),
+                blackjack_cn: contract_name.clone(),
+                dice_cn: "dice".into(),
+                dice_config: DiceConfig::default(),
+                wallet_indexer: wallet.clone(),
+                operator: None,
+                clean_expire_after_blocks: None,
+                data_directory: data_directory.clone(),
+                session_encryption_key: None,
+                rate_limit_identity_per_minute: None,
+                rate_limit_ip_per_minute: None,
+                rate_limit_trusted_proxies: 0,
+                cors: CorsConf {
+                    allowed_origins: vec!["*".to_string()],
+                    allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+                    allowed_headers: vec!["*".to_string()],
+                },
+                admin_api_key: None,
+                admin_identities: vec![],
+            }))
+            .await?;
+        handler
+            .build_module::<ContractStateIndexer<BlackJack, AutoProverEvent<BlackJack>>>(
+                ContractStateIndexerCtx {
+                    contract_name: contract_name.clone(),
+                    data_directory: data_directory.clone(),
+                    api: api.clone(),
+                },
+            )
+            .await?;
+        let router = api
+            .router
+            .lock()
+            .map_err(|_| anyhow!("Router lock poisoned"))?
+            .take()
+            .context("Router should be available")?;
+        handler.start_modules().await?;
+
+        tokio::spawn(run_fake_da(
+            node.clone(),
+            FakeDaBusClient::new_from_bus(bus.new_handle()).await,
+        ));
+        init::init_contract(
+            node.as_ref(),
+            contract_name.clone(),
+            BlackJackConfig::default(),
+        )
+        .await?;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        Ok(Harness {
+            node,
+            wallet,
+            client: EzCasinoClient::new(url).with_contract_name(&contract_name.0),
+            data_directory,
+            _handler: handler,
+        })
+    }
+
+    /// Waits until the indexer settled the state matching the predicate
+    pub async fn settled_state(&self, predicate: impl Fn(&BlackJack) -> bool) -> Result<BlackJack> {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let Ok(state) = self.client.state().await {
+                    if predicate(&state) {
+                        return state;
+                    }
+                }
+                tokio::time::sleep(BLOCK_INTERVAL).await;
+            }
+        })
+        .await
+        .context("Timed out waiting for the settled state")
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.data_directory);
+    }
+}
+
+/// Wallet of a test player, signing its authorisation blobs with a session key
+struct TestWallet {
+    identity: Identity,
+    signing_key: SigningKey,
+    nonce: u64,
+}
+
+impl TestWallet {
+    /// Wallet signing with a new session key, registered for the identity
+    fn new(harness: &Harness, identity: &str) -> Self {
+        let identity: Identity = identity.into();
+        let signing_key =
+            SigningKey::from_slice(&rand::random::<[u8; 32]>()).expect("valid secret key");
+        harness.wallet.register(
+            &identity,
+            hex::encode(signing_key.verifying_key().to_sec1_bytes()),
+        );
+        TestWallet {
+            identity,
+            signing_key,
+            nonce: 0,
+        }
+    }
+
+    fn blobs(&mut self) -> [Blob; 2] {
+        self.nonce += 1;
+        session_key_blobs(&self.identity, &self.signing_key, self.nonce).expect("signed blobs")
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_play_full_hands_over_http() -> Result<()> {
+    let harness = Harness::start().await?;
+    let client = &harness.client;
+    let mut alice = TestWallet::new(&harness, "alice@wallet");
+
+    let resp = client.deposit(&alice.identity, alice.blobs(), 100).await?;
+    assert_eq!(resp.table.balance, 100);
+
+    let mut won = BTreeMap::new();
+    for _ in 0..3 {
+        let mut resp = client.init(&alice.identity, alice.blobs(), 10).await?;
+        let table_id = resp.table_id.context("new table")?;
+        while matches!(resp.table.state, TableState::Ongoing) {
+            let hint = client.hint(&alice.identity, table_id).await?;
+            assert_eq!(hint.table_id, table_id);
+            resp = client
+                .stand(&alice.identity, alice.blobs().into(), table_id)
+                .await?;
+        }
+        won.insert(table_id, matches!(resp.table.state, TableState::Won));
+    }
+
+    // Every hand settles on-chain, with the outcome the server answered
+    let state = harness
+        .settled_state(|state| {
+            state.tables.get(&alice.identity).is_some_and(|tables| {
+                tables.len() == won.len()
+                    && tables
+                        .values()
+                        .all(|table| !matches!(table.state, TableState::Ongoing))
+            })
+        })
+        .await?;
+    for (table_id, table) in &state.tables[&alice.identity] {
+        assert_eq!(matches!(table.state, TableState::Won), won[table_id]);
+    }
+    let balances = client.balances(&alice.identity).await?;
+    assert_eq!(
+        state.oranj_balances.get(&alice.identity),
+        Some(&balances.oranj)
+    );
+    // A deposit, then an init and at least one stand per hand
+    assert!(harness.node.sent_txs() >= 7);
+    Ok(())
+}
@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{header::CONTENT_TYPE, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+
+use crate::utils::AppError;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// How long a response is replayed for retries of the same request
+const IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 3600);
+/// A request handled for longer than this was dropped, e.g. because its client left
+const IN_FLIGHT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Largest request, and response, body kept for an idempotency key
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+/// Most idempotency keys remembered at once
+const MAX_ENTRIES: usize = 100_000;
+/// How often expired entries are dropped, besides when the cache is full
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+type Key = (String, String, String);
+
+struct Entry {
+    /// SHA-256 hash of the request body, which retries must repeat
+    request_hash: [u8; 32],
+    at: Instant,
+    /// Unset while the first request is still being handled
+    response: Option<CachedResponse>,
+}
+
+struct CachedResponse {
+    status: StatusCode,
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+}
+
+impl Entry {
+    fn is_expired(&self) -> bool {
+        match self.response {
+            Some(_) => self.at.elapsed() >= IDEMPOTENCY_TTL,
+            None => self.at.elapsed() >= IN_FLIGHT_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<Key, Entry>,
+    pruned_at: Option<Instant>,
+}
+
+impl Entries {
+    /// Drops the expired entries when the cache is full, or every `PRUNE_INTERVAL`
+    fn prune(&mut self) {
+        if self.map.len() < MAX_ENTRIES
+            && self
+                .pruned_at
+                .is_some_and(|at| at.elapsed() < PRUNE_INTERVAL)
+        {
+            return;
+        }
+        self.map.retain(|_, entry| !entry.is_expired());
+        self.pruned_at = Some(Instant::now());
+    }
+}
+
+/// Responses of the POST requests sent with an `Idempotency-Key` header, per identity,
+/// route and key
+#[derive(Default)]
+pub struct IdempotencyCache {
+    entries: Mutex<Entries>,
+}
+
+impl IdempotencyCache {
+    fn remove(&self, key: &Key) {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.map.remove(key);
+    }
+}
+
+/// Whether the response is the final outcome of the request. Requests rejected as
+/// unauthenticated, throttled or failed on the server can be retried with the same key.
+fn is_final(status: StatusCode) -> bool {
+    status.is_success()
+        || (status.is_client_error()
+            && status != StatusCode::UNAUTHORIZED
+            && status != StatusCode::TOO_MANY_REQUESTS)
+}
+
+pub async fn idempotent(
+    State(cache): State<Arc<IdempotencyCache>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::POST {
+        return next.run(request).await;
+    }
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let Some(idempotency_key) = header(IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    let key = (
+        header("x-identity").unwrap_or_default(),
+        request.uri().path().to_string(),
+        idempotency_key,
+    );
+
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(e) => return AppError(StatusCode::PAYLOAD_TOO_LARGE, e.into()).into_response(),
+    };
+    let request_hash: [u8; 32] = Sha256::digest(&body).into();
+    let request = Request::from_parts(parts, Body::from(body));
+
+    {
+        let mut entries = cache.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.prune();
+        match entries.map.get(&key).filter(|entry| !entry.is_expired()) {
+            Some(entry) if entry.request_hash != request_hash => {
+                return AppError(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    anyhow::anyhow!("This idempotency key was used for another request"),
+                )
+                .into_response();
+            }
+            Some(Entry { response: None, .. }) => {
+                return AppError(
+                    StatusCode::CONFLICT,
+                    anyhow::anyhow!("A request with this idempotency key is still in progress"),
+                )
+                .into_response();
+            }
+            Some(Entry {
+                response: Some(cached),
+                ..
+            }) => {
+                let mut response = (cached.status, cached.body.clone()).into_response();
+                if let Some(content_type) = &cached.content_type {
+                    response
+                        .headers_mut()
+                        .insert(CONTENT_TYPE, content_type.clone());
+                }
+                return response;
+            }
+            None if entries.map.len() >= MAX_ENTRIES => {
+                return AppError(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    anyhow::anyhow!("Too many idempotency keys in use, retry later"),
+                )
+                .into_response();
+            }
+            None => {
+                entries.map.insert(
+                    key.clone(),
+                    Entry {
+                        request_hash,
+                        at: Instant::now(),
+                        response: None,
+                    },
+                );
+            }
+        }
+    }
+
+    let response = next.run(request).await;
+    if !is_final(response.status()) {
+        cache.remove(&key);
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(e) => {
+            cache.remove(&key);
+            return AppError(StatusCode::INTERNAL_SERVER_ERROR, e.into()).into_response();
+        }
+    };
+
+    let mut entries = cache.entries.lock().unwrap_or_else(PoisonError::into_inner);
+    entries.map.insert(
+        key,
+        Entry {
+            request_hash,
+            at: Instant::now(),
+            response: Some(CachedResponse {
+                status: parts.status,
+                content_type: parts.headers.get(CONTENT_TYPE).cloned(),
+                body: body.clone(),
+            }),
+        },
+    );
+    drop(entries);
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[tokio::test]
+async fn test_retries_replay_the_response() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{middleware, routing::post, Router};
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let router = Router::new()
+        .route(
+            "/deposit",
+            post(move || async move {
+                let call = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                format!("call {call}")
+            }),
+        )
+        .layer(middleware::from_fn_with_state(
+            Arc::new(IdempotencyCache::default()),
+            idempotent,
+        ));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/deposit", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    let client = reqwest::Client::new();
+    let send = |identity: &'static str, key: &'static str| {
+        client
+            .post(&url)
+            .header("x-identity", identity)
+            .header(IDEMPOTENCY_KEY_HEADER, key)
+            .send()
+    };
+    let first = send("alice@wallet", "1")
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let retry = send("alice@wallet", "1")
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(first, "call 1");
+    assert_eq!(retry, first);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Another key, or the same key of another identity, is a new request
+    let other = send("alice@wallet", "2")
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(other, "call 2");
+    let other = send("bob@wallet", "1").await.unwrap().text().await.unwrap();
+    assert_eq!(other, "call 3");
+}
+
+#[tokio::test]
+async fn test_retries_must_repeat_the_request() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{middleware, routing::post, Router};
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let router = Router::new()
+        .route(
+            "/hit",
+            post(move || async move {
+                // Fails on the server the first time
+                match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 => (StatusCode::INTERNAL_SERVER_ERROR, "failed"),
+                    _ => (StatusCode::OK, "hit"),
+                }
+            }),
+        )
+        .layer(middleware::from_fn_with_state(
+            Arc::new(IdempotencyCache::default()),
+            idempotent,
+        ));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hit", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    let client = reqwest::Client::new();
+    let send = |body: &'static str| {
+        client
+            .post(&url)
+            .header("x-identity", "alice@wallet")
+            .header(IDEMPOTENCY_KEY_HEADER, "1")
+            .body(body)
+            .send()
+    };
+    // Server errors are not replayed
+    let status = send("table 0").await.unwrap().status();
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let status = send("table 0").await.unwrap().status();
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // The key can't be used for another request
+    let status = send("table 1").await.unwrap().status();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let status = send("table 0").await.unwrap().status();
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
@@ -1,45 +1,104 @@
 use std::{sync::Arc, time::Duration};
 
 use anyhow::{bail, Result};
-use blackjack::BlackJack;
-use client_sdk::rest_client::NodeApiClient;
-use client_sdk::rest_client::NodeApiHttpClient;
+use blackjack::{BlackJack, BlackJackConfig};
+use dice::{Dice, DiceConfig};
+use roulette::Roulette;
 use sdk::{api::APIRegisterContract, info, ContractName, ProgramId, ZkContract};
 use tokio::time::timeout;
 
+use crate::node_client::NodeClient;
+
 pub async fn init_node(
-    node: Arc<NodeApiHttpClient>,
+    node: Arc<dyn NodeClient>,
     contract_name: impl Into<ContractName>,
+    config: BlackJackConfig,
+    roulette_contract_name: impl Into<ContractName>,
+    dice_contract_name: impl Into<ContractName>,
+    dice_config: DiceConfig,
 ) -> Result<()> {
-    init_contract(&node, contract_name.into()).await?;
+    init_contract(node.as_ref(), contract_name.into(), config).await?;
+    init_roulette_contract(node.as_ref(), roulette_contract_name.into()).await?;
+    init_dice_contract(node.as_ref(), dice_contract_name.into(), dice_config).await?;
     Ok(())
 }
 
-async fn init_contract(node: &NodeApiHttpClient, contract_name: ContractName) -> Result<()> {
+pub async fn init_contract(
+    node: &dyn NodeClient,
+    contract_name: ContractName,
+    config: BlackJackConfig,
+) -> Result<()> {
+    let program_id = blackjack::client::metadata::PROGRAM_ID;
+    ensure_contract(
+        node,
+        APIRegisterContract {
+            verifier: "risc0-1".into(),
+            program_id: ProgramId(program_id.to_vec()),
+            constructor_metadata: Some(borsh::to_vec(&config)?),
+            state_commitment: BlackJack::new(config).commit(),
+            contract_name,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+pub async fn init_roulette_contract(
+    node: &dyn NodeClient,
+    contract_name: ContractName,
+) -> Result<()> {
+    let program_id = roulette::client::metadata::PROGRAM_ID;
+    ensure_contract(
+        node,
+        APIRegisterContract {
+            verifier: "risc0-1".into(),
+            program_id: ProgramId(program_id.to_vec()),
+            state_commitment: Roulette::default().commit(),
+            contract_name,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+pub async fn init_dice_contract(
+    node: &dyn NodeClient,
+    contract_name: ContractName,
+    config: DiceConfig,
+) -> Result<()> {
+    let program_id = dice::client::metadata::PROGRAM_ID;
+    ensure_contract(
+        node,
+        APIRegisterContract {
+            verifier: "risc0-1".into(),
+            program_id: ProgramId(program_id.to_vec()),
+            constructor_metadata: Some(borsh::to_vec(&config)?),
+            state_commitment: Dice::new(config).map_err(anyhow::Error::msg)?.commit(),
+            contract_name,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Registers the contract unless it is already on-chain, in which case its program must
+/// be the one this server proves
+async fn ensure_contract(node: &dyn NodeClient, registration: APIRegisterContract) -> Result<()> {
+    let contract_name = registration.contract_name.clone();
     match node.get_contract(contract_name.clone()).await {
         Ok(contract) => {
-            let image_id = blackjack::client::metadata::PROGRAM_ID;
-            let program_id = contract.program_id.0;
-            if program_id != image_id {
+            if contract.program_id != registration.program_id {
                 bail!(
-                    "Invalid contract image_id. On-chain version is {:?}, expected {:?}",
-                    hex::encode(program_id),
-                    hex::encode(image_id),
+                    "Invalid {contract_name} contract image_id. On-chain version is {:?}, expected {:?}",
+                    hex::encode(contract.program_id.0),
+                    hex::encode(registration.program_id.0),
                 );
             }
-            info!("✅ Blackjack contract is up to date");
+            info!("✅ {contract_name} contract is up to date");
         }
         Err(_) => {
-            info!("🚀 Registering Blackjack contract");
-            let image_id = hex::encode(blackjack::client::metadata::PROGRAM_ID);
-            node.register_contract(APIRegisterContract {
-                verifier: "risc0-1".into(),
-                program_id: ProgramId(hex::decode(image_id)?),
-                state_commitment: BlackJack::default().commit(),
-                contract_name: contract_name.clone(),
-                ..Default::default()
-            })
-            .await?;
+            info!("🚀 Registering {contract_name} contract");
+            node.register_contract(registration).await?;
             wait_contract_state(node, &contract_name).await?;
         }
     };
@@ -47,7 +106,7 @@ async fn init_contract(node: &NodeApiHttpClient, contract_name: ContractName) ->
 }
 
 pub async fn wait_contract_state(
-    node: &NodeApiHttpClient,
+    node: &dyn NodeClient,
     contract_name: &ContractName,
 ) -> anyhow::Result<()> {
     timeout(Duration::from_secs(30), async {
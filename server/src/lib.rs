@@ -0,0 +1,7 @@
+//! Parts of the server shared with the `ezcasino-cli` binary
+
+pub mod conf;
+pub mod init;
+pub mod node_client;
+pub mod replay;
+pub mod wallet;
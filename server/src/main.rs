@@ -4,7 +4,7 @@ use axum::Router;
 use blackjack::BlackJack;
 use clap::Parser;
 use client_sdk::{helpers::risc0::Risc0Prover, rest_client::NodeApiHttpClient};
-use conf::Conf;
+use dice::Dice;
 use hyle_modules::{
     bus::{metrics::BusMetrics, SharedMessageBus},
     modules::{
@@ -18,13 +18,20 @@ use hyle_modules::{
     utils::logger::setup_tracing,
 };
 use prometheus::Registry;
-use sdk::api::NodeInfo;
+use roulette::Roulette;
+use sdk::{api::NodeInfo, ContractName, Identity};
+use server::{conf::Conf, init, wallet::WalletIndexerHttpClient};
 use std::sync::Arc;
 use tracing::{error, info, warn};
 
 mod app;
-mod conf;
-mod init;
+mod auth;
+mod dice_api;
+#[cfg(test)]
+mod e2e;
+mod idempotency;
+mod rate_limit;
+mod session;
 mod utils;
 
 #[derive(Parser, Debug)]
@@ -33,9 +40,15 @@ pub struct Args {
     #[arg(long, default_value = "config.toml")]
     pub config_file: Vec<String>,
 
-    #[arg(long, default_value = "blackjack")]
+    #[arg(long, default_value = blackjack::CONTRACT_NAME)]
     pub contract_name: String,
 
+    #[arg(long, default_value = "roulette")]
+    pub roulette_contract_name: String,
+
+    #[arg(long, default_value = "dice")]
+    pub dice_contract_name: String,
+
     #[clap(long, action)]
     pub pg: bool,
 }
@@ -54,7 +67,19 @@ async fn main() -> Result<()> {
     let node_client =
         Arc::new(NodeApiHttpClient::new(config.node_url.clone()).context("build node client")?);
 
-    match init::init_node(node_client.clone(), args.contract_name.clone()).await {
+    let blackjack_config = config.blackjack_config();
+    let dice_config = config.dice_config();
+
+    match init::init_node(
+        node_client.clone(),
+        args.contract_name.clone(),
+        blackjack_config.clone(),
+        args.roulette_contract_name.clone(),
+        args.dice_contract_name.clone(),
+        dice_config.clone(),
+    )
+    .await
+    {
         Ok(_) => {}
         Err(e) => {
             error!("Error initializing node: {:?}", e);
@@ -88,8 +113,28 @@ async fn main() -> Result<()> {
 
     let app_ctx = Arc::new(AppModuleCtx {
         api: build_api_ctx.clone(),
-        node_client,
+        node_client: node_client.clone(),
         blackjack_cn: args.contract_name.into(),
+        dice_cn: args.dice_contract_name.into(),
+        dice_config: dice_config.clone(),
+        wallet_indexer: Arc::new(WalletIndexerHttpClient::new(
+            config.wallet_indexer_url.clone(),
+        )),
+        operator: blackjack_config.operator.clone(),
+        clean_expire_after_blocks: config.clean_expire_after_blocks,
+        data_directory: config.data_directory.clone(),
+        session_encryption_key: config.session_encryption_key.clone(),
+        rate_limit_identity_per_minute: config.rate_limit_identity_per_minute,
+        rate_limit_ip_per_minute: config.rate_limit_ip_per_minute,
+        rate_limit_trusted_proxies: config.rate_limit_trusted_proxies,
+        cors: config.cors.clone(),
+        admin_api_key: config.admin_api_key.clone(),
+        admin_identities: config
+            .admin_identities
+            .iter()
+            .cloned()
+            .map(Identity)
+            .collect(),
     });
 
     handler.build_module::<AppModule>(app_ctx.clone()).await?;
@@ -109,11 +154,62 @@ async fn main() -> Result<()> {
                 data_directory: config.data_directory.clone(),
                 prover: Arc::new(Risc0Prover::new(blackjack::client::metadata::BLACKJACK_ELF)),
                 contract_name: app_ctx.blackjack_cn.clone(),
-                node: app_ctx.node_client.clone(),
+                node: node_client.clone(),
+                buffer_blocks: config.buffer_blocks,
+                max_txs_per_proof: config.max_txs_per_proof,
+                tx_working_window_size: config.tx_working_window_size,
+                default_state: BlackJack::new(blackjack_config),
+                api: Some(build_api_ctx.clone()),
+            }
+            .into(),
+        )
+        .await?;
+
+    let roulette_cn: ContractName = args.roulette_contract_name.into();
+    handler
+        .build_module::<ContractStateIndexer<Roulette>>(ContractStateIndexerCtx {
+            contract_name: roulette_cn.clone(),
+            data_directory: config.data_directory.clone(),
+            api: build_api_ctx.clone(),
+        })
+        .await?;
+    handler
+        .build_module::<AutoProver<Roulette>>(
+            AutoProverCtx {
+                data_directory: config.data_directory.clone(),
+                prover: Arc::new(Risc0Prover::new(roulette::client::metadata::ROULETTE_ELF)),
+                contract_name: roulette_cn,
+                node: node_client.clone(),
+                buffer_blocks: config.buffer_blocks,
+                max_txs_per_proof: config.max_txs_per_proof,
+                tx_working_window_size: config.tx_working_window_size,
+                default_state: Roulette::default(),
+                api: Some(build_api_ctx.clone()),
+            }
+            .into(),
+        )
+        .await?;
+
+    handler
+        .build_module::<ContractStateIndexer<Dice, AutoProverEvent<Dice>>>(
+            ContractStateIndexerCtx {
+                contract_name: app_ctx.dice_cn.clone(),
+                data_directory: config.data_directory.clone(),
+                api: build_api_ctx.clone(),
+            },
+        )
+        .await?;
+    handler
+        .build_module::<AutoProver<Dice>>(
+            AutoProverCtx {
+                data_directory: config.data_directory.clone(),
+                prover: Arc::new(Risc0Prover::new(dice::client::metadata::DICE_ELF)),
+                contract_name: app_ctx.dice_cn.clone(),
+                node: node_client,
                 buffer_blocks: config.buffer_blocks,
                 max_txs_per_proof: config.max_txs_per_proof,
                 tx_working_window_size: config.tx_working_window_size,
-                default_state: BlackJack::default(),
+                default_state: Dice::new(dice_config).map_err(anyhow::Error::msg)?,
                 api: Some(build_api_ctx.clone()),
             }
             .into(),
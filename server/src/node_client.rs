@@ -0,0 +1,35 @@
+//! The node calls the server makes, behind a trait so that tests can run the server
+//! against an in-process node.
+
+use std::{future::Future, pin::Pin};
+
+use anyhow::Result;
+use client_sdk::rest_client::{NodeApiClient, NodeApiHttpClient};
+use sdk::{api::APIRegisterContract, BlobTransaction, BlockHeight, Contract, ContractName, TxHash};
+
+pub type NodeFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+pub trait NodeClient: Send + Sync {
+    fn send_tx_blob(&self, tx: BlobTransaction) -> NodeFuture<'_, TxHash>;
+    fn get_contract(&self, contract_name: ContractName) -> NodeFuture<'_, Contract>;
+    fn register_contract(&self, tx: APIRegisterContract) -> NodeFuture<'_, TxHash>;
+    fn get_block_height(&self) -> NodeFuture<'_, BlockHeight>;
+}
+
+impl NodeClient for NodeApiHttpClient {
+    fn send_tx_blob(&self, tx: BlobTransaction) -> NodeFuture<'_, TxHash> {
+        Box::pin(NodeApiClient::send_tx_blob(self, tx))
+    }
+
+    fn get_contract(&self, contract_name: ContractName) -> NodeFuture<'_, Contract> {
+        Box::pin(NodeApiClient::get_contract(self, contract_name))
+    }
+
+    fn register_contract(&self, tx: APIRegisterContract) -> NodeFuture<'_, TxHash> {
+        Box::pin(NodeApiClient::register_contract(self, tx))
+    }
+
+    fn get_block_height(&self) -> NodeFuture<'_, BlockHeight> {
+        Box::pin(NodeApiClient::get_block_height(self))
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::{bail, Result};
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sdk::Identity;
+
+use crate::utils::AppError;
+
+/// Above this many buckets, full ones are forgotten as they limit nothing
+const MAX_BUCKETS: usize = 10_000;
+
+/// Token bucket per identity and per IP address, refilled continuously.
+///
+/// The IP address is limited by the `rate_limit` middleware. The identity is limited once
+/// the request authenticated it, as anyone can send another player's identity header.
+pub struct RateLimiter {
+    identity_per_minute: Option<u32>,
+    ip_per_minute: Option<u32>,
+    /// Reverse proxies in front of the server, each appending the address it was reached
+    /// from to the `X-Forwarded-For` header
+    trusted_proxies: usize,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    capacity: f64,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.updated_at = now;
+    }
+}
+
+impl RateLimiter {
+    /// Fails when IP addresses are to be limited without a proxy reporting them, as the
+    /// REST server does not expose the address of the connection
+    pub fn new(
+        identity_per_minute: Option<u32>,
+        ip_per_minute: Option<u32>,
+        trusted_proxies: usize,
+    ) -> Result<Self> {
+        if ip_per_minute.is_some() && trusted_proxies == 0 {
+            bail!("rate_limit_ip_per_minute requires rate_limit_trusted_proxies");
+        }
+        Ok(RateLimiter {
+            identity_per_minute,
+            ip_per_minute,
+            trusted_proxies,
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Counts a request of an authenticated identity
+    pub fn acquire_identity(&self, identity: &Identity) -> Result<(), AppError> {
+        let Some(per_minute) = self.identity_per_minute else {
+            return Ok(());
+        };
+        if !self.try_acquire(format!("identity:{identity}"), per_minute, Instant::now()) {
+            return Err(too_many_requests());
+        }
+        Ok(())
+    }
+
+    fn try_acquire(&self, key: String, per_minute: u32, now: Instant) -> bool {
+        let Ok(mut buckets) = self.buckets.lock() else {
+            return true;
+        };
+        if buckets.len() >= MAX_BUCKETS {
+            buckets.retain(|_, bucket| {
+                bucket.refill(now);
+                bucket.tokens < bucket.capacity
+            });
+        }
+
+        let capacity = per_minute as f64;
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+            capacity,
+        });
+        bucket.refill(now);
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+fn too_many_requests() -> AppError {
+    AppError(
+        StatusCode::TOO_MANY_REQUESTS,
+        anyhow::anyhow!("Too many requests, slow down"),
+    )
+}
+
+/// Client address, as appended to `X-Forwarded-For` by the outermost trusted proxy. The
+/// entries left of it are set by the client, who can pick any.
+fn client_ip(forwarded_for: &str, trusted_proxies: usize) -> Option<String> {
+    forwarded_for
+        .rsplit(',')
+        .nth(trusted_proxies.checked_sub(1)?)
+        .map(|ip| ip.trim().to_string())
+        .filter(|ip| !ip.is_empty())
+}
+
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(per_minute) = limiter.ip_per_minute {
+        // Without the address, the request did not come through every proxy
+        let Some(ip) = request
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| client_ip(v, limiter.trusted_proxies))
+        else {
+            return AppError(
+                StatusCode::BAD_REQUEST,
+                anyhow::anyhow!("Missing client address"),
+            )
+            .into_response();
+        };
+        if !limiter.try_acquire(format!("ip:{ip}"), per_minute, Instant::now()) {
+            return too_many_requests().into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+#[test]
+fn test_bucket_refill() {
+    use std::time::Duration;
+
+    let now = Instant::now();
+    let mut bucket = Bucket {
+        tokens: 0.0,
+        updated_at: now,
+        capacity: 60.0,
+    };
+    // One token per second for 60 requests per minute
+    bucket.refill(now + Duration::from_secs(30));
+    assert_eq!(bucket.tokens, 30.0);
+    bucket.refill(now + Duration::from_secs(120));
+    assert_eq!(bucket.tokens, 60.0);
+
+    let limiter = RateLimiter::new(Some(2), None, 0).unwrap();
+    let alice: Identity = "alice@wallet".into();
+    assert!(limiter.acquire_identity(&alice).is_ok());
+    assert!(limiter.acquire_identity(&alice).is_ok());
+    assert!(limiter.acquire_identity(&alice).is_err());
+    assert!(limiter.acquire_identity(&"bob@wallet".into()).is_ok());
+    // Half a minute later, one more request is allowed
+    let later = Instant::now() + Duration::from_secs(30);
+    assert!(limiter.try_acquire(format!("identity:{alice}"), 2, later));
+    assert!(!limiter.try_acquire(format!("identity:{alice}"), 2, later));
+}
+
+#[test]
+fn test_client_ip() {
+    // Set by the client, then appended by each of two proxies
+    let forwarded_for = "6.6.6.6, 1.2.3.4, 10.0.0.1";
+    assert_eq!(client_ip(forwarded_for, 1).as_deref(), Some("10.0.0.1"));
+    assert_eq!(client_ip(forwarded_for, 2).as_deref(), Some("1.2.3.4"));
+    assert_eq!(client_ip("1.2.3.4", 2), None);
+    assert_eq!(client_ip(forwarded_for, 0), None);
+
+    assert!(RateLimiter::new(None, Some(60), 0).is_err());
+    assert!(RateLimiter::new(None, Some(60), 1).is_ok());
+}
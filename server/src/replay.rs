@@ -0,0 +1,291 @@
+//! Deterministic replay of the blackjack transactions settled on-chain.
+//!
+//! Blocks are replayed from genesis, as the state at any height depends on every earlier
+//! transaction. Each settled blackjack blob goes through `TxExecutorHandler::handle`, the
+//! same execution as the indexer's, and its commitments are checked against the ones
+//! proven on-chain when the block carries the proof.
+//!
+//! Dump files hold borsh-encoded blocks, one after the other.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use blackjack::{BlackJack, BlackJackConfig};
+use borsh::BorshDeserialize;
+use client_sdk::transaction_builder::TxExecutorHandler;
+use sdk::{
+    BlobIndex, BlobTransaction, Block, Calldata, ContractName, Hashed, StateCommitment,
+    TransactionData, TxContext, TxHash, HYLE_TESTNET_CHAIN_ID,
+};
+use serde::Serialize;
+
+/// Execution of one blackjack blob of a settled transaction
+#[derive(Serialize, Debug, Clone)]
+pub struct ReplayStep {
+    pub block_height: u64,
+    pub tx_hash: String,
+    pub blob_index: usize,
+    pub identity: String,
+    /// Hex-encoded state commitments around the execution
+    pub initial_state: String,
+    pub next_state: String,
+    pub success: bool,
+    pub output: String,
+    /// Whether the next state matches the one proven on-chain, when the proof was seen
+    pub matches_proof: Option<bool>,
+}
+
+pub struct Replayer {
+    contract_name: ContractName,
+    state: BlackJack,
+    /// Transactions sequenced but not settled yet
+    sequenced: HashMap<TxHash, (BlobTransaction, TxContext)>,
+    /// Next states proven on-chain, per blob, until their transaction settles
+    proven: HashMap<(TxHash, BlobIndex), StateCommitment>,
+}
+
+impl Replayer {
+    /// Replays the contract registered with the given settings
+    pub fn new(contract_name: ContractName, config: BlackJackConfig) -> Self {
+        Replayer {
+            contract_name,
+            state: BlackJack::new(config),
+            sequenced: HashMap::new(),
+            proven: HashMap::new(),
+        }
+    }
+
+    pub fn state(&self) -> &BlackJack {
+        &self.state
+    }
+
+    pub fn state_commitment(&self) -> StateCommitment {
+        self.state.get_state_commitment()
+    }
+
+    /// Replays the transactions the block settles, in order
+    pub fn replay_block(&mut self, block: &Block) -> Result<Vec<ReplayStep>> {
+        for proof in block.blob_proof_outputs.iter() {
+            if proof.contract_name == self.contract_name {
+                self.proven.insert(
+                    (proof.blob_tx_hash.clone(), proof.blob_index),
+                    proof.hyle_output.next_state.clone(),
+                );
+            }
+        }
+
+        for (_, tx) in block.txs.iter() {
+            let TransactionData::Blob(tx) = &tx.transaction_data else {
+                continue;
+            };
+            if !tx
+                .blobs
+                .iter()
+                .any(|blob| blob.contract_name == self.contract_name)
+            {
+                continue;
+            }
+            let tx_hash = tx.hashed();
+            let tx_ctx = TxContext {
+                lane_id: block.lane_ids.get(&tx_hash).cloned().unwrap_or_default(),
+                block_hash: block.hash.clone(),
+                block_height: block.block_height,
+                timestamp: block.block_timestamp.clone(),
+                chain_id: HYLE_TESTNET_CHAIN_ID,
+            };
+            self.sequenced.insert(tx_hash, (tx.clone(), tx_ctx));
+        }
+
+        let mut steps = vec![];
+        for tx_hash in block.successful_txs.iter() {
+            let Some((tx, tx_ctx)) = self.sequenced.remove(tx_hash) else {
+                continue;
+            };
+            for (index, blob) in tx.blobs.iter().enumerate() {
+                if blob.contract_name != self.contract_name {
+                    continue;
+                }
+                steps.push(self.execute(block.block_height.0, &tx, BlobIndex(index), &tx_ctx)?);
+            }
+        }
+        for tx_hash in block.failed_txs.iter().chain(block.timed_out_txs.iter()) {
+            self.sequenced.remove(tx_hash);
+            // Proofs of a transaction that will not settle are never checked
+            self.proven.retain(|(proven_tx, _), _| proven_tx != tx_hash);
+        }
+        Ok(steps)
+    }
+
+    fn execute(
+        &mut self,
+        block_height: u64,
+        tx: &BlobTransaction,
+        index: BlobIndex,
+        tx_ctx: &TxContext,
+    ) -> Result<ReplayStep> {
+        let tx_hash = tx.hashed();
+        let calldata = Calldata {
+            identity: tx.identity.clone(),
+            index,
+            blobs: tx.blobs.clone().into(),
+            tx_blob_count: tx.blobs.len(),
+            tx_hash: tx_hash.clone(),
+            tx_ctx: Some(tx_ctx.clone()),
+            private_input: vec![],
+        };
+        let output = self
+            .state
+            .handle(&calldata)
+            .with_context(|| format!("Executing blob {} of tx {tx_hash}", index.0))?;
+        let matches_proof = self
+            .proven
+            .remove(&(tx_hash.clone(), index))
+            .map(|proven| proven == output.next_state);
+
+        Ok(ReplayStep {
+            block_height,
+            tx_hash: tx_hash.to_string(),
+            blob_index: index.0,
+            identity: tx.identity.0.clone(),
+            initial_state: hex::encode(&output.initial_state.0),
+            next_state: hex::encode(&output.next_state.0),
+            success: output.success,
+            output: String::from_utf8_lossy(&output.program_outputs).into_owned(),
+            matches_proof,
+        })
+    }
+}
+
+/// Appends blocks to a dump file
+pub struct DumpWriter {
+    file: File,
+}
+
+impl DumpWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).context("Creating dump file")?;
+        Ok(DumpWriter { file })
+    }
+
+    pub fn write(&mut self, block: &Block) -> Result<()> {
+        let bytes = borsh::to_vec(block).context("Failed to encode block")?;
+        self.file.write_all(&bytes).context("Writing dump file")?;
+        self.file.flush().context("Writing dump file")
+    }
+}
+
+/// Reads every block of a dump file
+pub fn read_dump(path: &Path) -> Result<Vec<Block>> {
+    let mut reader = BufReader::new(File::open(path).context("Opening dump file")?);
+    let mut blocks = vec![];
+    while !reader.fill_buf()?.is_empty() {
+        blocks.push(Block::deserialize_reader(&mut reader).context("Failed to decode block")?);
+    }
+    Ok(blocks)
+}
+
+#[test]
+fn test_replay_dump() {
+    use sdk::{
+        BlockHeight, ConsensusProposalHash, ContractAction, DataProposalHash, Identity,
+        Transaction, TxId,
+    };
+
+    let contract_name: ContractName = "blackjack".into();
+    let config = BlackJackConfig {
+        operator: Some("operator@wallet".into()),
+        game_timeout_blocks: Some(10),
+    };
+    let open_table = |player: &str| {
+        BlobTransaction::new(
+            Identity(player.to_string()),
+            vec![blackjack::BlackJackAction::OpenSharedTable.as_blob(
+                contract_name.clone(),
+                None,
+                None,
+            )],
+        )
+    };
+    let settled = open_table("alice@wallet");
+    let failed = open_table("bob@wallet");
+    let sequenced = |tx: &BlobTransaction| {
+        (
+            TxId(DataProposalHash::default(), tx.hashed()),
+            Transaction {
+                version: 1,
+                transaction_data: TransactionData::Blob(tx.clone()),
+            },
+        )
+    };
+    let blocks = [
+        Block {
+            hash: ConsensusProposalHash("block-1".to_string()),
+            block_height: BlockHeight(1),
+            txs: vec![sequenced(&settled), sequenced(&failed)],
+            ..Default::default()
+        },
+        Block {
+            hash: ConsensusProposalHash("block-2".to_string()),
+            block_height: BlockHeight(2),
+            successful_txs: vec![settled.hashed()],
+            failed_txs: vec![failed.hashed()],
+            ..Default::default()
+        },
+    ];
+
+    let path = std::env::temp_dir().join(format!("ezcasino-dump-{}", rand::random::<u64>()));
+    let mut dump = DumpWriter::create(&path).unwrap();
+    for block in blocks.iter() {
+        dump.write(block).unwrap();
+    }
+    let blocks = read_dump(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(blocks.len(), 2);
+
+    let mut replayer = Replayer::new(contract_name.clone(), config.clone());
+    assert!(replayer.replay_block(&blocks[0]).unwrap().is_empty());
+    let steps = replayer.replay_block(&blocks[1]).unwrap();
+    assert_eq!(steps.len(), 1);
+    assert!(steps[0].success);
+    assert_eq!(steps[0].tx_hash, settled.hashed().to_string());
+    assert!(replayer.sequenced.is_empty());
+
+    // The settled transaction executed alone, in the block it was sequenced in
+    let mut expected = BlackJack::new(config);
+    let initial_state = expected.get_state_commitment();
+    expected
+        .handle(&Calldata {
+            identity: settled.identity.clone(),
+            index: BlobIndex(0),
+            blobs: settled.blobs.clone().into(),
+            tx_blob_count: 1,
+            tx_hash: settled.hashed(),
+            tx_ctx: Some(TxContext {
+                lane_id: Default::default(),
+                block_hash: blocks[0].hash.clone(),
+                block_height: blocks[0].block_height,
+                timestamp: blocks[0].block_timestamp.clone(),
+                chain_id: HYLE_TESTNET_CHAIN_ID,
+            }),
+            private_input: vec![],
+        })
+        .unwrap();
+    assert_eq!(steps[0].initial_state, hex::encode(&initial_state.0));
+    assert_eq!(
+        steps[0].next_state,
+        hex::encode(&expected.get_state_commitment().0)
+    );
+    assert_eq!(replayer.state_commitment(), expected.get_state_commitment());
+
+    // The state registered with other settings commits to another state
+    let mut replayer = Replayer::new(contract_name, BlackJackConfig::default());
+    for block in blocks.iter() {
+        replayer.replay_block(block).unwrap();
+    }
+    assert_ne!(replayer.state_commitment(), expected.get_state_commitment());
+}
@@ -0,0 +1,404 @@
+//! Session keys the server signs with on behalf of players, so that playing a hand does not
+//! need a wallet round-trip per action.
+//!
+//! A player opens a session with their wallet, then registers the returned public key as a
+//! session key of their wallet account. The server then builds the authorisation blobs of
+//! the player's actions itself, within the limits of the session, for requests bearing the
+//! token returned when the session was opened.
+//!
+//! The store keeps a hash of each token, and seals the secret keys with its encryption key.
+//! Without a configured encryption key, sessions are only kept in memory.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use k256::ecdsa::SigningKey;
+use sdk::{Blob, Identity};
+use serde::{Deserialize, Serialize};
+use server::wallet::session_key_blobs;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+pub const SESSIONS_FILE: &str = "session_keys.json";
+/// Longest session a player can open
+pub const MAX_SESSION_DURATION_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SessionKey {
+    identity: Identity,
+    /// Hex-encoded nonce and secp256k1 secret key, sealed with the store's encryption key
+    sealed_secret_key: String,
+    /// Hex-encoded SHA-256 hash of the token authorising requests
+    token_hash: String,
+    /// Hex-encoded compressed public key, also the id of the session
+    public_key: String,
+    created_at_ms: u64,
+    expires_at_ms: u64,
+    max_actions: u32,
+    actions_used: u32,
+    /// Oranj tokens the session can commit to the tables, e.g. by doubling down
+    max_spend: u32,
+    spent: u32,
+    revoked: bool,
+    /// Last nonce signed with the key
+    #[serde(default)]
+    last_nonce: u64,
+}
+
+impl SessionKey {
+    fn is_active(&self, now_ms: u64) -> bool {
+        !self.revoked && now_ms < self.expires_at_ms && self.actions_used < self.max_actions
+    }
+}
+
+/// Session as reported to the player, without its secret
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct SessionView {
+    pub public_key: String,
+    pub created_at_ms: u64,
+    pub expires_at_ms: u64,
+    pub max_actions: u32,
+    pub actions_used: u32,
+    pub max_spend: u32,
+    pub spent: u32,
+}
+
+/// Session just opened, with the token to send in the `x-session-key` header. The token is
+/// not stored and cannot be retrieved later.
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct OpenedSession {
+    #[serde(flatten)]
+    pub session: SessionView,
+    pub token: String,
+}
+
+impl From<&SessionKey> for SessionView {
+    fn from(session: &SessionKey) -> Self {
+        SessionView {
+            public_key: session.public_key.clone(),
+            created_at_ms: session.created_at_ms,
+            expires_at_ms: session.expires_at_ms,
+            max_actions: session.max_actions,
+            actions_used: session.actions_used,
+            max_spend: session.max_spend,
+            spent: session.spent,
+        }
+    }
+}
+
+/// Action authorised by a session, charged to it once the action is accepted
+#[derive(Debug, Clone)]
+pub struct SessionCharge {
+    public_key: String,
+    spend: u32,
+}
+
+/// Session keys of every player, persisted as JSON under the data directory
+pub struct SessionStore {
+    /// Unset when sessions are only kept in memory
+    path: Option<PathBuf>,
+    cipher: ChaCha20Poly1305,
+    sessions: BTreeMap<String, SessionKey>,
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl SessionStore {
+    /// Opens the sessions persisted in `data_directory`, sealed with the hex-encoded 32 bytes
+    /// `encryption_key`. Without a key, the store starts empty and is not persisted.
+    pub fn open(data_directory: &Path, encryption_key: Option<&str>) -> Result<Self> {
+        let Some(encryption_key) = encryption_key else {
+            return Ok(SessionStore {
+                path: None,
+                cipher: ChaCha20Poly1305::new(Key::from_slice(&rand::random::<[u8; 32]>())),
+                sessions: BTreeMap::new(),
+            });
+        };
+        let encryption_key: [u8; 32] = hex::decode(encryption_key)
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| anyhow!("Session encryption key must be 32 hex-encoded bytes"))?;
+
+        let path = data_directory.join(SESSIONS_FILE);
+        let sessions = if path.exists() {
+            let file = std::fs::read(&path).context("Reading session keys")?;
+            serde_json::from_slice(&file).context("Failed to decode session keys")?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(SessionStore {
+            path: Some(path),
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&encryption_key)),
+            sessions,
+        })
+    }
+
+    /// Seals the secret key, bound to the public key it belongs to
+    fn seal(&self, signing_key: &SigningKey, public_key: &str) -> Result<String> {
+        let nonce = rand::random::<[u8; 12]>();
+        let sealed = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &signing_key.to_bytes(),
+                    aad: public_key.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow!("Failed to seal session key"))?;
+        Ok(hex::encode([nonce.as_slice(), &sealed].concat()))
+    }
+
+    fn unseal(&self, session: &SessionKey) -> Result<SigningKey> {
+        let sealed = hex::decode(&session.sealed_secret_key).context("Invalid session key")?;
+        if sealed.len() < 12 {
+            bail!("Invalid session key");
+        }
+        let (nonce, sealed) = sealed.split_at(12);
+        let secret_key = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: session.public_key.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow!("Session key sealed with another encryption key"))?;
+        SigningKey::from_slice(&secret_key).context("Invalid session key")
+    }
+
+    /// Writes the sessions on a blocking thread. The store is held meanwhile, so that the
+    /// writes land in order.
+    async fn save(&self) -> Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let bytes = serde_json::to_vec(&self.sessions)?;
+        tokio::task::spawn_blocking(move || write(&path, &bytes))
+            .await
+            .context("Writing session keys")?
+    }
+
+    /// Generates a new session key for the player, valid for `duration_ms`
+    pub async fn create(
+        &mut self,
+        identity: Identity,
+        duration_ms: u64,
+        max_actions: u32,
+        max_spend: u32,
+    ) -> Result<OpenedSession> {
+        if duration_ms == 0 || duration_ms > MAX_SESSION_DURATION_MS {
+            bail!("Session duration must be between 1ms and {MAX_SESSION_DURATION_MS}ms");
+        }
+        let signing_key = loop {
+            // Almost every 32 bytes string is a valid secret key
+            if let Ok(key) = SigningKey::from_slice(&rand::random::<[u8; 32]>()) {
+                break key;
+            }
+        };
+        let public_key = hex::encode(signing_key.verifying_key().to_sec1_bytes());
+        let token = hex::encode(rand::random::<[u8; 32]>());
+
+        let now = now_ms();
+        let session = SessionKey {
+            identity,
+            sealed_secret_key: self.seal(&signing_key, &public_key)?,
+            token_hash: token_hash(&token),
+            public_key: public_key.clone(),
+            created_at_ms: now,
+            expires_at_ms: now.saturating_add(duration_ms),
+            max_actions,
+            actions_used: 0,
+            max_spend,
+            spent: 0,
+            revoked: false,
+            last_nonce: 0,
+        };
+        let opened = OpenedSession {
+            session: SessionView::from(&session),
+            token,
+        };
+        self.sessions.insert(public_key, session);
+        self.prune(now);
+        self.save().await?;
+        Ok(opened)
+    }
+
+    pub async fn revoke(&mut self, identity: &Identity, public_key: &str) -> Result<()> {
+        match self.sessions.get_mut(public_key) {
+            Some(session) if &session.identity == identity => session.revoked = true,
+            _ => bail!("No session {public_key} for {identity}"),
+        }
+        self.save().await
+    }
+
+    /// Whether the token is the one of an active session of the player
+    pub fn is_holder(&self, identity: &Identity, token: &str) -> bool {
+        let now = now_ms();
+        let token_hash = token_hash(token);
+        self.sessions.values().any(|session| {
+            &session.identity == identity
+                && session.token_hash == token_hash
+                && session.is_active(now)
+        })
+    }
+
+    pub fn active(&self, identity: &Identity) -> Vec<SessionView> {
+        let now = now_ms();
+        self.sessions
+            .values()
+            .filter(|session| &session.identity == identity && session.is_active(now))
+            .map(SessionView::from)
+            .collect()
+    }
+
+    /// Signs the wallet blobs authorising one action committing `spend` tokens with the
+    /// session of the token, if its limits allow it. The action is charged with `charge`.
+    pub fn authorise(
+        &mut self,
+        identity: &Identity,
+        token: &str,
+        spend: u32,
+    ) -> Result<([Blob; 2], SessionCharge)> {
+        let now = now_ms();
+        let token_hash = token_hash(token);
+        let public_key = self
+            .sessions
+            .values()
+            .find(|session| &session.identity == identity && session.token_hash == token_hash)
+            .map(|session| session.public_key.clone())
+            .ok_or_else(|| anyhow!("No session of {identity} for this token"))?;
+        let signing_key = self.unseal(&self.sessions[&public_key])?;
+        let Some(session) = self.sessions.get_mut(&public_key) else {
+            bail!("No session of {identity} for this token");
+        };
+        if !session.is_active(now) {
+            bail!("Session {public_key} is expired, revoked or used up");
+        }
+        session
+            .spent
+            .checked_add(spend)
+            .filter(|spent| *spent <= session.max_spend)
+            .ok_or_else(|| anyhow!("Session {public_key} spending limit reached"))?;
+
+        // Increasing even when requests are signed within the same millisecond; the wallet
+        // rejects a nonce used twice
+        let nonce = (session.last_nonce + 1).max(now);
+        let blobs = session_key_blobs(identity, &signing_key, nonce)?;
+        session.last_nonce = nonce;
+        Ok((blobs, SessionCharge { public_key, spend }))
+    }
+
+    /// Counts an action authorised by `authorise` against its session, once it is accepted
+    pub async fn charge(&mut self, charge: SessionCharge) -> Result<()> {
+        let Some(session) = self.sessions.get_mut(&charge.public_key) else {
+            bail!("No session {}", charge.public_key);
+        };
+        session.actions_used = session.actions_used.saturating_add(1);
+        session.spent = session.spent.saturating_add(charge.spend);
+        self.save().await
+    }
+
+    /// Forgets the sessions that can no longer be used
+    fn prune(&mut self, now_ms: u64) {
+        self.sessions.retain(|_, session| session.is_active(now_ms));
+    }
+}
+
+fn write(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, bytes).context("Writing session keys")?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+    }
+    std::fs::rename(&tmp, path).context("Writing session keys")
+}
+
+#[tokio::test]
+async fn test_session_limits() {
+    let alice: Identity = "alice@wallet".into();
+    let data_directory = std::env::temp_dir().join(format!("sessions-{}", rand::random::<u64>()));
+    std::fs::create_dir_all(&data_directory).unwrap();
+    let encryption_key = hex::encode([3; 32]);
+
+    let mut store = SessionStore::open(&data_directory, Some(&encryption_key)).unwrap();
+    let opened = store.create(alice.clone(), 60_000, 2, 10).await.unwrap();
+    let token = &opened.token;
+
+    // Once registered in the wallet, the blobs signed by the server pass the same checks as
+    // the wallet's, however fast they are signed
+    let wallet = std::sync::Arc::new(crate::auth::MockWallet::default());
+    wallet.register(&alice, opened.session.public_key.clone());
+    let wallet_auth = crate::auth::WalletAuth::open(wallet, &data_directory).unwrap();
+    for _ in 0..3 {
+        let (blobs, _) = store.authorise(&alice, token, 0).unwrap();
+        wallet_auth.verify(&alice, &blobs).await.unwrap();
+    }
+
+    // The public key is not a credential
+    assert!(store
+        .authorise(&alice, &opened.session.public_key, 0)
+        .is_err());
+    assert!(store.authorise(&"bob@wallet".into(), token, 0).is_err());
+    assert!(store.authorise(&alice, token, 20).is_err());
+    // Only accepted actions are charged
+    let (_, charge) = store.authorise(&alice, token, 10).unwrap();
+    store.charge(charge).await.unwrap();
+    let (_, charge) = store.authorise(&alice, token, 0).unwrap();
+    assert!(store.authorise(&alice, token, 1).is_err());
+    store.charge(charge).await.unwrap();
+    // Both actions are used
+    assert!(store.authorise(&alice, token, 0).is_err());
+    assert!(!store.is_holder(&alice, token));
+    assert!(store.active(&alice).is_empty());
+
+    // Persisted across restarts without the token or the secret key, and revocable
+    let opened = store.create(alice.clone(), 60_000, 5, 0).await.unwrap();
+    let file = std::fs::read_to_string(data_directory.join(SESSIONS_FILE)).unwrap();
+    assert!(!file.contains(&opened.token));
+    let mut store = SessionStore::open(&data_directory, Some(&encryption_key)).unwrap();
+    assert_eq!(store.active(&alice).len(), 1);
+    assert!(store.is_holder(&alice, &opened.token));
+    store.authorise(&alice, &opened.token, 0).unwrap();
+
+    // Sealed keys are useless with another encryption key
+    let mut other = SessionStore::open(&data_directory, Some(&hex::encode([4; 32]))).unwrap();
+    assert!(other.authorise(&alice, &opened.token, 0).is_err());
+
+    store
+        .revoke(&alice, &opened.session.public_key)
+        .await
+        .unwrap();
+    assert!(store.authorise(&alice, &opened.token, 0).is_err());
+
+    // Without an encryption key, nothing is persisted
+    let mut store = SessionStore::open(&data_directory, None).unwrap();
+    assert!(store.active(&alice).is_empty());
+    store.create(alice.clone(), 60_000, 5, 0).await.unwrap();
+    assert!(SessionStore::open(&data_directory, Some(&encryption_key))
+        .unwrap()
+        .active(&alice)
+        .is_empty());
+
+    std::fs::remove_dir_all(&data_directory).unwrap();
+}
@@ -0,0 +1,131 @@
+//! Identity blobs of the wallet contract, as a wallet frontend builds them for a session key,
+//! and the session keys registered in the contract, as its indexer serves them.
+
+use anyhow::{bail, Context, Result};
+use hyle_wallet::WalletAction;
+use k256::ecdsa::{signature::hazmat::PrehashSigner, Signature, SigningKey};
+use sdk::{verifiers::Secp256k1Blob, Blob, BlobData, ContractName, Identity};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::node_client::NodeFuture;
+
+/// Contract name of the native secp256k1 verifier
+pub const SECP256K1_CONTRACT: &str = "secp256k1";
+
+/// Builds the secp256k1 and wallet blobs authorising `identity` with one of its session
+/// keys. `nonce` must increase from one transaction to the next, e.g. a timestamp.
+pub fn session_key_blobs(
+    identity: &Identity,
+    signing_key: &SigningKey,
+    nonce: u64,
+) -> Result<[Blob; 2]> {
+    let Some((account, wallet_contract)) = identity.0.rsplit_once('@') else {
+        bail!("Identity {identity} is not a wallet identity");
+    };
+
+    let data = session_key_message(nonce as u128);
+    let signature: Signature = signing_key.sign_prehash(&data)?;
+    let secp256k1 = Secp256k1Blob {
+        identity: identity.clone(),
+        data,
+        public_key: signing_key
+            .verifying_key()
+            .to_sec1_bytes()
+            .as_ref()
+            .try_into()?,
+        signature: signature.to_bytes().as_slice().try_into()?,
+    };
+
+    let use_session_key = WalletAction::UseSessionKey {
+        account: account.to_string(),
+        nonce: nonce as u128,
+    };
+
+    Ok([
+        Blob {
+            contract_name: ContractName(SECP256K1_CONTRACT.to_string()),
+            data: BlobData(borsh::to_vec(&secp256k1)?),
+        },
+        Blob {
+            contract_name: ContractName(wallet_contract.to_string()),
+            data: BlobData(borsh::to_vec(&use_session_key)?),
+        },
+    ])
+}
+
+/// Message a session key signs to use the nonce in a transaction
+pub fn session_key_message(nonce: u128) -> [u8; 32] {
+    Sha256::digest(nonce.to_string().as_bytes()).into()
+}
+
+/// Session key registered for an account of the wallet contract
+#[derive(Deserialize, Debug, Clone)]
+pub struct RegisteredSessionKey {
+    /// Hex-encoded compressed public key
+    pub key: String,
+    /// Timestamp after which the key is rejected, in milliseconds
+    pub expiration_date: u128,
+}
+
+#[derive(Deserialize)]
+struct AccountInfo {
+    session_keys: Vec<RegisteredSessionKey>,
+}
+
+/// Accounts of the wallet contract, behind a trait so that tests can run the server
+/// without a wallet indexer
+pub trait WalletIndexer: Send + Sync {
+    /// Session keys registered for `account`, empty for an unknown account
+    fn session_keys(
+        &self,
+        wallet_contract: &str,
+        account: &str,
+    ) -> NodeFuture<'_, Vec<RegisteredSessionKey>>;
+}
+
+/// Client of the indexer of the wallet contract
+pub struct WalletIndexerHttpClient {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl WalletIndexerHttpClient {
+    pub fn new(url: String) -> Self {
+        WalletIndexerHttpClient {
+            url,
+            http: reqwest::Client::new(),
+        }
+    }
+}
+
+impl WalletIndexer for WalletIndexerHttpClient {
+    fn session_keys(
+        &self,
+        wallet_contract: &str,
+        account: &str,
+    ) -> NodeFuture<'_, Vec<RegisteredSessionKey>> {
+        let url = format!(
+            "{}/v1/indexer/contract/{wallet_contract}/account/{account}",
+            self.url
+        );
+        Box::pin(async move {
+            let resp = self
+                .http
+                .get(&url)
+                .send()
+                .await
+                .context("Fetching wallet account")?;
+            if resp.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(vec![]);
+            }
+            let account: AccountInfo = resp
+                .error_for_status()
+                .context("Fetching wallet account")?
+                .json()
+                .await
+                .context("Decoding wallet account")?;
+            Ok(account.session_keys)
+        })
+    }
+}